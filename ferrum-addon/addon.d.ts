@@ -1,11 +1,182 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
+/**
+ * Finds tracks that are the same recording, by comparing fingerprints of
+ * their audio. Fingerprints are cached in Cache.redb.
+ */
+export declare class AcousticDuplicateSearch {
+  /** `min_similarity` is between 0 and 1, and defaults to 0.75 */
+  static new(itemIds: Array<ItemId>, minSimilarity?: number | undefined | null): AcousticDuplicateSearch
+  start(): Promise<AcousticDuplicatesStatus>
+  /** Tracks that were fingerprinted or failed */
+  progress(): AnalysisProgress
+  /**
+   * Pairs of tracks that were compared, out of the pairs that share parts
+   * of their fingerprints. The total is 0 until fingerprinting is done.
+   */
+  comparisonProgress(): AnalysisProgress
+  /**
+   * Stops fingerprinting and comparing. Duplicates among the pairs that
+   * were already compared are still returned.
+   */
+  cancel(): void
+}
+
+/**
+ * Detects the tempo and musical key of tracks in the background. Values that
+ * can't be detected are left unchanged.
+ */
+export declare class BpmKeyAnalysis {
+  static new(itemIds: Array<ItemId>, writeTags?: boolean | undefined | null): BpmKeyAnalysis
+  start(): Promise<AnalysisStatus>
+  progress(): AnalysisProgress
+  /**
+   * Stops after the tracks that are being analyzed. Tracks that were
+   * already analyzed are kept.
+   */
+  cancel(): void
+  /** Saves the results to the tracks */
+  finish(): void
+}
+
+/**
+ * Imports a folder recursively in the background. Files are only read while
+ * importing, and are copied and added to the library in `finish`.
+ */
+export declare class FolderImport {
+  static new(path: string, now: MsSinceUnixEpoch, options?: ImportOptions | undefined | null): FolderImport
+  start(): Promise<FolderImportStatus>
+  /**
+   * Files that were imported or failed. The total is 0 until the folder
+   * has been scanned.
+   */
+  progress(): AnalysisProgress
+  /**
+   * Stops after the files that are being parsed. Files that were already
+   * read can still be added with `finish`.
+   */
+  cancel(): void
+  /**
+   * Copies the files and adds the tracks to the library. Returns errors for
+   * files that could not be copied, and for replaced files that could not
+   * be moved to the trash.
+   */
+  finish(): Array<string>
+}
+
 export declare class ItunesImport {
-  static new(): ItunesImport
+  static new(mode?: string | undefined | null, resync?: boolean | undefined | null): ItunesImport
+  start(path: string): Promise<ImportStatus>
+  /** Details of what will be imported. Available after `start` */
+  preview(): ItunesImportPreview
+  finish(): void
+}
+
+/**
+ * Measures loudness and true peak of tracks in the background. Album loudness
+ * is measured over all tracks of the album in the library, so the other
+ * tracks of the selected albums are analyzed too. If any track of an album
+ * fails, the album values are left unchanged.
+ */
+export declare class LoudnessAnalysis {
+  static new(itemIds: Array<ItemId>, writeTags?: boolean | undefined | null): LoudnessAnalysis
+  start(): Promise<AnalysisStatus>
+  progress(): AnalysisProgress
+  /**
+   * Stops after the tracks that are being analyzed. Tracks that were
+   * already analyzed are kept.
+   */
+  cancel(): void
+  /** Saves the results to the tracks */
+  finish(): void
+}
+
+export declare class RhythmboxImport {
+  static new(mode?: string | undefined | null): RhythmboxImport
+  /** Imports `rhythmdb.xml`, and `playlists.xml` if it's in the same folder */
   start(path: string): Promise<ImportStatus>
   finish(): void
 }
 
+export declare class Scrobbler {
+  /** Loads the queue from `Paths.scrobble_queue_file` */
+  static load(): Scrobbler
+  /**
+   * Why the queue file could not be loaded, in which case the queue
+   * started empty
+   */
+  loadError(): string | null
+  /**
+   * Submitting is disabled without a token. The base URL defaults to
+   * ListenBrainz.
+   */
+  configure(baseUrl?: string | undefined | null, token?: string | undefined | null): void
+  config(): ScrobblerConfig
+  /**
+   * Queues a play of the track that just finished. Does nothing if
+   * submitting is disabled.
+   */
+  add(trackId: string): void
+  pendingCount(): number
+  /**
+   * Submits queued listens, unless a failed submission is waiting to be
+   * retried
+   */
+  flush(): Promise<ScrobbleFlushStatus>
+}
+
+/**
+ * Merges duplicate tracks into one. Plays, skips and playlist entries of the
+ * other tracks are moved to the surviving track. Nothing is changed until
+ * `finish` is called, and the files of the other tracks are only moved to
+ * the trash by `trash_files`.
+ */
+/**
+ * Imports files one by one, checking each one for duplicates against the
+ * library and the files imported before it
+ */
+export declare class TrackImport {
+  static new(now: MsSinceUnixEpoch, options?: ImportOptions | undefined | null): TrackImport
+  importFile(path: string): ImportedTrack
+}
+
+export declare class TrackMerge {
+  /**
+   * `item_ids` are the tracks to merge into `survivor`, which may include
+   * `survivor` itself
+   */
+  static new(survivor: ItemId, itemIds: Array<ItemId>): TrackMerge
+  preview(): TrackMergePreview
+  /** Merges the tracks in the library, without saving it */
+  finish(): void
+  /**
+   * Restores the tracks as they were before `finish`, for example if the
+   * library could not be saved
+   */
+  revert(): void
+  /**
+   * Moves the files of the merged tracks to the trash. Call this once the
+   * library is saved. Returns errors for files that could not be moved to
+   * the trash.
+   */
+  trashFiles(): Array<string>
+}
+
+export interface AcousticDuplicateCluster {
+  itemIds: Array<ItemId>
+  /**
+   * Lowest similarity between 0 and 1 of the matches that formed the
+   * cluster
+   */
+  similarity: number
+}
+
+export interface AcousticDuplicatesStatus {
+  clusters: Array<AcousticDuplicateCluster>
+  errors: Array<string>
+  cancelled: boolean
+}
+
 export declare function add_play(trackId: string): void
 
 export declare function add_play_time(id: TrackID, start: MsSinceUnixEpoch, durMs: number): void
@@ -14,12 +185,32 @@ export declare function add_skip(trackId: string): void
 
 export declare function add_tracks_to_playlist(playlistId: string, trackIds: Array<string>): void
 
+export interface AnalysisProgress {
+  /** Tracks that were analyzed or failed */
+  doneCount: number
+  totalCount: number
+}
+
+/** Result of a background analysis of tracks, like `LoudnessAnalysis` */
+export interface AnalysisStatus {
+  analyzedCount: number
+  errors: Array<string>
+  /**
+   * Files that tags were not written to, because they are referenced or
+   * hardlinked
+   */
+  untaggedFiles: Array<string>
+  cancelled: boolean
+}
+
 export declare function close_cache_db(): Promise<void>
 
 export interface CountObject {
   count: number
   fromDate: MsSinceUnixEpoch
   toDate: MsSinceUnixEpoch
+  /** For example "rhythmbox". Counts without a source are from iTunes. */
+  source?: string
 }
 
 /** Returns the deleted track lists, including folder children */
@@ -27,6 +218,14 @@ export declare function delete_track_list(id: string): void
 
 export declare function delete_tracks_with_item_ids(itemIds: Array<ItemId>): void
 
+/** Returns errors for tracks that were skipped */
+export declare function export_itunes_library(path: string): Array<string>
+
+export declare function export_playlist(id: string, dir: string, format: string, relativePaths: boolean): number
+
+/** `format` is "listenbrainz" or "scrobbler_log" */
+export declare function export_plays(path: string, format: string, fromDate?: MsSinceUnixEpoch | undefined | null, toDate?: MsSinceUnixEpoch | undefined | null): PlaysExportStatus
+
 export interface Folder {
   id: TrackListID
   name: string
@@ -42,6 +241,17 @@ export interface Folder {
   children: Array<TrackListID>
 }
 
+export interface FolderImportStatus {
+  errors: Array<string>
+  /** Problems with imported files that were worked around */
+  warnings: Array<string>
+  tracksCount: number
+  /** Files that were ignored because they aren't a supported file type */
+  skippedCount: number
+  /** Likely duplicates that were skipped or replaced */
+  duplicatesCount: number
+}
+
 export declare function get_artists(): Array<string>
 
 export declare function get_default_sort_desc(field: string): boolean
@@ -68,12 +278,58 @@ export declare function get_track_playlist_ids(trackId: TrackID): Array<TrackID>
 
 export declare function get_tracks_page(options: TracksPageOptions): TracksPage
 
-export declare function import_file(path: string, now: MsSinceUnixEpoch): void
+export declare function import_file(path: string, now: MsSinceUnixEpoch, options?: ImportOptions | undefined | null): ImportedTrack
+
+export declare function import_m3u(path: string, parentId: string, now: MsSinceUnixEpoch, importOptions?: ImportOptions | undefined | null): PlaylistImportStatus
+
+export declare function import_plays(path: string): PlaysImportStatus
+
+export declare function import_spotify_history(paths: Array<string>, minMsPlayed?: number | undefined | null): SpotifyImportStatus
+
+export interface ImportedTrack {
+  /** The new track, or the existing track if the file was skipped or replaced */
+  id: TrackID
+  track: Track
+  /** Problems with the file that were worked around */
+  warnings: Array<string>
+  /** The existing track that the file is a likely duplicate of */
+  duplicateOf?: TrackID
+}
+
+export interface ImportOptions {
+  /** "copy" (default), "move", "hardlink" or "reference" */
+  mode?: string
+  /** What to do with likely duplicates: "import" (default), "skip" or "replace" */
+  duplicates?: string
+  /** Only treat tracks as duplicates if their files are identical */
+  compareHash?: boolean
+}
 
 export interface ImportStatus {
   errors: Array<string>
+  /** New tracks */
   tracksCount: number
+  /** New playlists and folders */
   playlistsCount: number
+  /** Previously imported tracks that got new plays, skips, ratings or likes */
+  updatedTracksCount: number
+  /** Previously imported playlists and folders that were updated */
+  updatedPlaylistsCount: number
+  addedPlaysCount: number
+  addedSkipsCount: number
+}
+
+/** What `finish` will do */
+export interface ItunesImportPreview {
+  /** New tracks */
+  tracks: Array<TrackPreview>
+  skippedTracks: Array<SkippedTrackPreview>
+  /** New and updated playlists, in tree order */
+  playlists: Array<PlaylistPreview>
+  /** Bytes that will be copied or moved into the tracks folder */
+  bytesToCopy: number
+  /** Free space where the tracks folder is, if known */
+  freeSpace?: number
 }
 
 export interface JsImage {
@@ -102,6 +358,8 @@ export declare function move_tracks(playlistId: string, itemIds: Array<ItemId>,
 
 export declare function new_playlist(name: string, description: string, isFolder: boolean, parentId: string): void
 
+export declare function new_smart_playlist(name: string, description: string, rules: SmartPlaylistRules, parentId: string): TrackListID
+
 export interface Paths {
   pathSeparator: string
   libraryDir: string
@@ -112,6 +370,7 @@ export interface Paths {
   localDataDir: string
   viewOptionsFile: string
   queueFile: string
+  scrobbleQueueFile: string
   logsDir: string
 }
 
@@ -130,6 +389,52 @@ export interface Playlist {
 
 export declare function playlist_filter_duplicates(playlistId: TrackID, ids: Array<string>): Array<TrackID>
 
+export interface PlaylistPreview {
+  name: string
+  /** "playlist", "smart" or "folder" */
+  kind: string
+  /** 0 for playlists in the root */
+  depth: number
+  /** False if it replaces a previously imported playlist */
+  isNew: boolean
+  /** Not set for folders */
+  tracksCount?: number
+}
+
+export interface PlaylistImportStatus {
+  playlistId: TrackListID
+  /** Entries that could not be resolved or imported */
+  errors: Array<string>
+  /** Problems with imported files that were worked around */
+  warnings: Array<string>
+  /** Entries added to the playlist */
+  tracksCount: number
+  /** Unknown files that were imported into the library */
+  importedCount: number
+}
+
+export interface PlaysExportStatus {
+  listensCount: number
+  /**
+   * Plays from `playsImported` in the date range. They have no timestamps,
+   * so they are not exported.
+   */
+  importedPlaysCount: number
+}
+
+export interface PlaysImportStatus {
+  /** Lines that could not be parsed */
+  errors: Array<string>
+  /** Entries that did not match any track */
+  unmatched: Array<string>
+  /** Entries that matched a track */
+  matchedCount: number
+  /** Matched entries that were already in the track's plays */
+  duplicateCount: number
+  addedPlaysCount: number
+  updatedTracksCount: number
+}
+
 export interface QueueCurrentState {
   item: QueueItemState
   from_auto_queue: boolean
@@ -156,20 +461,82 @@ export declare function read_cover_async(filePath: string, index: number): Promi
 /** Returns `None` if the file does not have an image */
 export declare function read_small_cover_async(path: string, index: number, cacheDbPath: string): Promise<Buffer | null>
 
+/**
+ * Min, max and RMS of the audio in `buckets` evenly sized parts. Cached
+ * until the file is modified.
+ */
+export declare function read_waveform_async(path: string, buckets: number, cacheDbPath: string): Promise<Waveform>
+
 export declare function remove_from_playlist(playlistId: TrackID, itemIds: Array<ItemId>): void
 
 export declare function remove_image(index: number): void
 
+/**
+ * Replaces the file of a track with a copy of another file. See
+ * `import::relink`.
+ */
+export declare function replace_track_file(trackId: TrackID, path: string, now: MsSinceUnixEpoch, keepTags: boolean): ImportedTrack
+
 export declare function save(): void
 
 export declare function save_queue_state(queueState: QueueState, filePath: string): Promise<void>
 
 export declare function save_view_options(viewOptions: ViewOptions, filePath: string): Promise<void>
 
+export interface ScrobbleFlushStatus {
+  submittedCount: number
+  /** Listens that the server rejected, which were removed from the queue */
+  rejected: Array<string>
+  pendingCount: number
+  /** The error of a failed submission */
+  error?: string
+  /** When the next submission will be attempted, if it failed */
+  retryAt?: MsSinceUnixEpoch
+}
+
+export interface ScrobblerConfig {
+  baseUrl?: string
+  token?: string
+}
+
 export declare function set_image(index: number, path: string): void
 
 export declare function set_image_data(index: number, bytes: ArrayBuffer): void
 
+export interface SkippedTrackPreview {
+  name: string
+  artist: string
+  /** iTunes track location URL */
+  location?: string
+  reason: string
+}
+
+/** A playlist whose tracks are computed from its rules */
+export interface SmartPlaylist {
+  id: TrackListID
+  name: string
+  description?: string
+  liked: boolean
+  disliked: boolean
+  importedFrom?: string
+  originalId?: string
+  dateImported?: MsSinceUnixEpoch
+  dateCreated?: MsSinceUnixEpoch
+  rules: SmartPlaylistRules
+}
+
+export interface SmartPlaylistRules {
+  /** Same syntax as the track list filter */
+  filterQuery: string
+  /** Track field, or "index" for the order tracks were added in */
+  sortKey: string
+  sortDesc: boolean
+  /** Maximum number of tracks */
+  limitCount?: number
+  /** Maximum total duration in seconds */
+  limitDuration?: number
+}
+
 export interface Special {
   id: TrackListID
   name: SpecialTrackListName
@@ -181,11 +548,33 @@ export declare const enum SpecialTrackListName {
   Root = 0
 }
 
+export interface SpotifyImportStatus {
+  errors: Array<string>
+  /**
+   * Artist and title of tracks that were not found, with the number of
+   * entries
+   */
+  unmatched: Array<string>
+  /** Entries within periods that were imported before */
+  alreadyImportedCount: number
+  addedPlaysCount: number
+  addedSkipsCount: number
+  /** Milliseconds of listening time added */
+  addedPlayTime: number
+  updatedTracksCount: number
+}
+
 export interface Track {
   size: number
   duration: number
+  /** 0 if unknown */
   bitrate: number
+  /** 0 if unknown */
   sampleRate: number
+  /**
+   * Filename in the tracks folder, or an absolute path if the file is
+   * referenced in place
+   */
   file: string
   dateModified: MsSinceUnixEpoch
   dateAdded: MsSinceUnixEpoch
@@ -202,6 +591,8 @@ export interface Track {
   rating?: PercentInteger
   year?: number
   bpm?: number
+  /** Musical key, like "Am" or "F#" */
+  key?: string
   comments?: string
   grouping?: string
   liked?: boolean
@@ -225,6 +616,20 @@ export interface Track {
   skipsImported?: Array<CountObject>
   /** -100 to 100 */
   volume?: number
+  /** Seconds into the file where playback starts */
+  startTime?: number
+  /** Seconds into the file where playback stops */
+  stopTime?: number
+  albumRating?: number
+  albumLiked?: boolean
+  /** Equalizer preset name, like "Bass Booster" */
+  equalizer?: string
+  /** Integrated loudness in LUFS */
+  loudness?: number
+  /** True peak, where 1.0 is full scale */
+  peak?: number
+  albumLoudness?: number
+  albumPeak?: number
 }
 
 export declare function track_exists(id: string): boolean
@@ -251,7 +656,42 @@ export interface TrackMd {
   discNum: string
   discCount: string
   bpm: string
+  key: string
   comments: string
+  /** Seconds. Not saved to the file tags */
+  startTime: string
+  /** Seconds. Not saved to the file tags */
+  stopTime: string
+  /** 0 to 100. Not saved to the file tags */
+  albumRating: string
+  /** Not saved to the file tags */
+  albumLiked: boolean
+  /** Not saved to the file tags */
+  equalizer: string
+}
+
+export interface TrackMergePreview {
+  playCount: number
+  skipCount: number
+  /** Playlist entries that will point to the merged track */
+  playlistEntryCount: number
+  /**
+   * Playlist entries that will be removed, since the playlist already has
+   * the merged track
+   */
+  removedPlaylistEntryCount: number
+  /** Files that will be moved to the trash */
+  trashedFiles: Array<string>
+}
+
+export interface TrackPreview {
+  name: string
+  artist: string
+  /** Path of the iTunes file */
+  sourcePath: string
+  /** Path of the file in Ferrum. Same as `sourcePath` when referencing files */
+  destinationPath: string
+  size: number
 }
 
 export interface TracksPage {
@@ -272,6 +712,8 @@ export interface TracksPageOptions {
 
 export declare function update_playlist(id: string, name: string, description: string): void
 
+export declare function update_smart_playlist_rules(id: string, rules: SmartPlaylistRules): void
+
 export declare function update_track_info(trackId: string, info: TrackMd): void
 
 export interface ViewOptions {
@@ -282,3 +724,9 @@ export interface ViewOptions {
   noAutoUpdate: boolean
   skipUpdatingToVersion?: string
 }
+
+export interface Waveform {
+  min: Array<number>
+  max: Array<number>
+  rms: Array<number>
+}
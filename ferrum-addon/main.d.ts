@@ -19,6 +19,9 @@ declare module 'ferrum-addon/addon' {
 	export interface Special {
 		type: 'special'
 	}
+	export interface SmartPlaylist {
+		type: 'smart'
+	}
 
-	export type TrackList = Playlist | Folder | Special
+	export type TrackList = Playlist | Folder | Special | SmartPlaylist
 }
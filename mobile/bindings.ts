@@ -52,7 +52,11 @@ async getTracksPage(options: TracksPageOptions) : Promise<Result<TracksPage, str
 
 /** user-defined types **/
 
-export type CountObject = { count: string; fromDate: string; toDate: string }
+export type CountObject = { count: string; fromDate: string; toDate: string; 
+/**
+ * For example "rhythmbox". Counts without a source are from iTunes.
+ */
+source?: string | null }
 export type Folder = { id: string; name: string; description?: string | null; liked: boolean; disliked: boolean; 
 /**
  * For example "itunes"
@@ -64,18 +68,76 @@ importedFrom?: string | null;
 originalId?: string | null; dateImported?: string | null; dateCreated?: string | null; children: string[] }
 export type LibraryTauri = { track_lists: Partial<{ [key in string]: TrackList }>; song_count: string }
 export type Playlist = { id: string; name: string; description?: string | null; liked: boolean; disliked: boolean; importedFrom?: string | null; originalId?: string | null; dateImported?: string | null; dateCreated?: string | null; tracks: string[] }
+/**
+ * A playlist whose tracks are computed from its rules
+ */
+export type SmartPlaylist = { id: string; name: string; description?: string | null; liked: boolean; disliked: boolean; importedFrom?: string | null; originalId?: string | null; dateImported?: string | null; dateCreated?: string | null; rules: SmartPlaylistRules }
+export type SmartPlaylistRules = { 
+/**
+ * Same syntax as the track list filter
+ */
+filterQuery: string; 
+/**
+ * Track field, or "index" for the order tracks were added in
+ */
+sortKey: string; sortDesc: boolean; 
+/**
+ * Maximum number of tracks
+ */
+limitCount?: number | null; 
+/**
+ * Maximum total duration in seconds
+ */
+limitDuration?: number | null }
 export type Special = { id: string; name: SpecialTrackListName; dateCreated: string; children: string[] }
 export type SpecialTrackListName = "Root"
-export type Track = { size: string; duration: number; bitrate: number; sampleRate: number; file: string; dateModified: string; dateAdded: string; name: string; importedFrom?: string | null; 
+export type Track = { size: string; duration: number; 
+/**
+ * 0 if unknown
+ */
+bitrate: number; 
+/**
+ * 0 if unknown
+ */
+sampleRate: number; 
+/**
+ * Filename in the tracks folder, or an absolute path if the file is
+ * referenced in place
+ */
+file: string; dateModified: string; dateAdded: string; name: string; importedFrom?: string | null; 
 /**
  * Imported ID, like iTunes Persistent ID
  */
-originalId?: string | null; artist?: string; composer?: string | null; sortName?: string | null; sortArtist?: string | null; sortComposer?: string | null; genre?: string | null; rating?: number | null; year?: string | null; bpm?: number | null; comments?: string | null; grouping?: string | null; liked?: boolean | null; disliked?: boolean | null; disabled?: boolean | null; compilation?: boolean | null; albumName?: string | null; albumArtist?: string | null; sortAlbumName?: string | null; sortAlbumArtist?: string | null; trackNum?: number | null; trackCount?: number | null; discNum?: number | null; discCount?: number | null; dateImported?: string | null; playCount?: number | null; plays?: string[] | null; playsImported?: CountObject[] | null; skipCount?: number | null; skips?: string[] | null; skipsImported?: CountObject[] | null; 
+originalId?: string | null; artist?: string; composer?: string | null; sortName?: string | null; sortArtist?: string | null; sortComposer?: string | null; genre?: string | null; rating?: number | null; year?: string | null; bpm?: number | null; 
+/**
+ * Musical key, like "Am" or "F#"
+ */
+key?: string | null; comments?: string | null; grouping?: string | null; liked?: boolean | null; disliked?: boolean | null; disabled?: boolean | null; compilation?: boolean | null; albumName?: string | null; albumArtist?: string | null; sortAlbumName?: string | null; sortAlbumArtist?: string | null; trackNum?: number | null; trackCount?: number | null; discNum?: number | null; discCount?: number | null; dateImported?: string | null; playCount?: number | null; plays?: string[] | null; playsImported?: CountObject[] | null; skipCount?: number | null; skips?: string[] | null; skipsImported?: CountObject[] | null; 
 /**
  * -100 to 100
  */
-volume?: number | null }
-export type TrackList = ({ type: "playlist" } & Playlist) | ({ type: "folder" } & Folder) | ({ type: "special" } & Special)
+volume?: number | null; 
+/**
+ * Seconds into the file where playback starts
+ */
+startTime?: number | null; 
+/**
+ * Seconds into the file where playback stops
+ */
+stopTime?: number | null; albumRating?: number | null; albumLiked?: boolean | null; 
+/**
+ * Equalizer preset name, like "Bass Booster"
+ */
+equalizer?: string | null; 
+/**
+ * Integrated loudness in LUFS
+ */
+loudness?: number | null; 
+/**
+ * True peak, where 1.0 is full scale
+ */
+peak?: number | null; albumLoudness?: number | null; albumPeak?: number | null }
+export type TrackList = ({ type: "playlist" } & Playlist) | ({ type: "folder" } & Folder) | ({ type: "smart" } & SmartPlaylist) | ({ type: "special" } & Special)
 export type TracksPage = { playlist_kind: string; playlist_name: string; playlist_description: string | null; playlist_length: number; item_ids: number[] }
 export type TracksPageOptions = { playlist_id: string; sort_key: string; sort_desc: boolean; filter_query: string; group_album_tracks: boolean }
 
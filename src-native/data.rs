@@ -103,6 +103,7 @@ impl Data {
 			local_data_dir: path_to_string(&local_data_dir),
 			view_options_file: path_to_string(local_data_dir.join("view.json")),
 			queue_file: path_to_string(local_data_dir.join("queue.cbor")),
+			scrobble_queue_file: path_to_string(local_data_dir.join("scrobbles.cbor")),
 			// This makes sure we can get the logs dir, which is important for crash logs
 			logs_dir: path_to_string(app_log_dir()?),
 		};
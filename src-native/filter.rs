@@ -1,4 +1,5 @@
 use crate::library_types::{ItemId, Library, TRACK_ID_MAP};
+use crate::musical_key::MusicalKey;
 use rayon::prelude::*;
 use std::str::Chars;
 use std::time::Instant;
@@ -57,6 +58,27 @@ fn find_match_opt(text: &Option<String>, keyword: &str) -> bool {
 	}
 }
 
+/// Keys match if they are the same key in any notation, like "Am" and "8A"
+fn key_matches(key: &Option<String>, keyword: &str) -> bool {
+	let key = match key {
+		Some(key) => key,
+		None => return keyword.is_empty(),
+	};
+	match (MusicalKey::parse(key), MusicalKey::parse(keyword)) {
+		(Some(key), Some(keyword_key)) => key == keyword_key,
+		_ => key.eq_ignore_ascii_case(keyword),
+	}
+}
+
+/// Matches times shown as `m:ss` like in the track list, or as seconds
+fn time_matches(seconds: Option<f64>, keyword: &str) -> bool {
+	let Some(seconds) = seconds else {
+		return keyword.is_empty();
+	};
+	let total = seconds.round() as i64;
+	format!("{}:{:02}", total / 60, total % 60) == keyword || total.to_string() == keyword
+}
+
 fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
 	if text.len() >= prefix.len() && text.get(..prefix.len())?.eq_ignore_ascii_case(prefix) {
 		Some(&text[prefix.len()..])
@@ -156,9 +178,28 @@ fn filter_keyword(ids: Vec<ItemId>, keyword: Keyword, library: &Library) -> Vec<
 						.unwrap_or("".to_string())
 						== keyword.literal
 				}
+				"playcount" | "play_count" => {
+					track.playCount.unwrap_or(0).to_string() == keyword.literal
+				}
+				"skipcount" | "skip_count" => {
+					track.skipCount.unwrap_or(0).to_string() == keyword.literal
+				}
 				"bpm" => {
 					track.bpm.map(|n| n.to_string()).unwrap_or("".to_string()) == keyword.literal
 				}
+				"key" => key_matches(&track.key, &keyword.literal),
+				"start" | "starttime" | "start_time" => {
+					time_matches(track.startTime, &keyword.literal)
+				}
+				"stop" | "stoptime" | "stop_time" => time_matches(track.stopTime, &keyword.literal),
+				"albumrating" | "album_rating" => {
+					track
+						.albumRating
+						.map(|n| n.to_string())
+						.unwrap_or("".to_string())
+						== keyword.literal
+				}
+				"eq" | "equalizer" => find_match_opt(&track.equalizer, &keyword.literal),
 				_ => {
 					find_match(&track.name, &keyword.full_word)
 						|| find_match(&track.artist, &keyword.full_word)
@@ -246,6 +287,18 @@ impl Keyword {
 	}
 }
 
+/// Formats a filter query keyword, or returns `None` if the value cannot be
+/// expressed in a filter query
+pub fn format_keyword(keyword: &str, value: &str) -> Option<String> {
+	if value.contains('"') {
+		None
+	} else if value.contains(char::is_whitespace) {
+		Some(format!("{keyword}:\"{value}\""))
+	} else {
+		Some(format!("{keyword}:{value}"))
+	}
+}
+
 pub fn filter(mut item_ids: Vec<ItemId>, query: String, library: &Library) -> Vec<ItemId> {
 	let now = Instant::now();
 	if query == "" {
@@ -890,3 +943,20 @@ fn check(user_char: char, data_char: char) -> Eq {
 		false => Eq::False,
 	};
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn time_matches_test() {
+		assert!(time_matches(Some(90.4), "1:30"));
+		assert!(time_matches(Some(90.4), "90"));
+		assert!(time_matches(Some(5.0), "0:05"));
+		assert!(time_matches(Some(3725.0), "62:05"));
+		assert!(!time_matches(Some(90.4), "1:31"));
+		assert!(!time_matches(Some(90.4), ""));
+		assert!(!time_matches(None, "0:00"));
+		assert!(time_matches(None, ""));
+	}
+}
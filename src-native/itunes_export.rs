@@ -0,0 +1,551 @@
+use crate::data::Data;
+use crate::data_js::get_data;
+use crate::get_now_timestamp;
+use crate::library::{Paths, get_tracklist_item_ids};
+use crate::library_types::{
+	Library, MsSinceUnixEpoch, Track, TrackList, get_track_ids_from_item_ids,
+};
+use crate::tracks::import::FileType;
+use anyhow::{Context, Result, bail};
+use napi::Env;
+use plist::{Dictionary, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, the output is guaranteed to stay the
+/// same between Rust versions.
+fn fnv1a(s: &str) -> u64 {
+	let mut hash: u64 = 0xcbf29ce484222325;
+	for byte in s.bytes() {
+		hash ^= u64::from(byte);
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+	hash
+}
+
+/// Items imported from iTunes keep their original Persistent ID. Other items
+/// get one derived from their Ferrum ID, so repeated exports keep the same IDs.
+fn persistent_id(id: &str, imported_from: Option<&str>, original_id: Option<&str>) -> String {
+	match (imported_from, original_id) {
+		(Some("itunes"), Some(original_id)) => original_id.to_string(),
+		_ => format!("{:016X}", fnv1a(id)),
+	}
+}
+
+/// iTunes dates have whole seconds
+fn timestamp_to_date(timestamp: MsSinceUnixEpoch) -> Option<Value> {
+	let ms = u64::try_from(timestamp).ok()?;
+	let sys_time = UNIX_EPOCH + Duration::from_secs(ms / 1000);
+	Some(Value::Date(sys_time.into()))
+}
+
+fn file_url(path: &Path) -> Result<String> {
+	let path = std::path::absolute(path).context("Invalid track path")?;
+	match url::Url::from_file_path(&path) {
+		Ok(url) => Ok(url.to_string()),
+		Err(()) => bail!("Invalid track path: {}", path.to_string_lossy()),
+	}
+}
+
+fn kind(file_type: &FileType) -> &'static str {
+	match file_type {
+		FileType::Mp3 => "MPEG audio file",
+		FileType::M4a => "AAC audio file",
+		FileType::Opus => "Opus audio file",
+		FileType::Flac => "FLAC audio file",
+		FileType::Ogg => "Ogg Vorbis audio file",
+		FileType::Wav => "WAV audio file",
+		FileType::Aiff => "AIFF audio file",
+	}
+}
+
+struct DictBuilder(Dictionary);
+impl DictBuilder {
+	fn set(&mut self, key: &str, value: impl Into<Value>) {
+		self.0.insert(key.to_string(), value.into());
+	}
+	fn set_opt<T: Into<Value>>(&mut self, key: &str, value: Option<T>) {
+		if let Some(value) = value {
+			self.set(key, value);
+		}
+	}
+	fn set_true(&mut self, key: &str, value: Option<bool>) {
+		if value == Some(true) {
+			self.set(key, true);
+		}
+	}
+}
+
+fn track_dict(id: &str, xml_id: u64, track: &Track, paths: &Paths) -> Result<Dictionary> {
+	let mut dict = DictBuilder(Dictionary::new());
+	dict.set("Track ID", xml_id);
+	dict.set("Name", track.name.as_str());
+	if !track.artist.is_empty() {
+		dict.set("Artist", track.artist.as_str());
+	}
+	dict.set_opt("Album Artist", track.albumArtist.as_deref());
+	dict.set_opt("Composer", track.composer.as_deref());
+	dict.set_opt("Album", track.albumName.as_deref());
+	dict.set_opt("Grouping", track.grouping.as_deref());
+	dict.set_opt("Genre", track.genre.as_deref());
+	if let Ok(file_type) = FileType::from_path(&paths.get_track_file_path(&track.file)) {
+		dict.set("Kind", kind(&file_type));
+	}
+	dict.set("Size", track.size);
+	dict.set("Total Time", (track.duration * 1000.0).round() as i64);
+	dict.set_opt("Disc Number", track.discNum);
+	dict.set_opt("Disc Count", track.discCount);
+	dict.set_opt("Track Number", track.trackNum);
+	dict.set_opt("Track Count", track.trackCount);
+	dict.set_opt("Year", track.year);
+	dict.set_opt("BPM", track.bpm.map(|bpm| bpm.round() as i64));
+	dict.set_opt("Date Modified", timestamp_to_date(track.dateModified));
+	dict.set_opt("Date Added", timestamp_to_date(track.dateAdded));
+	if track.bitrate > 0.0 {
+		dict.set("Bit Rate", (track.bitrate / 1000.0).round() as i64);
+	}
+	if track.sampleRate > 0.0 {
+		dict.set("Sample Rate", track.sampleRate.round() as i64);
+	}
+	dict.set_opt("Comments", track.comments.as_deref());
+	dict.set_opt("Play Count", track.playCount);
+	let last_play = track.plays.as_ref().and_then(|plays| plays.iter().max());
+	dict.set_opt(
+		"Play Date UTC",
+		last_play.and_then(|ts| timestamp_to_date(*ts)),
+	);
+	dict.set_opt("Skip Count", track.skipCount);
+	let last_skip = track.skips.as_ref().and_then(|skips| skips.iter().max());
+	dict.set_opt("Skip Date", last_skip.and_then(|ts| timestamp_to_date(*ts)));
+	dict.set_opt("Rating", track.rating);
+	dict.set_true("Loved", track.liked);
+	dict.set_true("Disliked", track.disliked);
+	dict.set_true("Disabled", track.disabled);
+	dict.set_true("Compilation", track.compilation);
+	if let Some(volume) = track.volume {
+		dict.set(
+			"Volume Adjustment",
+			(f64::from(volume) * 2.55).round() as i64,
+		);
+	}
+	let to_ms = |seconds: f64| (seconds * 1000.0).round() as i64;
+	dict.set_opt("Start Time", track.startTime.map(to_ms));
+	dict.set_opt("Stop Time", track.stopTime.map(to_ms));
+	dict.set_opt("Album Rating", track.albumRating);
+	dict.set_true("Album Loved", track.albumLiked);
+	dict.set_opt("Equalizer", track.equalizer.as_deref());
+	dict.set_opt("Sort Album", track.sortAlbumName.as_deref());
+	dict.set_opt("Sort Album Artist", track.sortAlbumArtist.as_deref());
+	dict.set_opt("Sort Artist", track.sortArtist.as_deref());
+	dict.set_opt("Sort Composer", track.sortComposer.as_deref());
+	dict.set_opt("Sort Name", track.sortName.as_deref());
+	let persistent_id = persistent_id(
+		id,
+		track.importedFrom.as_deref(),
+		track.originalId.as_deref(),
+	);
+	dict.set("Persistent ID", persistent_id);
+	dict.set("Track Type", "File");
+	let file_path = paths.get_track_file_path(&track.file);
+	dict.set("Location", file_url(&file_path)?);
+	Ok(dict.0)
+}
+
+fn playlist_items(track_ids: &[String], xml_ids: &HashMap<&str, u64>) -> Value {
+	let items = track_ids
+		.iter()
+		.filter_map(|id| xml_ids.get(id.as_str()))
+		.map(|xml_id| {
+			let mut item = Dictionary::new();
+			item.insert("Track ID".to_string(), (*xml_id).into());
+			Value::Dictionary(item)
+		})
+		.collect();
+	Value::Array(items)
+}
+
+struct PlaylistsBuilder<'a> {
+	library: &'a Library,
+	xml_ids: HashMap<&'a str, u64>,
+	playlists: Vec<Value>,
+	next_playlist_id: u64,
+}
+impl PlaylistsBuilder<'_> {
+	/// Adds playlists and folders in tree order, so parents come before their
+	/// children
+	fn add_children(&mut self, children: &[String], parent_id: Option<&str>) -> Result<()> {
+		for child_id in children {
+			let tracklist = self.library.get_tracklist(child_id)?;
+			let mut dict = DictBuilder(Dictionary::new());
+			let persistent_id = match tracklist {
+				TrackList::Playlist(playlist) => persistent_id(
+					&playlist.id,
+					playlist.importedFrom.as_deref(),
+					playlist.originalId.as_deref(),
+				),
+				TrackList::Folder(folder) => persistent_id(
+					&folder.id,
+					folder.importedFrom.as_deref(),
+					folder.originalId.as_deref(),
+				),
+				TrackList::Smart(smart) => persistent_id(
+					&smart.id,
+					smart.importedFrom.as_deref(),
+					smart.originalId.as_deref(),
+				),
+				TrackList::Special(_) => continue,
+			};
+			dict.set("Playlist ID", self.next_playlist_id);
+			self.next_playlist_id += 1;
+			dict.set("Playlist Persistent ID", persistent_id.as_str());
+			dict.set_opt("Parent Persistent ID", parent_id);
+			dict.set("All Items", true);
+			match tracklist {
+				TrackList::Playlist(playlist) => {
+					dict.set("Name", playlist.name.as_str());
+					dict.set_opt("Description", playlist.description.as_deref());
+					dict.set_true("Loved", Some(playlist.liked));
+					dict.set_true("Disliked", Some(playlist.disliked));
+					let track_ids = playlist.get_track_ids();
+					dict.set("Playlist Items", playlist_items(&track_ids, &self.xml_ids));
+					self.playlists.push(Value::Dictionary(dict.0));
+				}
+				TrackList::Folder(folder) => {
+					dict.set("Name", folder.name.as_str());
+					dict.set_opt("Description", folder.description.as_deref());
+					dict.set_true("Loved", Some(folder.liked));
+					dict.set_true("Disliked", Some(folder.disliked));
+					dict.set("Folder", true);
+					// Like iTunes, folders contain the tracks of their descendants
+					let item_ids = get_tracklist_item_ids(self.library, &folder.id)?;
+					let track_ids = get_track_ids_from_item_ids(&item_ids);
+					dict.set("Playlist Items", playlist_items(&track_ids, &self.xml_ids));
+					self.playlists.push(Value::Dictionary(dict.0));
+					self.add_children(&folder.children, Some(&persistent_id))?;
+				}
+				// Exported with their current tracks, since the rules can't be
+				// represented
+				TrackList::Smart(smart) => {
+					dict.set("Name", smart.name.as_str());
+					dict.set_opt("Description", smart.description.as_deref());
+					dict.set_true("Loved", Some(smart.liked));
+					dict.set_true("Disliked", Some(smart.disliked));
+					let item_ids = get_tracklist_item_ids(self.library, &smart.id)?;
+					let track_ids = get_track_ids_from_item_ids(&item_ids);
+					dict.set("Playlist Items", playlist_items(&track_ids, &self.xml_ids));
+					self.playlists.push(Value::Dictionary(dict.0));
+				}
+				TrackList::Special(_) => {}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Builds a Library.xml plist that `itunes_import` and other apps can read.
+/// Tracks whose file can't be turned into a URL are skipped, and returned as
+/// errors.
+pub fn build_itunes_library(data: &Data) -> Result<(Value, Vec<String>)> {
+	let library = &data.library;
+	let paths = &data.paths;
+
+	let mut tracks = Dictionary::new();
+	let mut xml_ids = HashMap::new();
+	let mut errors = Vec::new();
+	for (id, track) in library.get_tracks() {
+		let xml_id = xml_ids.len() as u64 + 1;
+		match track_dict(id, xml_id, track, paths) {
+			Ok(dict) => {
+				tracks.insert(xml_id.to_string(), Value::Dictionary(dict));
+				xml_ids.insert(id.as_str(), xml_id);
+			}
+			Err(e) => errors.push(format!(
+				"[{} - {}] Skipped track: {e:#}",
+				track.artist, track.name
+			)),
+		}
+	}
+
+	let all_track_ids: Vec<String> = library.get_tracks().keys().cloned().collect();
+	let library_id = format!("{:016X}", fnv1a(&paths.library_dir));
+	let mut builder = PlaylistsBuilder {
+		library,
+		xml_ids,
+		playlists: Vec::new(),
+		next_playlist_id: 1,
+	};
+
+	// iTunes includes a hidden master playlist, and a "Music" playlist that
+	// `itunes_import` uses to find music tracks
+	let mut master = DictBuilder(Dictionary::new());
+	master.set("Master", true);
+	master.set("Playlist ID", builder.next_playlist_id);
+	master.set(
+		"Playlist Persistent ID",
+		format!("{:016X}", fnv1a("Library")),
+	);
+	master.set("All Items", true);
+	master.set("Visible", false);
+	master.set("Name", "Library");
+	master.set(
+		"Playlist Items",
+		playlist_items(&all_track_ids, &builder.xml_ids),
+	);
+	builder.playlists.push(Value::Dictionary(master.0));
+	builder.next_playlist_id += 1;
+
+	let mut music = DictBuilder(Dictionary::new());
+	music.set("Playlist ID", builder.next_playlist_id);
+	music.set("Playlist Persistent ID", format!("{:016X}", fnv1a("Music")));
+	music.set("Distinguished Kind", 4);
+	music.set("Music", true);
+	music.set("All Items", true);
+	music.set("Name", "Music");
+	music.set(
+		"Playlist Items",
+		playlist_items(&all_track_ids, &builder.xml_ids),
+	);
+	builder.playlists.push(Value::Dictionary(music.0));
+	builder.next_playlist_id += 1;
+
+	let root = match library.get_tracklist("root")? {
+		TrackList::Special(root) => root,
+		_ => bail!("Root is not a special playlist"),
+	};
+	builder.add_children(&root.children, None)?;
+
+	let tracks_dir = Path::new(&paths.tracks_dir);
+	let mut music_folder = file_url(tracks_dir)?;
+	if !music_folder.ends_with('/') {
+		music_folder.push('/');
+	}
+
+	let mut dict = DictBuilder(Dictionary::new());
+	dict.set("Major Version", 1);
+	dict.set("Minor Version", 1);
+	dict.set_opt("Date", timestamp_to_date(get_now_timestamp()));
+	dict.set("Application Version", "Ferrum");
+	dict.set("Features", 5);
+	dict.set("Show Content Ratings", true);
+	dict.set("Music Folder", music_folder);
+	dict.set("Library Persistent ID", library_id);
+	dict.set("Tracks", Value::Dictionary(tracks));
+	dict.set("Playlists", Value::Array(builder.playlists));
+	Ok((Value::Dictionary(dict.0), errors))
+}
+
+/// Returns errors for tracks that were skipped
+#[napi(js_name = "export_itunes_library")]
+#[allow(dead_code)]
+pub fn export_itunes_library(path: String, env: Env) -> Result<Vec<String>> {
+	let data: &Data = get_data(&env);
+	let (value, errors) = build_itunes_library(data)?;
+	value
+		.to_file_xml(&path)
+		.with_context(|| format!("Unable to write {path}"))?;
+	Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::itunes_import::{ItunesImport, import_itunes};
+	use crate::library_types::{Folder, new_item_ids_from_track_ids};
+	use crate::playlists::insert_tracklist;
+	use crate::tracks::import::ImportMode;
+	use std::fs;
+
+	/// One second of mono 16-bit silence at 8000 Hz
+	fn wav_bytes() -> Vec<u8> {
+		let mut bytes = b"RIFF".to_vec();
+		bytes.extend((36u32 + 16000).to_le_bytes());
+		bytes.extend(b"WAVEfmt ");
+		bytes.extend(16u32.to_le_bytes());
+		bytes.extend(1u16.to_le_bytes());
+		bytes.extend(1u16.to_le_bytes());
+		bytes.extend(8000u32.to_le_bytes());
+		bytes.extend(16000u32.to_le_bytes());
+		bytes.extend(2u16.to_le_bytes());
+		bytes.extend(16u16.to_le_bytes());
+		bytes.extend(b"data");
+		bytes.extend(16000u32.to_le_bytes());
+		bytes.extend([0; 16000]);
+		bytes
+	}
+
+	fn test_data(dir: &Path) -> Data {
+		let dir = dir.to_string_lossy().into_owned();
+		let paths = Paths {
+			path_separator: std::path::MAIN_SEPARATOR.to_string(),
+			library_dir: dir.clone(),
+			tracks_dir: dir.clone(),
+			library_json: dir.clone(),
+			cache_dir: dir.clone(),
+			cache_db: dir.clone(),
+			local_data_dir: dir.clone(),
+			view_options_file: dir.clone(),
+			queue_file: dir.clone(),
+			scrobble_queue_file: dir.clone(),
+			logs_dir: dir,
+		};
+		Data {
+			paths,
+			library: Library::new(),
+			current_tag: None,
+		}
+	}
+
+	fn get<'a>(value: &'a Value, key: &str) -> &'a Value {
+		value.as_dictionary().unwrap().get(key).unwrap()
+	}
+
+	/// Track Persistent IDs, followed by playlist Persistent IDs in order
+	fn persistent_ids(value: &Value) -> Vec<String> {
+		let mut ids: Vec<String> = get(value, "Tracks")
+			.as_dictionary()
+			.unwrap()
+			.values()
+			.map(|track| get(track, "Persistent ID").as_string().unwrap().to_string())
+			.collect();
+		ids.sort();
+		for playlist in get(value, "Playlists").as_array().unwrap() {
+			let id = get(playlist, "Playlist Persistent ID");
+			ids.push(id.as_string().unwrap().to_string());
+		}
+		ids
+	}
+
+	fn find_playlist<'a>(value: &'a Value, name: &str) -> &'a Value {
+		let playlists = get(value, "Playlists").as_array().unwrap();
+		let playlist = playlists
+			.iter()
+			.find(|playlist| get(playlist, "Name").as_string() == Some(name));
+		playlist.unwrap()
+	}
+
+	#[tokio::test]
+	async fn round_trip_test() {
+		let dir = std::env::temp_dir().join("ferrum_itunes_export_test");
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		let file_path = dir.join("track.wav");
+		fs::write(&file_path, wav_bytes()).unwrap();
+
+		let mut data = test_data(&dir);
+		let track: Track = serde_json::from_value(serde_json::json!({
+			"size": 16044,
+			"duration": 1.0,
+			"bitrate": 128000.0,
+			"sampleRate": 8000.0,
+			"file": file_path.to_string_lossy(),
+			"dateModified": 1_600_000_000_000i64,
+			"dateAdded": 1_500_000_000_000i64,
+			"name": "Title",
+			"artist": "Artist",
+			"albumName": "Album",
+			"albumArtist": "Album Artist",
+			"genre": "Genre",
+			"comments": "Comment",
+			"year": 2001,
+			"trackNum": 3,
+			"trackCount": 9,
+			"rating": 80,
+			"liked": true,
+			"playCount": 2,
+			"plays": [1_600_000_100_000i64, 1_600_000_200_000i64],
+		}))
+		.unwrap();
+		let track_id = "export_track".to_string();
+		data.library.insert_track(track_id.clone(), track.clone());
+
+		let folder = data.library.new_folder("Folder".to_string(), None);
+		let folder_id = folder.id.clone();
+		insert_tracklist(&mut data.library, TrackList::Folder(folder), "root").unwrap();
+		let mut inner = data.library.new_playlist("Inner".to_string(), None);
+		inner.tracks = new_item_ids_from_track_ids(std::slice::from_ref(&track_id));
+		insert_tracklist(&mut data.library, TrackList::Playlist(inner), &folder_id).unwrap();
+		let outer = data.library.new_playlist("Outer".to_string(), None);
+		insert_tracklist(&mut data.library, TrackList::Playlist(outer), "root").unwrap();
+
+		let (value, errors) = build_itunes_library(&data).unwrap();
+		assert!(errors.is_empty());
+		let folder_pid = get(find_playlist(&value, "Folder"), "Playlist Persistent ID");
+		let inner_value = find_playlist(&value, "Inner");
+		assert_eq!(get(inner_value, "Parent Persistent ID"), folder_pid);
+		let outer_value = find_playlist(&value, "Outer");
+		assert!(
+			outer_value
+				.as_dictionary()
+				.unwrap()
+				.get("Parent Persistent ID")
+				.is_none()
+		);
+
+		// Persistent IDs don't change between exports
+		let (second_value, _) = build_itunes_library(&data).unwrap();
+		assert_eq!(persistent_ids(&value), persistent_ids(&second_value));
+
+		let xml_path = dir.join("Library.xml");
+		value.to_file_xml(&xml_path).unwrap();
+		let import = ItunesImport::with_library(
+			Library::new(),
+			data.paths.clone(),
+			ImportMode::Reference,
+			false,
+		);
+		let xml_path = xml_path.to_string_lossy().into_owned();
+		let status = import_itunes(&import, xml_path).await.unwrap();
+		assert_eq!(status.errors, Vec::<String>::new());
+		assert_eq!((status.tracks_count, status.playlists_count), (1, 3));
+		let imported = Data {
+			library: import.take_library().unwrap(),
+			..test_data(&dir)
+		};
+
+		let (imported_id, imported_track) = imported.library.get_tracks().iter().next().unwrap();
+		assert_eq!(imported_track.file, track.file);
+		assert_eq!(imported_track.name, track.name);
+		assert_eq!(imported_track.artist, track.artist);
+		assert_eq!(imported_track.albumName, track.albumName);
+		assert_eq!(imported_track.albumArtist, track.albumArtist);
+		assert_eq!(imported_track.genre, track.genre);
+		assert_eq!(imported_track.comments, track.comments);
+		assert_eq!(imported_track.year, track.year);
+		assert_eq!(imported_track.trackNum, track.trackNum);
+		assert_eq!(imported_track.trackCount, track.trackCount);
+		assert_eq!(imported_track.rating, track.rating);
+		assert_eq!(imported_track.liked, track.liked);
+		assert_eq!(imported_track.playCount, track.playCount);
+		assert_eq!(imported_track.dateAdded, track.dateAdded);
+		assert_eq!(imported_track.dateModified, track.dateModified);
+
+		let root = match imported.library.get_tracklist("root").unwrap() {
+			TrackList::Special(root) => root,
+			_ => panic!("Root is not a special playlist"),
+		};
+		let folder: &Folder = root
+			.children
+			.iter()
+			.find_map(|id| match imported.library.get_tracklist(id).unwrap() {
+				TrackList::Folder(folder) => Some(folder),
+				_ => None,
+			})
+			.unwrap();
+		assert_eq!(folder.name, "Folder");
+		assert_eq!(folder.children.len(), 1);
+		let inner = match imported.library.get_tracklist(&folder.children[0]).unwrap() {
+			TrackList::Playlist(playlist) => playlist,
+			_ => panic!("Not a playlist"),
+		};
+		assert_eq!(inner.name, "Inner");
+		assert_eq!(inner.get_track_ids(), std::slice::from_ref(imported_id));
+		assert_eq!(root.children.len(), 2);
+
+		// Imported items keep their iTunes Persistent IDs when exported again
+		let (reexported, _) = build_itunes_library(&imported).unwrap();
+		assert_eq!(persistent_ids(&value), persistent_ids(&reexported));
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -1,17 +1,17 @@
 use crate::data_js::get_data;
 use crate::get_now_timestamp;
-use crate::library::Paths;
+use crate::itunes_smart::translate_smart_playlist;
+use crate::library::{Paths, get_tracklist_item_ids};
 use crate::library_types::{
-	CountObject, Folder, Library, Playlist, Track, TrackList, new_item_ids_from_track_ids,
+	CountObject, Folder, Library, Playlist, SmartPlaylist, Track, TrackList, TrackListID,
+	new_item_ids_from_track_ids,
 };
-use crate::tracks::generate_filename;
-use crate::tracks::import::{FileType, read_file_metadata};
+use crate::tracks::import::{FileType, ImportMode, read_file_metadata};
 use anyhow::{Context, Result, bail};
 use lofty::file::{AudioFile, TaggedFileExt};
 use napi::Env;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs;
 use std::path::PathBuf;
 use std::sync::Mutex;
 use time::OffsetDateTime;
@@ -261,6 +261,30 @@ struct XmlTrack {
 	// Location
 	#[serde(rename = "Location")]
 	location: Option<String>,
+
+	// Start Time, in milliseconds
+	#[serde(rename = "Start Time")]
+	start_time: Option<u32>,
+
+	// Stop Time, in milliseconds
+	#[serde(rename = "Stop Time")]
+	stop_time: Option<u32>,
+
+	// Album Rating
+	#[serde(rename = "Album Rating")]
+	album_rating: Option<u8>,
+
+	// Album Rating Computed, when the album rating comes from track ratings
+	#[serde(rename = "Album Rating Computed")]
+	album_rating_computed: Option<bool>,
+
+	// Album Loved
+	#[serde(rename = "Album Loved")]
+	album_loved: Option<bool>,
+
+	// Equalizer
+	#[serde(rename = "Equalizer")]
+	equalizer: Option<String>,
 }
 impl XmlTrack {
 	fn artist_title(&self) -> String {
@@ -307,7 +331,7 @@ impl CountInfo {
 	}
 }
 
-fn parse_file_url(value: &str) -> Result<PathBuf> {
+pub fn parse_file_url(value: &str) -> Result<PathBuf> {
 	let file_url = url::Url::parse(value).context("Invalid track location")?;
 	match file_url.scheme() {
 		"file" => {}
@@ -320,7 +344,12 @@ fn parse_file_url(value: &str) -> Result<PathBuf> {
 }
 
 /// Parses track but does not move it to `tracks_dir`
-fn parse_track(xml_track: XmlTrack, start_time: i64, paths: &Paths) -> Result<(PathBuf, Track)> {
+fn parse_track(
+	xml_track: XmlTrack,
+	start_time: i64,
+	paths: &Paths,
+	mode: ImportMode,
+) -> Result<(PathBuf, Track)> {
 	let xml_location = xml_track.location.context("Missing track location")?;
 	if xml_track.track_type != Some("File".to_string()) {
 		bail!(
@@ -355,7 +384,7 @@ fn parse_track(xml_track: XmlTrack, start_time: i64, paths: &Paths) -> Result<(P
 
 	let name = xml_track.name.unwrap_or_default();
 	let artist = xml_track.artist.unwrap_or_default();
-	let filename = generate_filename(&paths, &artist, &name, file_type.file_extension());
+	let filename = mode.track_file(paths, &xml_track_path, &artist, &name, &file_type)?;
 
 	let track = Track {
 		size: file_md.len() as i64,
@@ -383,6 +412,7 @@ fn parse_track(xml_track: XmlTrack, start_time: i64, paths: &Paths) -> Result<(P
 		rating: xml_track.rating,
 		year: xml_track.year,
 		bpm: xml_track.bpm.map(|bpm| bpm.into()),
+		key: None,
 		comments: keep_filled(xml_track.comments),
 		grouping: keep_filled(xml_track.grouping),
 		liked: keep_true(xml_track.loved),
@@ -405,6 +435,7 @@ fn parse_track(xml_track: XmlTrack, start_time: i64, paths: &Paths) -> Result<(P
 				count: count.into(),
 				fromDate: datetime_to_timestamp_millis(xml_track.date_added),
 				toDate: start_time,
+				source: Some("itunes".to_string()),
 			}]
 		}),
 		skipCount: skip.count,
@@ -414,6 +445,7 @@ fn parse_track(xml_track: XmlTrack, start_time: i64, paths: &Paths) -> Result<(P
 				count: count.into(),
 				fromDate: datetime_to_timestamp_millis(xml_track.date_added),
 				toDate: start_time,
+				source: Some("itunes".to_string()),
 			}]
 		}),
 		volume: match xml_track.volume_adjustment {
@@ -427,6 +459,18 @@ fn parse_track(xml_track: XmlTrack, start_time: i64, paths: &Paths) -> Result<(P
 				Some(vol)
 			}
 		},
+		startTime: xml_track.start_time.map(|ms| f64::from(ms) / 1000.0),
+		stopTime: xml_track.stop_time.map(|ms| f64::from(ms) / 1000.0),
+		albumRating: match xml_track.album_rating_computed {
+			Some(true) => None,
+			_ => xml_track.album_rating,
+		},
+		albumLiked: keep_true(xml_track.album_loved),
+		equalizer: keep_filled(xml_track.equalizer),
+		loudness: None,
+		peak: None,
+		albumLoudness: None,
+		albumPeak: None,
 	};
 
 	Ok((xml_track_path, track))
@@ -452,6 +496,9 @@ struct XmlPlaylist {
 	#[serde(rename = "Smart Info")]
 	smart_info: Option<plist::Value>,
 
+	#[serde(rename = "Smart Criteria")]
+	smart_criteria: Option<plist::Value>,
+
 	#[serde(rename = "Playlist Persistent ID")]
 	playlist_persistent_id: String,
 
@@ -475,10 +522,6 @@ impl XmlPlaylist {
 		if self.visible == Some(false) {
 			return false;
 		};
-		// smart playlists
-		if self.smart_info.is_some() {
-			return false;
-		};
 		match self.distinguished_kind {
 			None | Some(1) => return true,
 			Some(_) => return false, // ignore special iTunes playlists
@@ -497,83 +540,341 @@ struct XmlPlaylistInfo {
 	child_indexes: Vec<usize>,
 }
 
-/// Returns id of the imported playlist
-fn import_playlist(
-	infos: &Vec<XmlPlaylistInfo>,
-	i: usize,
-	library: &mut Library,
+/// Returns `None` if it's not a smart playlist, or if the smart playlist data
+/// is invalid, in which case it should be imported as a regular playlist
+fn import_smart_playlist(
+	xml_playlist: &XmlPlaylist,
+	id: &str,
 	start_time: i64,
 	errors: &mut Vec<String>,
-	xml_track_id_map: &HashMap<String, String>,
-) -> String {
-	let tracklist;
-	let xml_playlist = &infos[i].xml_playlist;
-	let id = library.generate_id();
-
-	if xml_playlist.folder == Some(true) {
-		let folder = TrackList::Folder(Folder {
-			id: id.clone(),
-			name: xml_playlist.name.clone(),
-			description: xml_playlist.description.clone(),
-			liked: xml_playlist.loved.unwrap_or_default(),
-			disliked: xml_playlist.disliked.unwrap_or_default(),
-			importedFrom: Some("itunes".to_string()),
-			originalId: Some(xml_playlist.playlist_persistent_id.clone()),
-			dateImported: Some(start_time),
-			dateCreated: None,
-			children: infos[i]
-				.child_indexes
-				.iter()
-				.map(|child_i| {
-					return import_playlist(
-						&infos,
-						*child_i,
-						library,
-						start_time,
-						errors,
-						xml_track_id_map,
-					);
-				})
-				.collect(),
-		});
-		// immediately insert into library so new generated ids are unique
-		library.trackLists.insert(id.clone(), folder);
-	} else {
-		let mut track_ids = Vec::new();
-		for playlist_item in xml_playlist.playlist_items.as_ref().unwrap() {
-			let track_id = xml_track_id_map.get(&playlist_item.track_id.to_string());
-			match track_id {
-				Some(track_id) => track_ids.push(track_id.clone()),
-				None => errors.push(format!(
-					"Track with id {} not found in playlist {}",
-					playlist_item.track_id, xml_playlist.name
-				)),
+) -> Option<SmartPlaylist> {
+	let info = xml_playlist.smart_info.as_ref()?.as_data()?;
+	let criteria = match &xml_playlist.smart_criteria {
+		Some(criteria) => criteria.as_data()?,
+		None => &[],
+	};
+	let mut rule_errors = Vec::new();
+	let result = translate_smart_playlist(info, criteria, &mut rule_errors);
+	for rule_error in rule_errors {
+		errors.push(format!(
+			"Smart playlist \"{}\": {rule_error}",
+			xml_playlist.name
+		));
+	}
+	let rules = match result {
+		Ok(rules) => rules,
+		Err(e) => {
+			errors.push(format!(
+				"Smart playlist \"{}\" was imported as a regular playlist: {e}",
+				xml_playlist.name
+			));
+			return None;
+		}
+	};
+	Some(SmartPlaylist {
+		id: id.to_string(),
+		name: xml_playlist.name.clone(),
+		description: xml_playlist.description.clone(),
+		liked: xml_playlist.loved.unwrap_or_default(),
+		disliked: xml_playlist.disliked.unwrap_or_default(),
+		importedFrom: Some("itunes".to_string()),
+		originalId: Some(xml_playlist.playlist_persistent_id.clone()),
+		dateImported: Some(start_time),
+		dateCreated: None,
+		rules,
+	})
+}
+
+struct PlaylistImporter<'a> {
+	infos: &'a Vec<XmlPlaylistInfo>,
+	library: &'a mut Library,
+	start_time: i64,
+	errors: &'a mut Vec<String>,
+	xml_track_id_map: &'a HashMap<String, String>,
+	/// iTunes Persistent ID -> Ferrum ID of previously imported playlists
+	existing_ids: HashMap<String, TrackListID>,
+	updated_count: i64,
+	/// Depth, ID and whether it's new, for every imported playlist in tree order
+	imported: Vec<(u32, TrackListID, bool)>,
+}
+impl PlaylistImporter<'_> {
+	/// Returns id of the imported playlist, and whether it's new. Previously
+	/// imported playlists are updated in place.
+	fn import_playlist(&mut self, i: usize, depth: u32) -> (TrackListID, bool) {
+		let infos = self.infos;
+		let xml_playlist = &infos[i].xml_playlist;
+		let is_folder = xml_playlist.folder == Some(true);
+		let existing_id = self
+			.existing_ids
+			.get(&xml_playlist.playlist_persistent_id)
+			.filter(|id| match self.library.get_tracklist(id) {
+				Ok(TrackList::Folder(_)) => is_folder,
+				Ok(TrackList::Playlist(_) | TrackList::Smart(_)) => !is_folder,
+				_ => false,
+			})
+			.cloned();
+		let id = match &existing_id {
+			Some(id) => id.clone(),
+			None => self.library.generate_id(),
+		};
+		self.imported
+			.push((depth, id.clone(), existing_id.is_none()));
+
+		if is_folder {
+			let mut new_children = Vec::new();
+			for child_i in &infos[i].child_indexes {
+				let (child_id, is_new) = self.import_playlist(*child_i, depth + 1);
+				if is_new {
+					new_children.push(child_id);
+				}
+			}
+			match self.library.trackLists.get_mut(&id) {
+				// Keep children that were added or moved in Ferrum
+				Some(TrackList::Folder(folder)) => {
+					folder.name = xml_playlist.name.clone();
+					folder.description = xml_playlist.description.clone();
+					folder.liked = xml_playlist.loved.unwrap_or_default();
+					folder.disliked = xml_playlist.disliked.unwrap_or_default();
+					folder.dateImported = Some(self.start_time);
+					folder.children.extend(new_children);
+				}
+				_ => {
+					let folder = TrackList::Folder(Folder {
+						id: id.clone(),
+						name: xml_playlist.name.clone(),
+						description: xml_playlist.description.clone(),
+						liked: xml_playlist.loved.unwrap_or_default(),
+						disliked: xml_playlist.disliked.unwrap_or_default(),
+						importedFrom: Some("itunes".to_string()),
+						originalId: Some(xml_playlist.playlist_persistent_id.clone()),
+						dateImported: Some(self.start_time),
+						dateCreated: None,
+						children: new_children,
+					});
+					// immediately insert into library so new generated ids are unique
+					self.library.trackLists.insert(id.clone(), folder);
+				}
 			}
+		} else if let Some(smart) =
+			import_smart_playlist(xml_playlist, &id, self.start_time, self.errors)
+		{
+			// immediately insert into library so new generated ids are unique
+			self.library
+				.trackLists
+				.insert(id.clone(), TrackList::Smart(smart));
+		} else {
+			let mut track_ids = Vec::new();
+			// Smart playlists without tracks have no items
+			for playlist_item in xml_playlist.playlist_items.iter().flatten() {
+				let track_id = self
+					.xml_track_id_map
+					.get(&playlist_item.track_id.to_string());
+				match track_id {
+					Some(track_id) => track_ids.push(track_id.clone()),
+					None => self.errors.push(format!(
+						"Track with id {} not found in playlist {}",
+						playlist_item.track_id, xml_playlist.name
+					)),
+				}
+			}
+
+			let tracklist = TrackList::Playlist(Playlist {
+				id: id.clone(),
+				name: xml_playlist.name.clone(),
+				description: xml_playlist.description.clone(),
+				liked: xml_playlist.loved.unwrap_or_default(),
+				disliked: xml_playlist.disliked.unwrap_or_default(),
+				importedFrom: Some("itunes".to_string()),
+				originalId: Some(xml_playlist.playlist_persistent_id.clone()),
+				dateImported: Some(self.start_time),
+				dateCreated: None,
+				tracks: new_item_ids_from_track_ids(&track_ids),
+			});
+			// immediately insert into library so new generated ids are unique
+			self.library.trackLists.insert(id.clone(), tracklist);
 		}
+		if existing_id.is_some() {
+			self.updated_count += 1;
+		}
+		(id, existing_id.is_none())
+	}
+}
 
-		tracklist = TrackList::Playlist(Playlist {
-			id: id.clone(),
-			name: xml_playlist.name.clone(),
-			description: xml_playlist.description.clone(),
-			liked: xml_playlist.loved.unwrap_or_default(),
-			disliked: xml_playlist.disliked.unwrap_or_default(),
-			importedFrom: Some("itunes".to_string()),
-			originalId: Some(xml_playlist.playlist_persistent_id.clone()),
-			dateImported: Some(start_time),
-			dateCreated: None,
-			tracks: new_item_ids_from_track_ids(&track_ids),
-		});
-		// immediately insert into library so new generated ids are unique
-		library.trackLists.insert(id.clone(), tracklist);
+/// Ferrum ID of every tracklist imported from iTunes, by iTunes Persistent ID
+fn get_imported_tracklist_ids(library: &Library) -> HashMap<String, TrackListID> {
+	let mut ids = HashMap::new();
+	for (id, tracklist) in &library.trackLists {
+		let (imported_from, original_id) = match tracklist {
+			TrackList::Playlist(playlist) => (&playlist.importedFrom, &playlist.originalId),
+			TrackList::Smart(smart) => (&smart.importedFrom, &smart.originalId),
+			TrackList::Folder(folder) => (&folder.importedFrom, &folder.originalId),
+			TrackList::Special(_) => continue,
+		};
+		if let (Some("itunes"), Some(original_id)) = (imported_from.as_deref(), original_id) {
+			ids.insert(original_id.clone(), id.clone());
+		}
+	}
+	ids
+}
+
+/// Adds plays or skips that were made in iTunes since the track was last
+/// synced, as an imported count. Returns the number of added plays/skips.
+fn add_new_itunes_count(
+	count: &mut Option<u32>,
+	dates: &Option<Vec<i64>>,
+	imported: &mut Option<Vec<CountObject>>,
+	itunes_count: Option<u32>,
+	date_imported: Option<i64>,
+	start_time: i64,
+) -> u32 {
+	let itunes_counts: Vec<&CountObject> = imported
+		.iter()
+		.flatten()
+		.filter(|c| c.is_from_itunes())
+		.collect();
+	// The first import adds an iTunes count along with at most one date, the
+	// last play. Other dates from before then may come from play history
+	// imports, so they are not from iTunes.
+	let has_imported_date = match (dates, date_imported) {
+		(Some(dates), Some(date_imported)) => dates.iter().any(|date| *date < date_imported),
+		_ => false,
+	};
+	let imported_dates_count = i64::from(has_imported_date && !itunes_counts.is_empty());
+	let imported_count: i64 = itunes_counts.iter().map(|c| c.count).sum();
+	let known_count = imported_count + imported_dates_count;
+	let new_count = i64::from(itunes_count.unwrap_or(0)) - known_count;
+	if new_count <= 0 {
+		return 0;
+	}
+	let last_sync = itunes_counts
+		.iter()
+		.map(|c| c.toDate)
+		.chain(date_imported)
+		.max()
+		.unwrap_or(start_time);
+	imported.get_or_insert_with(Vec::new).push(CountObject {
+		count: new_count,
+		fromDate: last_sync,
+		toDate: start_time,
+		source: Some("itunes".to_string()),
+	});
+	let new_count = new_count as u32;
+	*count = Some(count.unwrap_or(0) + new_count);
+	new_count
+}
+
+/// Merges iTunes data into a previously imported track. Ratings and likes
+/// are only taken from iTunes if they aren't set in Ferrum. Returns the number
+/// of added plays and skips, and whether anything changed.
+fn merge_track(track: &mut Track, xml_track: &XmlTrack, start_time: i64) -> (u32, u32, bool) {
+	let added_plays = add_new_itunes_count(
+		&mut track.playCount,
+		&track.plays,
+		&mut track.playsImported,
+		xml_track.play_count,
+		track.dateImported,
+		start_time,
+	);
+	let added_skips = add_new_itunes_count(
+		&mut track.skipCount,
+		&track.skips,
+		&mut track.skipsImported,
+		xml_track.skip_count,
+		track.dateImported,
+		start_time,
+	);
+	let mut changed = added_plays > 0 || added_skips > 0;
+	if track.rating.is_none() && xml_track.rating.is_some() {
+		track.rating = xml_track.rating;
+		changed = true;
+	}
+	if track.albumRating.is_none()
+		&& xml_track.album_rating_computed != Some(true)
+		&& let Some(album_rating) = xml_track.album_rating
+	{
+		track.albumRating = Some(album_rating);
+		changed = true;
+	}
+	if track.albumLiked.is_none() && xml_track.album_loved == Some(true) {
+		track.albumLiked = Some(true);
+		changed = true;
+	}
+	if track.liked.is_none() && track.disliked.is_none() {
+		let liked = keep_true(xml_track.loved);
+		let disliked = keep_true(xml_track.disliked);
+		if liked.is_some() || disliked.is_some() {
+			track.liked = liked;
+			track.disliked = disliked;
+			changed = true;
+		}
 	}
-	id
+	(added_plays, added_skips, changed)
 }
 
 #[napi(object)]
 pub struct ImportStatus {
 	pub errors: Vec<String>,
+	/// New tracks
 	pub tracks_count: i64,
+	/// New playlists and folders
 	pub playlists_count: i64,
+	/// Previously imported tracks that got new plays, skips, ratings or likes
+	pub updated_tracks_count: i64,
+	/// Previously imported playlists and folders that were updated
+	pub updated_playlists_count: i64,
+	pub added_plays_count: i64,
+	pub added_skips_count: i64,
+}
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct TrackPreview {
+	pub name: String,
+	pub artist: String,
+	/// Path of the iTunes file
+	pub source_path: String,
+	/// Path of the file in Ferrum. Same as `source_path` when referencing files
+	pub destination_path: String,
+	pub size: i64,
+}
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct SkippedTrackPreview {
+	pub name: String,
+	pub artist: String,
+	/// iTunes track location URL
+	pub location: Option<String>,
+	pub reason: String,
+}
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct PlaylistPreview {
+	pub name: String,
+	/// "playlist", "smart" or "folder"
+	pub kind: String,
+	/// 0 for playlists in the root
+	pub depth: u32,
+	/// False if it replaces a previously imported playlist
+	pub is_new: bool,
+	/// Not set for folders
+	pub tracks_count: Option<i64>,
+}
+
+/// What `finish` will do
+#[napi(object)]
+#[derive(Clone)]
+pub struct ItunesImportPreview {
+	/// New tracks
+	pub tracks: Vec<TrackPreview>,
+	pub skipped_tracks: Vec<SkippedTrackPreview>,
+	/// New and updated playlists, in tree order
+	pub playlists: Vec<PlaylistPreview>,
+	/// Bytes that will be copied or moved into the tracks folder
+	pub bytes_to_copy: i64,
+	/// Free space where the tracks folder is, if known
+	pub free_space: Option<i64>,
 }
 
 #[napi]
@@ -582,29 +883,43 @@ pub struct ItunesImport {
 	/// iTunes path -> Ferrum file
 	itunes_track_paths: Mutex<HashMap<PathBuf, String>>,
 	paths: Paths,
+	mode: ImportMode,
+	/// Update tracks and playlists that were previously imported from iTunes
+	/// instead of importing them again
+	resync: bool,
+	preview: Mutex<Option<ItunesImportPreview>>,
 }
 #[napi]
 impl ItunesImport {
 	#[napi(factory)]
-	pub fn new(env: Env) -> Self {
+	pub fn new(mode: Option<String>, resync: Option<bool>, env: Env) -> napi::Result<Self> {
 		let data = get_data(&env);
-		Self {
-			new_library: Some(data.library.clone()).into(),
-			itunes_track_paths: HashMap::new().into(),
-			paths: data.paths.clone(),
-		}
+		let mode = ImportMode::from_option(mode)?;
+		let resync = resync.unwrap_or(false);
+		Ok(Self::with_library(
+			data.library.clone(),
+			data.paths.clone(),
+			mode,
+			resync,
+		))
 	}
 	#[napi]
 	pub async fn start(&self, path: String) -> napi::Result<ImportStatus> {
 		Ok(import_itunes(self, path).await?)
 	}
+	/// Details of what will be imported. Available after `start`
+	#[napi]
+	pub fn preview(&self) -> napi::Result<ItunesImportPreview> {
+		let preview = self.preview.lock().unwrap();
+		Ok(preview.clone().context("Not started")?)
+	}
 	#[napi]
 	pub fn finish(&mut self, env: Env) -> napi::Result<()> {
 		let data = get_data(&env);
 		let itunes_track_paths = &mut *self.itunes_track_paths.lock().unwrap();
 		for (itunes_path, ferrum_file) in itunes_track_paths {
 			let new_path = data.paths.get_track_file_path(ferrum_file);
-			fs::copy(itunes_path, new_path).context("Error copying file")?;
+			self.mode.transfer_file(itunes_path, &new_path)?;
 		}
 		let new_library = &mut self.new_library.lock().unwrap();
 		data.library = new_library.take().context("Not initialized")?;
@@ -612,9 +927,29 @@ impl ItunesImport {
 	}
 }
 
-async fn import_itunes(itunes_import: &ItunesImport, path: String) -> Result<ImportStatus> {
+impl ItunesImport {
+	/// Imports into a copy of `library`
+	pub fn with_library(library: Library, paths: Paths, mode: ImportMode, resync: bool) -> Self {
+		Self {
+			new_library: Some(library).into(),
+			itunes_track_paths: HashMap::new().into(),
+			paths,
+			mode,
+			resync,
+			preview: None.into(),
+		}
+	}
+	/// The library with the imported tracks and playlists, without
+	/// transferring any files
+	#[cfg(test)]
+	pub fn take_library(&self) -> Option<Library> {
+		self.new_library.lock().unwrap().take()
+	}
+}
+
+pub async fn import_itunes(itunes_import: &ItunesImport, path: String) -> Result<ImportStatus> {
 	let new_library_lock = &mut *itunes_import.new_library.lock().unwrap();
-	let mut library = match new_library_lock {
+	let library = match new_library_lock {
 		Some(library) => library,
 		None => bail!("Not initialized"),
 	};
@@ -637,6 +972,23 @@ async fn import_itunes(itunes_import: &ItunesImport, path: String) -> Result<Imp
 	// iTunes ID -> Ferrum ID
 	let mut xml_track_id_map = HashMap::<String, String>::new();
 
+	// iTunes Persistent ID -> Ferrum ID of previously imported tracks
+	let mut existing_track_ids = HashMap::<String, String>::new();
+	if itunes_import.resync {
+		for (id, track) in library.get_tracks() {
+			if let (Some("itunes"), Some(original_id)) =
+				(track.importedFrom.as_deref(), &track.originalId)
+			{
+				existing_track_ids.insert(original_id.clone(), id.clone());
+			}
+		}
+	}
+	let mut track_previews = Vec::new();
+	let mut skipped_track_previews = Vec::new();
+	let mut updated_tracks_count = 0;
+	let mut added_plays_count = 0;
+	let mut added_skips_count = 0;
+
 	// We import the tracks that are in the "Music" playlist since xml.tracks
 	// contains podcasts, etc.
 	let xml_music_playlist = XmlLibraryProps::get_music_playlist(&xml.playlists)?;
@@ -651,6 +1003,18 @@ async fn import_itunes(itunes_import: &ItunesImport, path: String) -> Result<Imp
 			.with_context(|| format!("Track with id {} not found", playlist_item.track_id))?;
 		let artist_title = xml_track.artist_title();
 
+		if let Some(track_id) = existing_track_ids.get(&xml_track.persistent_id) {
+			let track = library.get_track_mut(track_id)?;
+			let (added_plays, added_skips, changed) = merge_track(track, &xml_track, start_time);
+			added_plays_count += i64::from(added_plays);
+			added_skips_count += i64::from(added_skips);
+			if changed {
+				updated_tracks_count += 1;
+			}
+			xml_track_id_map.insert(xml_id, track_id.clone());
+			continue;
+		}
+
 		if matches!(xml_track.name.as_deref(), Some("") | None) {
 			errors.push(format!("Missing track name: {artist_title}"));
 		}
@@ -658,11 +1022,29 @@ async fn import_itunes(itunes_import: &ItunesImport, path: String) -> Result<Imp
 			errors.push(format!("Missing track artist: {artist_title}"));
 		}
 
-		match parse_track(xml_track, start_time, &itunes_import.paths) {
+		let name = xml_track.name.clone().unwrap_or_default();
+		let artist = xml_track.artist.clone().unwrap_or_default();
+		let location = xml_track.location.clone();
+		match parse_track(
+			xml_track,
+			start_time,
+			&itunes_import.paths,
+			itunes_import.mode,
+		) {
 			Ok((xml_track_path, track)) => {
+				let destination_path = itunes_import.paths.get_track_file_path(&track.file);
+				track_previews.push(TrackPreview {
+					name,
+					artist,
+					source_path: xml_track_path.to_string_lossy().into_owned(),
+					destination_path: destination_path.to_string_lossy().into_owned(),
+					size: track.size,
+				});
 				let generated_id = library.generate_id();
 				// immediately insert into library so new generated ids are unique
-				itunes_track_paths.insert(xml_track_path, track.file.clone());
+				if itunes_import.mode != ImportMode::Reference {
+					itunes_track_paths.insert(xml_track_path, track.file.clone());
+				}
 				library.insert_track(generated_id.clone(), track);
 				if xml_track_id_map.contains_key(&xml_id) {
 					errors.push(format!("Duplicate track ids \"{}\": artist_title", xml_id));
@@ -671,6 +1053,12 @@ async fn import_itunes(itunes_import: &ItunesImport, path: String) -> Result<Imp
 			}
 			Err(e) => {
 				errors.push(format!("[{artist_title}] Skipped track: {e}"));
+				skipped_track_previews.push(SkippedTrackPreview {
+					name,
+					artist,
+					location,
+					reason: e.to_string(),
+				});
 			}
 		};
 	}
@@ -734,24 +1122,76 @@ async fn import_itunes(itunes_import: &ItunesImport, path: String) -> Result<Imp
 		(xml_playlist_infos, root_child_indexes)
 	};
 
+	let existing_tracklist_ids = match itunes_import.resync {
+		true => get_imported_tracklist_ids(library),
+		false => HashMap::new(),
+	};
+	let mut playlist_importer = PlaylistImporter {
+		infos: &xml_playlist_infos,
+		library,
+		start_time,
+		errors: &mut errors,
+		xml_track_id_map: &xml_track_id_map,
+		existing_ids: existing_tracklist_ids,
+		updated_count: 0,
+		imported: Vec::new(),
+	};
 	// recursively import playlists
 	for i in root_child_indexes {
-		let playlist_id = import_playlist(
-			&xml_playlist_infos,
-			i,
-			&mut library,
-			start_time,
-			&mut errors,
-			&xml_track_id_map,
-		);
-		let root = library.get_root_tracklist_mut()?;
-		root.children.push(playlist_id);
+		let (playlist_id, is_new) = playlist_importer.import_playlist(i, 0);
+		if is_new {
+			let root = playlist_importer.library.get_root_tracklist_mut()?;
+			root.children.push(playlist_id);
+		}
+	}
+	let updated_playlists_count = playlist_importer.updated_count;
+	let imported_playlists = playlist_importer.imported;
+
+	let mut playlist_previews = Vec::new();
+	for (depth, id, is_new) in imported_playlists {
+		let tracklist = library.get_tracklist(&id)?;
+		let (name, tracks_count) = match tracklist {
+			TrackList::Folder(folder) => (folder.name.clone(), None),
+			TrackList::Playlist(playlist) => {
+				(playlist.name.clone(), Some(playlist.tracks.len() as i64))
+			}
+			TrackList::Smart(smart) => {
+				let item_ids = get_tracklist_item_ids(library, &id)?;
+				(smart.name.clone(), Some(item_ids.len() as i64))
+			}
+			TrackList::Special(_) => bail!("Unexpected special playlist"),
+		};
+		playlist_previews.push(PlaylistPreview {
+			name,
+			kind: tracklist.kind().to_string(),
+			depth,
+			is_new,
+			tracks_count,
+		});
 	}
+	let bytes_to_copy = match itunes_import.mode {
+		ImportMode::Copy | ImportMode::Move => track_previews.iter().map(|t| t.size).sum(),
+		ImportMode::Hardlink | ImportMode::Reference => 0,
+	};
+	let free_space = fs2::available_space(&itunes_import.paths.tracks_dir)
+		.ok()
+		.map(|bytes| bytes as i64);
+	*itunes_import.preview.lock().unwrap() = Some(ItunesImportPreview {
+		tracks: track_previews,
+		skipped_tracks: skipped_track_previews,
+		playlists: playlist_previews,
+		bytes_to_copy,
+		free_space,
+	});
 
 	Ok(ImportStatus {
 		errors,
 		tracks_count: (library.get_tracks().len() - original_tracks_count) as i64,
 		playlists_count: (library.trackLists.len() - original_tracklists_count) as i64,
+		updated_tracks_count,
+		updated_playlists_count,
+		added_plays_count,
+		added_skips_count,
 	})
 }
 
@@ -760,3 +1200,52 @@ async fn import_itunes_test() {
 	let library_xml_path = "src-native/tests/Library.xml".to_string();
 	let _xml: XmlLibrary = plist::from_file(library_xml_path).unwrap();
 }
+
+#[test]
+fn add_new_itunes_count_test() {
+	let date_imported = 1_000_000;
+	let itunes_count = CountObject {
+		count: 4,
+		fromDate: 0,
+		toDate: date_imported,
+		source: Some("itunes".to_string()),
+	};
+	let rhythmbox_count = CountObject {
+		count: 10,
+		fromDate: 0,
+		toDate: 500_000,
+		source: Some("rhythmbox".to_string()),
+	};
+	// The last iTunes play, plus two plays from a scrobble log import
+	let dates = Some(vec![100, 200, 900_000]);
+	let mut imported = Some(vec![itunes_count, rhythmbox_count]);
+	let mut count = Some(17);
+
+	// 4 imported + 1 dated play were known, so 2 plays are new
+	let added = add_new_itunes_count(
+		&mut count,
+		&dates,
+		&mut imported,
+		Some(7),
+		Some(date_imported),
+		2_000_000,
+	);
+	assert_eq!(added, 2);
+	assert_eq!(count, Some(19));
+	let new_count = imported.as_ref().unwrap().last().unwrap();
+	assert_eq!(new_count.count, 2);
+	assert_eq!(new_count.fromDate, date_imported);
+	assert_eq!(new_count.toDate, 2_000_000);
+
+	// Syncing again adds nothing
+	let added = add_new_itunes_count(
+		&mut count,
+		&dates,
+		&mut imported,
+		Some(7),
+		Some(date_imported),
+		3_000_000,
+	);
+	assert_eq!(added, 0);
+	assert_eq!(count, Some(19));
+}
@@ -0,0 +1,398 @@
+use crate::filter::format_keyword;
+use crate::library_types::SmartPlaylistRules;
+use anyhow::{Context, Result, bail};
+
+// Smart Info:
+// - `0`: Live updating
+// - `1`: Match rules
+// - `2`: Limit enabled
+// - `3`: Limit unit
+// - `4..8`: Limit selection method
+// - `8..12`: Limit value
+// - `12`: Match only checked items
+// - `13`: Reverse the limit selection method
+//
+// Smart Criteria starts with a 136 byte header (`SLst`, version, rule
+// count, conjunction at `12..16`). Each rule is a 56 byte header (field at
+// `0..4`, operator at `4..8`, data length at `52..56`) followed by the data,
+// which is UTF-16BE text for text fields and a 68 byte struct for number
+// fields, starting with the value as an i64. All numbers are big-endian.
+const CRITERIA_HEADER_LEN: usize = 136;
+const RULE_HEADER_LEN: usize = 56;
+
+const OP_IS: u32 = 0x0000_0001;
+const OP_IS_NOT: u32 = 0x0200_0001;
+const OP_GREATER_THAN: u32 = 0x0000_0010;
+const OP_LESS_THAN: u32 = 0x0000_0040;
+const OP_IN_RANGE: u32 = 0x0000_0100;
+const OP_IN_THE_LAST: u32 = 0x0000_0200;
+const OP_NOT_IN_THE_LAST: u32 = 0x0200_0200;
+const OP_BINARY_AND: u32 = 0x0000_0400;
+const OP_TEXT_IS: u32 = 0x0100_0001;
+const OP_TEXT_CONTAINS: u32 = 0x0100_0002;
+const OP_TEXT_STARTS_WITH: u32 = 0x0100_0004;
+const OP_TEXT_ENDS_WITH: u32 = 0x0100_0008;
+const OP_TEXT_IS_NOT: u32 = 0x0300_0001;
+const OP_TEXT_DOES_NOT_CONTAIN: u32 = 0x0300_0002;
+
+const FIELD_MEDIA_KIND: u32 = 0x3c;
+const MEDIA_KIND_MUSIC: i64 = 1;
+
+enum FieldKind {
+	Text,
+	Number,
+	Other,
+}
+
+struct ItunesField {
+	name: &'static str,
+	kind: FieldKind,
+	/// Filter query field, if the field can be filtered
+	keyword: Option<&'static str>,
+}
+
+fn get_field(code: u32) -> ItunesField {
+	let (name, kind, keyword) = match code {
+		0x02 => ("Name", FieldKind::Text, Some("title")),
+		0x03 => ("Album", FieldKind::Text, Some("album")),
+		0x04 => ("Artist", FieldKind::Text, Some("artist")),
+		0x05 => ("Bit Rate", FieldKind::Number, None),
+		0x06 => ("Sample Rate", FieldKind::Number, None),
+		0x07 => ("Year", FieldKind::Number, Some("year")),
+		0x08 => ("Genre", FieldKind::Text, Some("genre")),
+		0x09 => ("Kind", FieldKind::Text, None),
+		0x0a => ("Date Modified", FieldKind::Other, None),
+		0x0b => ("Track Number", FieldKind::Number, None),
+		0x0c => ("Size", FieldKind::Number, None),
+		0x0d => ("Time", FieldKind::Number, None),
+		0x0e => ("Comments", FieldKind::Text, Some("comment")),
+		0x10 => ("Date Added", FieldKind::Other, None),
+		0x12 => ("Composer", FieldKind::Text, Some("composer")),
+		0x16 => ("Plays", FieldKind::Number, Some("playcount")),
+		0x17 => ("Last Played", FieldKind::Other, None),
+		0x18 => ("Disc Number", FieldKind::Number, None),
+		0x19 => ("Rating", FieldKind::Number, None),
+		0x1f => ("Compilation", FieldKind::Other, None),
+		0x23 => ("BPM", FieldKind::Number, Some("bpm")),
+		0x27 => ("Grouping", FieldKind::Text, Some("grouping")),
+		0x28 => ("Playlist", FieldKind::Other, None),
+		FIELD_MEDIA_KIND => ("Media Kind", FieldKind::Other, None),
+		0x44 => ("Skips", FieldKind::Number, Some("skipcount")),
+		0x45 => ("Last Skipped", FieldKind::Other, None),
+		0x47 => ("Album Artist", FieldKind::Text, Some("albumartist")),
+		0x4e => ("Sort Name", FieldKind::Text, None),
+		0x4f => ("Sort Album", FieldKind::Text, None),
+		0x50 => ("Sort Artist", FieldKind::Text, None),
+		0x51 => ("Sort Album Artist", FieldKind::Text, None),
+		0x52 => ("Sort Composer", FieldKind::Text, None),
+		0x5a => ("Album Rating", FieldKind::Number, None),
+		0x9a => ("Love", FieldKind::Other, None),
+		_ => ("Unknown field", FieldKind::Other, None),
+	};
+	ItunesField {
+		name,
+		kind,
+		keyword,
+	}
+}
+
+fn describe_operator(op: u32) -> String {
+	let description = match op {
+		OP_IS | OP_TEXT_IS => "is",
+		OP_IS_NOT | OP_TEXT_IS_NOT => "is not",
+		OP_GREATER_THAN => "is greater than",
+		OP_LESS_THAN => "is less than",
+		OP_IN_RANGE => "is in the range",
+		OP_IN_THE_LAST => "is in the last",
+		OP_NOT_IN_THE_LAST => "is not in the last",
+		OP_BINARY_AND => "is",
+		OP_TEXT_CONTAINS => "contains",
+		OP_TEXT_STARTS_WITH => "starts with",
+		OP_TEXT_ENDS_WITH => "ends with",
+		OP_TEXT_DOES_NOT_CONTAIN => "does not contain",
+		_ => return format!("operator {op:#010x}"),
+	};
+	description.to_string()
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+	let slice = bytes
+		.get(offset..offset + 4)
+		.context("Unexpected end of data")?;
+	Ok(u32::from_be_bytes(slice.try_into()?))
+}
+
+fn read_i64(bytes: &[u8], offset: usize) -> Result<i64> {
+	let slice = bytes
+		.get(offset..offset + 8)
+		.context("Unexpected end of data")?;
+	Ok(i64::from_be_bytes(slice.try_into()?))
+}
+
+fn read_utf16_be(bytes: &[u8]) -> String {
+	let units: Vec<u16> = bytes
+		.chunks_exact(2)
+		.map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+		.collect();
+	String::from_utf16_lossy(&units)
+}
+
+struct ItunesRule {
+	field_code: u32,
+	op: u32,
+	data: Vec<u8>,
+}
+
+fn parse_criteria(criteria: &[u8]) -> Result<(bool, Vec<ItunesRule>)> {
+	if !criteria.starts_with(b"SLst") {
+		bail!("Invalid Smart Criteria");
+	}
+	let match_any = read_u32(criteria, 12)? == 1;
+	let mut rules = Vec::new();
+	let mut offset = CRITERIA_HEADER_LEN;
+	while offset + RULE_HEADER_LEN <= criteria.len() {
+		let data_len = read_u32(criteria, offset + 52)? as usize;
+		let data_start = offset + RULE_HEADER_LEN;
+		let data = criteria
+			.get(data_start..data_start + data_len)
+			.context("Invalid Smart Criteria rule length")?;
+		rules.push(ItunesRule {
+			field_code: read_u32(criteria, offset)?,
+			op: read_u32(criteria, offset + 4)?,
+			data: data.to_vec(),
+		});
+		offset = data_start + data_len;
+	}
+	Ok((match_any, rules))
+}
+
+/// Returns the filter query keyword for the rule, or `Ok(None)` if the rule
+/// can be ignored
+fn translate_rule(rule: &ItunesRule, errors: &mut Vec<String>) -> Result<Option<String>> {
+	let field = get_field(rule.field_code);
+	if rule.data.starts_with(b"SLst") {
+		bail!("Nested rules are not supported");
+	}
+	let description = format!("\"{} {}\"", field.name, describe_operator(rule.op));
+	match field.kind {
+		FieldKind::Text => {
+			let value = read_utf16_be(&rule.data);
+			let keyword = field
+				.keyword
+				.with_context(|| format!("Rule {description} is not supported"))?;
+			match rule.op {
+				OP_TEXT_CONTAINS => {}
+				// The filter query only supports "contains"
+				OP_TEXT_IS => {
+					errors.push(format!("Rule {description} was imported as \"contains\""));
+				}
+				_ => bail!("Rule {description} is not supported"),
+			}
+			let keyword = format_keyword(keyword, &value)
+				.with_context(|| format!("Rule {description} has an unsupported value"))?;
+			Ok(Some(keyword))
+		}
+		FieldKind::Number => {
+			let keyword = field
+				.keyword
+				.with_context(|| format!("Rule {description} is not supported"))?;
+			if rule.op != OP_IS {
+				bail!("Rule {description} is not supported");
+			}
+			let value = read_i64(&rule.data, 0)?;
+			Ok(Some(format!("{keyword}:{value}")))
+		}
+		// Only music is imported from iTunes, so this rule is redundant
+		FieldKind::Other
+			if rule.field_code == FIELD_MEDIA_KIND
+				&& rule.op == OP_BINARY_AND
+				&& read_i64(&rule.data, 0)? == MEDIA_KIND_MUSIC =>
+		{
+			Ok(None)
+		}
+		FieldKind::Other => bail!("Rule {description} is not supported"),
+	}
+}
+
+/// Returns the sort key and whether it's sorted descending
+fn translate_limit_selection(method: u32, reverse: bool) -> Result<(&'static str, bool)> {
+	let (sort_key, sort_desc) = match method {
+		0x05 => ("name", false),
+		0x06 => ("albumName", false),
+		0x07 => ("artist", false),
+		0x09 => ("genre", false),
+		0x15 => ("dateAdded", true),
+		0x19 => ("playCount", true),
+		0x1c => ("rating", true),
+		0x02 => bail!("Random selection is not supported"),
+		0x1a => bail!("Selection by last played is not supported"),
+		_ => bail!("Unknown selection method {method:#x}"),
+	};
+	Ok((sort_key, sort_desc != reverse))
+}
+
+/// Translates an iTunes smart playlist. Errors are returned for invalid data,
+/// and for rules or limits that cannot be translated, since leaving them out
+/// would match different tracks. Approximations are added to `errors`.
+pub fn translate_smart_playlist(
+	info: &[u8],
+	criteria: &[u8],
+	errors: &mut Vec<String>,
+) -> Result<SmartPlaylistRules> {
+	if info.len() < 14 {
+		bail!("Invalid Smart Info");
+	}
+	let mut rules = SmartPlaylistRules {
+		filterQuery: String::new(),
+		sortKey: "index".to_string(),
+		sortDesc: false,
+		limitCount: None,
+		limitDuration: None,
+	};
+
+	let match_rules = info[1] == 1;
+	if match_rules {
+		let (match_any, itunes_rules) = parse_criteria(criteria)?;
+		if match_any && itunes_rules.len() > 1 {
+			bail!("Matching any rule is not supported");
+		}
+		let mut keywords = Vec::new();
+		for itunes_rule in &itunes_rules {
+			if let Some(keyword) = translate_rule(itunes_rule, errors)? {
+				keywords.push(keyword);
+			}
+		}
+		rules.filterQuery = keywords.join(" ");
+	}
+
+	let limit_enabled = info[2] == 1;
+	if limit_enabled {
+		let limit_value = read_u32(info, 8)?;
+		match info[3] {
+			1 => rules.limitDuration = Some(f64::from(limit_value) * 60.0),
+			3 => rules.limitCount = Some(limit_value),
+			4 => rules.limitDuration = Some(f64::from(limit_value) * 3600.0),
+			2 | 5 => bail!("Limiting by size is not supported"),
+			unit => bail!("Unknown limit unit {unit}"),
+		}
+		let method = read_u32(info, 4)?;
+		let reverse = info[13] == 1;
+		let (sort_key, sort_desc) = translate_limit_selection(method, reverse)?;
+		rules.sortKey = sort_key.to_string();
+		rules.sortDesc = sort_desc;
+	}
+	if info[12] == 1 {
+		errors.push("Matching only checked items is not supported".into());
+	}
+	Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const FIELD_GENRE: u32 = 0x08;
+	const FIELD_PLAYS: u32 = 0x16;
+	const FIELD_DATE_ADDED: u32 = 0x10;
+
+	fn info(limit: Option<(u8, u32, u32)>) -> Vec<u8> {
+		let mut info = vec![0; 14];
+		info[1] = 1;
+		if let Some((unit, method, value)) = limit {
+			info[2] = 1;
+			info[3] = unit;
+			info[4..8].copy_from_slice(&method.to_be_bytes());
+			info[8..12].copy_from_slice(&value.to_be_bytes());
+		}
+		info
+	}
+
+	fn text_rule(field_code: u32, op: u32, value: &str) -> (u32, u32, Vec<u8>) {
+		let data = value.encode_utf16().flat_map(u16::to_be_bytes).collect();
+		(field_code, op, data)
+	}
+
+	fn number_rule(field_code: u32, op: u32, value: i64) -> (u32, u32, Vec<u8>) {
+		let mut data = vec![0; 68];
+		data[0..8].copy_from_slice(&value.to_be_bytes());
+		(field_code, op, data)
+	}
+
+	fn criteria(match_any: bool, rules: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+		let mut criteria = vec![0; CRITERIA_HEADER_LEN];
+		criteria[0..4].copy_from_slice(b"SLst");
+		criteria[12..16].copy_from_slice(&u32::from(match_any).to_be_bytes());
+		for (field_code, op, data) in rules {
+			let mut header = vec![0; RULE_HEADER_LEN];
+			header[0..4].copy_from_slice(&field_code.to_be_bytes());
+			header[4..8].copy_from_slice(&op.to_be_bytes());
+			header[52..56].copy_from_slice(&(data.len() as u32).to_be_bytes());
+			criteria.extend(header);
+			criteria.extend(data);
+		}
+		criteria
+	}
+
+	#[test]
+	fn translate_smart_playlist_test() {
+		let criteria = criteria(
+			false,
+			&[
+				text_rule(FIELD_GENRE, OP_TEXT_CONTAINS, "Hip Hop"),
+				number_rule(FIELD_PLAYS, OP_IS, 5),
+				number_rule(FIELD_MEDIA_KIND, OP_BINARY_AND, MEDIA_KIND_MUSIC),
+			],
+		);
+		let mut errors = Vec::new();
+		let rules =
+			translate_smart_playlist(&info(Some((3, 0x19, 25))), &criteria, &mut errors).unwrap();
+		assert_eq!(rules.filterQuery, "genre:\"Hip Hop\" playcount:5");
+		assert_eq!(rules.sortKey, "playCount");
+		assert!(rules.sortDesc);
+		assert_eq!(rules.limitCount, Some(25));
+		assert_eq!(rules.limitDuration, None);
+		assert!(errors.is_empty());
+	}
+
+	#[test]
+	fn translate_smart_playlist_approximation_test() {
+		let criteria = criteria(false, &[text_rule(FIELD_GENRE, OP_TEXT_IS, "Rock")]);
+		let mut errors = Vec::new();
+		let rules =
+			translate_smart_playlist(&info(Some((1, 0x07, 90))), &criteria, &mut errors).unwrap();
+		assert_eq!(rules.filterQuery, "genre:Rock");
+		assert_eq!(rules.sortKey, "artist");
+		assert_eq!(rules.limitDuration, Some(5400.0));
+		assert_eq!(errors.len(), 1);
+	}
+
+	#[test]
+	fn translate_smart_playlist_unsupported_test() {
+		let genre = text_rule(FIELD_GENRE, OP_TEXT_CONTAINS, "Rock");
+		let plays = number_rule(FIELD_PLAYS, OP_IS, 5);
+		let date_added = number_rule(FIELD_DATE_ADDED, OP_IN_THE_LAST, 7);
+		let mut errors = Vec::new();
+
+		// Matching any of several rules
+		let any = criteria(true, &[genre.clone(), plays.clone()]);
+		assert!(translate_smart_playlist(&info(None), &any, &mut errors).is_err());
+		let any_single = criteria(true, std::slice::from_ref(&genre));
+		assert!(translate_smart_playlist(&info(None), &any_single, &mut errors).is_ok());
+
+		// A rule that cannot be translated
+		let untranslatable = criteria(false, &[genre.clone(), date_added]);
+		assert!(translate_smart_playlist(&info(None), &untranslatable, &mut errors).is_err());
+		let greater_than = criteria(false, &[number_rule(FIELD_PLAYS, OP_GREATER_THAN, 5)]);
+		assert!(translate_smart_playlist(&info(None), &greater_than, &mut errors).is_err());
+
+		// Limits that cannot be translated
+		let valid = criteria(false, &[genre]);
+		let size_limit = info(Some((2, 0x05, 100)));
+		assert!(translate_smart_playlist(&size_limit, &valid, &mut errors).is_err());
+		let random = info(Some((3, 0x02, 100)));
+		assert!(translate_smart_playlist(&random, &valid, &mut errors).is_err());
+
+		assert!(errors.is_empty());
+		assert!(translate_smart_playlist(&info(None), b"XXXX", &mut errors).is_err());
+	}
+}
@@ -23,16 +23,35 @@ mod data;
 mod data_js;
 pub mod filter;
 #[cfg(feature = "napi-rs")]
+mod itunes_export;
+#[cfg(feature = "napi-rs")]
 mod itunes_import;
+#[cfg(feature = "napi-rs")]
+mod itunes_smart;
 pub mod library;
 pub mod library_types;
+#[cfg(feature = "napi-rs")]
+mod m3u_import;
+pub mod musical_key;
 pub mod page;
 #[cfg(feature = "napi-rs")]
+mod playlist_export;
+#[cfg(feature = "napi-rs")]
 pub mod playlists;
 #[cfg(feature = "napi-rs")]
+mod plays_export;
+#[cfg(feature = "napi-rs")]
+mod plays_import;
+#[cfg(feature = "napi-rs")]
 mod queue_state;
+#[cfg(feature = "napi-rs")]
+mod rhythmbox_import;
+#[cfg(feature = "napi-rs")]
+mod scrobble_queue;
 pub mod sort;
 #[cfg(feature = "napi-rs")]
+mod track_merge;
+#[cfg(feature = "napi-rs")]
 mod tracks;
 #[cfg(feature = "napi-rs")]
 mod view_options;
@@ -2,7 +2,12 @@
 use crate::data::Data;
 #[cfg(feature = "napi-rs")]
 use crate::data_js::get_data;
-use crate::library_types::{ItemId, Library, SpecialTrackListName, TrackList, VersionedLibrary};
+use crate::filter::filter;
+use crate::library_types::{
+	ItemId, Library, SmartPlaylistRules, SpecialTrackListName, TRACK_ID_MAP, TrackList,
+	VersionedLibrary,
+};
+use crate::sort::sort_item_ids;
 use anyhow::{Context, Result, bail};
 use linked_hash_map::LinkedHashMap;
 #[cfg(feature = "napi-rs")]
@@ -13,7 +18,7 @@ use std::fs::File;
 use std::fs::create_dir_all;
 use std::io::{ErrorKind, Read, Seek, SeekFrom};
 #[cfg(feature = "napi-rs")]
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 #[cfg(feature = "napi-rs")]
@@ -29,6 +34,7 @@ pub struct Paths {
 	pub local_data_dir: String,
 	pub view_options_file: String,
 	pub queue_file: String,
+	pub scrobble_queue_file: String,
 	pub logs_dir: String,
 }
 #[cfg(feature = "napi-rs")]
@@ -41,7 +47,13 @@ impl Paths {
 		// We do not create logs_dir, we create it lazily when a crash occurs
 		return Ok(());
 	}
+	/// `file` is a filename in `tracks_dir`, or an absolute path for tracks
+	/// that are referenced in place
 	pub fn get_track_file_path(&self, file: &str) -> PathBuf {
+		let path = Path::new(file);
+		if path.is_absolute() {
+			return path.to_path_buf();
+		}
 		PathBuf::from(&self.tracks_dir).join(file)
 	}
 }
@@ -168,6 +180,7 @@ pub fn get_track_field_type(field: &str) -> Result<TrackField> {
 		"rating" => TrackField::U8,
 		"year" => TrackField::I64,
 		"bpm" => TrackField::F64,
+		"key" => TrackField::String,
 		"comments" => TrackField::String,
 		"grouping" => TrackField::String,
 		"liked" => TrackField::Bool,
@@ -186,6 +199,11 @@ pub fn get_track_field_type(field: &str) -> Result<TrackField> {
 		"playCount" => TrackField::U32,
 		"skipCount" => TrackField::U32,
 		"volume" => TrackField::I8,
+		"startTime" => TrackField::F64,
+		"stopTime" => TrackField::F64,
+		"albumRating" => TrackField::U8,
+		"albumLiked" => TrackField::Bool,
+		"equalizer" => TrackField::String,
 		_ => bail!("Field type not found for {}", field),
 	};
 	return Ok(field);
@@ -228,5 +246,101 @@ pub fn get_tracklist_item_ids(library: &Library, playlist_id: &str) -> Result<Ve
 				Ok(item_ids)
 			}
 		},
+		TrackList::Smart(smart) => get_smart_playlist_item_ids(library, &smart.rules),
+	}
+}
+
+/// Computes the tracks of a smart playlist from all tracks in the library
+pub fn get_smart_playlist_item_ids(
+	library: &Library,
+	rules: &SmartPlaylistRules,
+) -> Result<Vec<ItemId>> {
+	let item_ids = library.get_track_item_ids().values().cloned().collect();
+	let mut item_ids = filter(item_ids, rules.filterQuery.clone(), library);
+	// Unlike track lists, where "index" is sorted newest first, ascending
+	// means the order the tracks were added in
+	if rules.sortKey == "index" {
+		if rules.sortDesc {
+			item_ids.reverse();
+		}
+	} else {
+		item_ids = sort_item_ids(item_ids, &rules.sortKey, rules.sortDesc, false, library)?;
+	}
+
+	if let Some(limit_count) = rules.limitCount {
+		item_ids.truncate(limit_count as usize);
+	}
+	if let Some(limit_duration) = rules.limitDuration {
+		let id_map = TRACK_ID_MAP.read().unwrap();
+		let mut total_duration = 0.0;
+		let mut count = 0;
+		for item_id in &item_ids {
+			let track = library.get_track(&id_map[*item_id as usize])?;
+			total_duration += track.duration;
+			if total_duration > limit_duration {
+				break;
+			}
+			count += 1;
+		}
+		item_ids.truncate(count);
+	}
+	Ok(item_ids)
+}
+
+/// Checks that the rules can be used to compute a smart playlist
+pub fn validate_smart_playlist_rules(rules: &SmartPlaylistRules) -> Result<()> {
+	if rules.sortKey != "index" {
+		get_track_field_type(&rules.sortKey)?;
+	}
+	if rules.limitDuration.is_some_and(|duration| duration < 0.0) {
+		bail!("Duration limit cannot be negative");
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::library_types::get_track_ids_from_item_ids;
+
+	fn smart_playlist_track_ids(library: &Library, sort_desc: bool) -> Vec<String> {
+		let rules = SmartPlaylistRules {
+			filterQuery: String::new(),
+			sortKey: "index".to_string(),
+			sortDesc: sort_desc,
+			limitCount: Some(2),
+			limitDuration: None,
+		};
+		let item_ids = get_smart_playlist_item_ids(library, &rules).unwrap();
+		get_track_ids_from_item_ids(&item_ids)
+	}
+
+	#[test]
+	fn smart_playlist_index_limit_test() {
+		let mut library = Library::new();
+		for i in 1..=4 {
+			let track = serde_json::from_value(json!({
+				"size": 0,
+				"duration": 200.0,
+				"bitrate": 0.0,
+				"sampleRate": 0.0,
+				"file": format!("{i}.mp3"),
+				"dateModified": i,
+				"dateAdded": i,
+				"name": format!("Track {i}"),
+				"artist": "Artist",
+			}))
+			.unwrap();
+			library.insert_track(format!("smart_index_{i}"), track);
+		}
+		// The first tracks that were added are kept
+		assert_eq!(
+			smart_playlist_track_ids(&library, false),
+			["smart_index_1", "smart_index_2"]
+		);
+		assert_eq!(
+			smart_playlist_track_ids(&library, true),
+			["smart_index_4", "smart_index_3"]
+		);
 	}
 }
@@ -12,6 +12,7 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::borrow::Cow;
 use std::collections::HashSet;
+use std::path::Path;
 use std::sync::RwLock;
 use std::time::Instant;
 
@@ -27,6 +28,8 @@ pub struct Library {
 	/// - timestamps aren't updated after pausing
 	pub v1PlayTime: Vec<PlayTime>,
 	pub playTime: Vec<PlayTime>,
+	/// Periods of listening history that have been imported
+	pub historyImports: Vec<HistoryImport>,
 	pub artists: Option<Vec<String>>,
 	pub genres: Option<Vec<String>>,
 }
@@ -37,6 +40,7 @@ impl Library {
 			trackLists: Cow::Borrowed(&self.trackLists),
 			v1PlayTime: Cow::Borrowed(&self.v1PlayTime),
 			playTime: Cow::Borrowed(&self.playTime),
+			historyImports: Cow::Borrowed(&self.historyImports),
 		})
 	}
 }
@@ -68,6 +72,8 @@ pub struct V2Library<'a> {
 	/// - timestamps aren't updated after pausing
 	pub v1PlayTime: Cow<'a, Vec<PlayTime>>,
 	pub playTime: Cow<'a, Vec<PlayTime>>,
+	#[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+	pub historyImports: Cow<'a, Vec<HistoryImport>>,
 }
 impl<'a> V2Library<'a> {
 	pub fn init_libary(self) -> Library {
@@ -77,6 +83,7 @@ impl<'a> V2Library<'a> {
 			trackLists: self.trackLists.into_owned(),
 			v1PlayTime: self.playTime.into_owned(),
 			playTime: Vec::new(),
+			historyImports: self.historyImports.into_owned(),
 			artists: None,
 			genres: None,
 		};
@@ -101,6 +108,7 @@ impl V1Library {
 			trackLists: Cow::Owned(self.trackLists),
 			v1PlayTime: Cow::Owned(self.playTime),
 			playTime: Cow::Owned(Vec::new()),
+			historyImports: Cow::Owned(Vec::new()),
 		}
 	}
 }
@@ -121,6 +129,7 @@ impl Library {
 			trackLists: track_lists,
 			v1PlayTime: Vec::new(),
 			playTime: Vec::new(),
+			historyImports: Vec::new(),
 			artists: None,
 			genres: None,
 		}
@@ -141,13 +150,22 @@ impl Library {
 		track_id_map.push(id.clone());
 		self.track_item_ids.insert(id, item_id);
 	}
+	/// Removes a track without removing it from playlists or deleting its file
+	pub fn remove_track(&mut self, id: &TrackID) -> Result<Track> {
+		let track = self.tracks.remove(id).context("Track ID not found")?;
+		self.track_item_ids.remove(id);
+		Ok(track)
+	}
 	#[cfg(feature = "napi-rs")]
 	pub fn delete_track_and_file(&mut self, id: &TrackID, paths: &Paths) -> Result<()> {
-		let file_path = {
+		let (file_path, is_referenced) = {
 			let track = self.get_track(id)?;
-			paths.get_track_file_path(&track.file)
+			(
+				paths.get_track_file_path(&track.file),
+				track.is_referenced(),
+			)
 		};
-		if !file_path.exists() {
+		if !is_referenced && !file_path.exists() {
 			bail!("File does not exist: {}", file_path.to_string_lossy());
 		}
 
@@ -158,7 +176,9 @@ impl Library {
 		self.track_item_ids
 			.remove(id)
 			.expect("Track ID not found when deleting (2)");
-		delete_file(&file_path)?;
+		if !is_referenced {
+			delete_file(&file_path)?;
+		}
 		Ok(())
 	}
 	pub fn generate_id(&self) -> String {
@@ -202,6 +222,25 @@ impl Library {
 			children: Vec::new(),
 		}
 	}
+	pub fn new_smart_playlist(
+		&self,
+		name: String,
+		description: Option<String>,
+		rules: SmartPlaylistRules,
+	) -> SmartPlaylist {
+		SmartPlaylist {
+			id: self.generate_id(),
+			name,
+			description,
+			liked: false,
+			disliked: false,
+			importedFrom: None,
+			originalId: None,
+			dateImported: None,
+			dateCreated: Some(get_now_timestamp()),
+			rules,
+		}
+	}
 	pub fn get_track(&self, id: &TrackID) -> Result<&Track> {
 		self.get_tracks()
 			.get(id)
@@ -230,7 +269,7 @@ impl Library {
 	pub fn get_parent_id(&self, id: &str) -> Option<String> {
 		for (parent_id, tracklist) in &self.trackLists {
 			let children = match tracklist {
-				TrackList::Playlist(_) => continue,
+				TrackList::Playlist(_) | TrackList::Smart(_) => continue,
 				TrackList::Folder(list) => &list.children,
 				TrackList::Special(list) => &list.children,
 			};
@@ -291,8 +330,12 @@ pub type PlayTime = (TrackID, MsSinceUnixEpoch, i64);
 pub struct Track {
 	pub size: i64,
 	pub duration: f64,
+	/// 0 if unknown
 	pub bitrate: f64,
+	/// 0 if unknown
 	pub sampleRate: f64,
+	/// Filename in the tracks folder, or an absolute path if the file is
+	/// referenced in place
 	pub file: String,
 	pub dateModified: MsSinceUnixEpoch,
 	pub dateAdded: MsSinceUnixEpoch,
@@ -320,6 +363,9 @@ pub struct Track {
 	pub year: Option<i64>,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub bpm: Option<f64>,
+	/// Musical key, like "Am" or "F#"
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub key: Option<String>,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub comments: Option<String>,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
@@ -365,8 +411,36 @@ pub struct Track {
 	/// -100 to 100
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub volume: Option<i8>,
+	/// Seconds into the file where playback starts
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub startTime: Option<f64>,
+	/// Seconds into the file where playback stops
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub stopTime: Option<f64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub albumRating: Option<PercentInteger>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub albumLiked: Option<bool>,
+	/// Equalizer preset name, like "Bass Booster"
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub equalizer: Option<String>,
+	/// Integrated loudness in LUFS
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub loudness: Option<f64>,
+	/// True peak, where 1.0 is full scale
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub peak: Option<f64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub albumLoudness: Option<f64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub albumPeak: Option<f64>,
 }
 impl Track {
+	/// Referenced files are outside of the tracks folder, so Ferrum doesn't
+	/// rename or delete them
+	pub fn is_referenced(&self) -> bool {
+		Path::new(&self.file).is_absolute()
+	}
 	pub fn has_album(&self) -> bool {
 		self.albumName.is_some() && self.albumArtist.is_some()
 	}
@@ -383,6 +457,26 @@ pub struct CountObject {
 	pub count: i64,
 	pub fromDate: MsSinceUnixEpoch,
 	pub toDate: MsSinceUnixEpoch,
+	/// For example "rhythmbox". Counts without a source are from iTunes.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub source: Option<String>,
+}
+impl CountObject {
+	pub fn is_from_itunes(&self) -> bool {
+		self.source
+			.as_deref()
+			.is_none_or(|source| source == "itunes")
+	}
+}
+
+/// Listening history from `source` between `fromDate` and `toDate` was
+/// imported, so it shouldn't be imported again
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryImport {
+	/// "spotify"
+	pub source: String,
+	pub fromDate: MsSinceUnixEpoch,
+	pub toDate: MsSinceUnixEpoch,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Type)]
@@ -394,6 +488,8 @@ pub enum TrackList {
 	Folder(Folder),
 	#[serde(rename = "special")]
 	Special(Special),
+	#[serde(rename = "smart")]
+	Smart(SmartPlaylist),
 }
 
 impl TrackList {
@@ -402,6 +498,7 @@ impl TrackList {
 			TrackList::Playlist(_) => "playlist",
 			TrackList::Folder(_) => "folder",
 			TrackList::Special(_) => "special",
+			TrackList::Smart(_) => "smart",
 		}
 	}
 	pub fn id(&self) -> &str {
@@ -409,6 +506,7 @@ impl TrackList {
 			TrackList::Playlist(list) => &list.id,
 			TrackList::Folder(list) => &list.id,
 			TrackList::Special(list) => &list.id,
+			TrackList::Smart(list) => &list.id,
 		}
 	}
 }
@@ -528,6 +626,45 @@ pub struct Folder {
 	pub children: Vec<TrackListID>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, Type)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct SmartPlaylistRules {
+	/// Same syntax as the track list filter
+	pub filterQuery: String,
+	/// Track field, or "index" for the order tracks were added in
+	pub sortKey: String,
+	pub sortDesc: bool,
+	/// Maximum number of tracks
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub limitCount: Option<u32>,
+	/// Maximum total duration in seconds
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub limitDuration: Option<f64>,
+}
+
+/// A playlist whose tracks are computed from its rules
+#[derive(Serialize, Deserialize, Clone, Debug, Type)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct SmartPlaylist {
+	pub id: TrackListID,
+	pub name: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub description: Option<String>,
+	#[serde(default, skip_serializing_if = "is_false")]
+	pub liked: bool,
+	#[serde(default, skip_serializing_if = "is_false")]
+	pub disliked: bool,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub importedFrom: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub originalId: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub dateImported: Option<MsSinceUnixEpoch>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub dateCreated: Option<MsSinceUnixEpoch>,
+	pub rules: SmartPlaylistRules,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Type)]
 #[cfg_attr(feature = "napi", napi(object))]
 pub struct Special {
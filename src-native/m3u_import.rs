@@ -0,0 +1,340 @@
+use crate::data::Data;
+use crate::data_js::get_data;
+use crate::library_types::{
+	Library, MsSinceUnixEpoch, TrackID, TrackList, TrackListID, new_item_ids_from_track_ids,
+};
+use crate::playlists::insert_tracklist;
+use crate::tracks::import::{self, DuplicateIndex, ImportOptions, ImportSettings};
+use anyhow::{Context, Result, bail};
+use napi::Env;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+struct M3uEntry {
+	line_num: usize,
+	location: String,
+	/// Duration in seconds, artist and title from `#EXTINF`
+	ext_inf: Option<(Option<f64>, Option<String>, String)>,
+}
+
+struct M3uPlaylist {
+	name: Option<String>,
+	entries: Vec<M3uEntry>,
+}
+
+/// Parses `#EXTINF:123,Artist - Title`
+fn parse_ext_inf(value: &str) -> Option<(Option<f64>, Option<String>, String)> {
+	let (duration, display) = value.split_once(',')?;
+	// The duration can be followed by attributes like `tvg-id="x"`
+	let duration = duration
+		.split_whitespace()
+		.next()
+		.and_then(|d| d.parse::<f64>().ok())
+		.filter(|d| *d > 0.0);
+	let display = display.trim();
+	if display.is_empty() {
+		return None;
+	}
+	match display.split_once(" - ") {
+		Some((artist, title)) => Some((
+			duration,
+			Some(artist.trim().to_string()),
+			title.trim().to_string(),
+		)),
+		None => Some((duration, None, display.to_string())),
+	}
+}
+
+fn parse_m3u(text: &str) -> M3uPlaylist {
+	let mut playlist = M3uPlaylist {
+		name: None,
+		entries: Vec::new(),
+	};
+	let mut ext_inf = None;
+	for (i, line) in text.lines().enumerate() {
+		let line = line.trim_start_matches('\u{feff}').trim();
+		if line.is_empty() {
+			continue;
+		} else if let Some(value) = line.strip_prefix("#EXTINF:") {
+			ext_inf = parse_ext_inf(value);
+		} else if let Some(value) = line.strip_prefix("#PLAYLIST:") {
+			playlist.name = Some(value.trim().to_string());
+		} else if line.starts_with('#') {
+			continue;
+		} else {
+			playlist.entries.push(M3uEntry {
+				line_num: i + 1,
+				location: line.to_string(),
+				ext_inf: ext_inf.take(),
+			});
+		}
+	}
+	playlist
+}
+
+/// Removes `.` and `..` without touching the file system
+fn normalize_path(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				normalized.pop();
+			}
+			_ => normalized.push(component),
+		}
+	}
+	normalized
+}
+
+/// Returns `None` for URLs that aren't local files
+fn entry_path(location: &str, playlist_dir: &Path) -> Option<PathBuf> {
+	if location.starts_with("file://") {
+		let url = url::Url::parse(location).ok()?;
+		return url.to_file_path().ok();
+	} else if location.contains("://") {
+		return None;
+	}
+	Some(normalize_path(&playlist_dir.join(location)))
+}
+
+/// Finds a track with the same artist and title, preferring one with a
+/// similar duration
+fn find_by_ext_inf(
+	library: &Library,
+	duration: Option<f64>,
+	artist: Option<&str>,
+	title: &str,
+) -> Option<TrackID> {
+	let title = title.to_lowercase();
+	let artist = artist.map(|artist| artist.to_lowercase());
+	let mut found = None;
+	for (id, track) in library.get_tracks() {
+		if track.name.to_lowercase() != title {
+			continue;
+		}
+		if artist
+			.as_ref()
+			.is_some_and(|artist| &track.artist.to_lowercase() != artist)
+		{
+			continue;
+		}
+		match duration {
+			Some(duration) if (track.duration - duration).abs() > 2.0 => {
+				found.get_or_insert(id);
+			}
+			_ => return Some(id.clone()),
+		}
+	}
+	found.cloned()
+}
+
+#[napi(object)]
+pub struct PlaylistImportStatus {
+	pub playlist_id: TrackListID,
+	/// Entries that could not be resolved or imported
+	pub errors: Vec<String>,
+	/// Problems with imported files that were worked around
+	pub warnings: Vec<String>,
+	/// Entries added to the playlist
+	pub tracks_count: i64,
+	/// Unknown files that were imported into the library
+	pub imported_count: i64,
+}
+
+/// Entries are resolved by path, and then by `#EXTINF` artist and title. If
+/// `import_options` is set, unknown files are imported with those options.
+/// Otherwise they are reported as errors.
+pub fn import_m3u_file(
+	data: &mut Data,
+	path: &Path,
+	parent_id: &str,
+	import_options: Option<ImportOptions>,
+	now: MsSinceUnixEpoch,
+) -> Result<PlaylistImportStatus> {
+	if let TrackList::Playlist(_) | TrackList::Smart(_) = data.library.get_tracklist(parent_id)? {
+		bail!("Parent cannot be playlist");
+	}
+	let bytes = fs::read(path).context("Unable to read playlist file")?;
+	// .m3u files are often not UTF-8
+	let m3u = parse_m3u(&String::from_utf8_lossy(&bytes));
+	let playlist_dir = path.parent().context("Invalid playlist path")?;
+	let mut import_settings = match import_options {
+		Some(options) => {
+			let settings = ImportSettings::from_options(Some(options))?;
+			let index = DuplicateIndex::new(&data.library, &data.paths, settings.duplicates);
+			Some((settings, index))
+		}
+		None => None,
+	};
+
+	let mut status = PlaylistImportStatus {
+		playlist_id: String::new(),
+		errors: Vec::new(),
+		warnings: Vec::new(),
+		tracks_count: 0,
+		imported_count: 0,
+	};
+	let mut path_map: HashMap<PathBuf, TrackID> = HashMap::new();
+	for (id, track) in data.library.get_tracks() {
+		let track_path = data.paths.get_track_file_path(&track.file);
+		path_map.insert(normalize_path(&track_path), id.clone());
+	}
+
+	let mut track_ids = Vec::new();
+	for entry in m3u.entries {
+		let entry_path = entry_path(&entry.location, playlist_dir);
+		if let Some(id) = entry_path.as_ref().and_then(|p| path_map.get(p)) {
+			track_ids.push(id.clone());
+			continue;
+		}
+		if let Some((duration, artist, title)) = &entry.ext_inf {
+			let id = find_by_ext_inf(&data.library, *duration, artist.as_deref(), title);
+			if let Some(id) = id {
+				track_ids.push(id);
+				continue;
+			}
+		}
+		let error_prefix = format!("Line {}", entry.line_num);
+		match (entry_path, &mut import_settings) {
+			(Some(entry_path), Some((settings, index))) if entry_path.is_file() => {
+				match import::import(data, &entry_path, settings, index, now) {
+					Ok(imported) => {
+						for warning in imported.warnings {
+							let path = entry_path.to_string_lossy();
+							status.warnings.push(format!("[{path}] {warning}"));
+						}
+						if imported.duplicate_of.is_none() {
+							status.imported_count += 1;
+						}
+						path_map.insert(entry_path, imported.id.clone());
+						track_ids.push(imported.id);
+					}
+					Err(e) => status.errors.push(format!(
+						"{error_prefix}: Unable to import {}: {e:#}",
+						entry.location
+					)),
+				}
+			}
+			_ => status.errors.push(format!(
+				"{error_prefix}: Track not found: {}",
+				entry.location
+			)),
+		}
+	}
+
+	let name = match m3u.name {
+		Some(name) => name,
+		None => path
+			.file_stem()
+			.map(|stem| stem.to_string_lossy().into_owned())
+			.unwrap_or_default(),
+	};
+	let mut playlist = data.library.new_playlist(name, None);
+	playlist.importedFrom = Some("m3u".to_string());
+	playlist.dateImported = Some(now);
+	playlist.tracks = new_item_ids_from_track_ids(&track_ids);
+	status.playlist_id = playlist.id.clone();
+	status.tracks_count = track_ids.len() as i64;
+	insert_tracklist(&mut data.library, TrackList::Playlist(playlist), parent_id)?;
+	Ok(status)
+}
+
+#[napi(js_name = "import_m3u")]
+#[allow(dead_code)]
+pub fn import_m3u(
+	path: String,
+	parent_id: String,
+	now: MsSinceUnixEpoch,
+	import_options: Option<ImportOptions>,
+	env: Env,
+) -> Result<PlaylistImportStatus> {
+	let data: &mut Data = get_data(&env);
+	import_m3u_file(data, Path::new(&path), &parent_id, import_options, now)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_ext_inf_test() {
+		assert_eq!(
+			parse_ext_inf("123,Artist - Title"),
+			Some((Some(123.0), Some("Artist".to_string()), "Title".to_string()))
+		);
+		assert_eq!(
+			parse_ext_inf("-1,Stream Name"),
+			Some((None, None, "Stream Name".to_string()))
+		);
+		assert_eq!(
+			parse_ext_inf(r#"61 tvg-id="x",A - B - C"#),
+			Some((Some(61.0), Some("A".to_string()), "B - C".to_string()))
+		);
+		assert_eq!(parse_ext_inf("123,"), None);
+		assert_eq!(parse_ext_inf("123"), None);
+	}
+
+	#[test]
+	fn parse_m3u_test() {
+		let text = "\u{feff}#EXTM3U\r\n\
+			#PLAYLIST: Road Trip \r\n\
+			#EXTINF:200,Artist - First\r\n\
+			first.mp3\r\n\
+			\r\n\
+			# comment\r\n\
+			../second.flac\r\n\
+			#EXTINF:100,Ignored\r\n\
+			#EXTINF:300,Third\r\n\
+			http://example.com/third.mp3\r\n";
+		let playlist = parse_m3u(text);
+		assert_eq!(playlist.name.as_deref(), Some("Road Trip"));
+		let entries: Vec<_> = playlist
+			.entries
+			.iter()
+			.map(|entry| {
+				(
+					entry.line_num,
+					entry.location.as_str(),
+					entry.ext_inf.clone(),
+				)
+			})
+			.collect();
+		assert_eq!(
+			entries,
+			vec![
+				(
+					4,
+					"first.mp3",
+					Some((Some(200.0), Some("Artist".to_string()), "First".to_string()))
+				),
+				(7, "../second.flac", None),
+				(
+					10,
+					"http://example.com/third.mp3",
+					Some((Some(300.0), None, "Third".to_string()))
+				),
+			]
+		);
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn entry_path_test() {
+		let dir = Path::new("/music/playlists");
+		assert_eq!(
+			entry_path("../a/b.mp3", dir),
+			Some(PathBuf::from("/music/a/b.mp3"))
+		);
+		assert_eq!(
+			entry_path("/abs/./c.mp3", dir),
+			Some(PathBuf::from("/abs/c.mp3"))
+		);
+		assert_eq!(
+			entry_path("file:///abs/d%20e.mp3", dir),
+			Some(PathBuf::from("/abs/d e.mp3"))
+		);
+		assert_eq!(entry_path("https://example.com/f.mp3", dir), None);
+	}
+}
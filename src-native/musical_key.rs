@@ -0,0 +1,161 @@
+const MAJOR_NAMES: [&str; 12] = [
+	"C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+const MINOR_NAMES: [&str; 12] = [
+	"C", "C#", "D", "Eb", "E", "F", "F#", "G", "G#", "A", "Bb", "B",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicalKey {
+	/// Pitch class of the tonic, where 0 is C
+	pub tonic: u8,
+	pub minor: bool,
+}
+impl MusicalKey {
+	/// Parses standard notation like "Am", "F#", "Eb minor", Camelot notation
+	/// like "8A" and Open Key notation like "1m"
+	pub fn parse(value: &str) -> Option<MusicalKey> {
+		let value = value.trim();
+		let first = value.chars().next()?;
+		if first.is_ascii_digit() {
+			return Self::parse_numbered(value);
+		}
+		let natural: i32 = match first.to_ascii_uppercase() {
+			'C' => 0,
+			'D' => 2,
+			'E' => 4,
+			'F' => 5,
+			'G' => 7,
+			'A' => 9,
+			'B' => 11,
+			_ => return None,
+		};
+		let mut rest = &value[1..];
+		let mut tonic = natural;
+		if let Some(r) = rest.strip_prefix(['#', '♯']) {
+			tonic += 1;
+			rest = r;
+		} else if let Some(r) = rest.strip_prefix(['b', '♭']) {
+			tonic -= 1;
+			rest = r;
+		}
+		let minor = match rest.trim().to_ascii_lowercase().as_str() {
+			"" | "maj" | "major" => false,
+			"m" | "min" | "minor" => true,
+			_ => return None,
+		};
+		Some(MusicalKey {
+			tonic: tonic.rem_euclid(12) as u8,
+			minor,
+		})
+	}
+	fn parse_numbered(value: &str) -> Option<MusicalKey> {
+		let split = value.find(|c: char| !c.is_ascii_digit())?;
+		let (number, suffix) = value.split_at(split);
+		let number: u8 = number.parse().ok()?;
+		if !(1..=12).contains(&number) {
+			return None;
+		}
+		let (camelot, minor) = match suffix.to_ascii_lowercase().as_str() {
+			"a" => (number, true),
+			"b" => (number, false),
+			// Open Key 1d/1m is Camelot 8B/8A
+			"m" => ((number + 6) % 12 + 1, true),
+			"d" => ((number + 6) % 12 + 1, false),
+			_ => return None,
+		};
+		// Camelot numbers go up in fifths, starting with 8B = C major
+		let major_tonic = (7 * (i32::from(camelot) - 8)).rem_euclid(12);
+		let tonic = match minor {
+			true => (major_tonic - 3).rem_euclid(12),
+			false => major_tonic,
+		};
+		Some(MusicalKey {
+			tonic: tonic as u8,
+			minor,
+		})
+	}
+	/// Standard notation, like "Am" or "F#"
+	pub fn name(&self) -> String {
+		match self.minor {
+			true => format!("{}m", MINOR_NAMES[self.tonic as usize]),
+			false => MAJOR_NAMES[self.tonic as usize].to_string(),
+		}
+	}
+	/// Camelot notation, like "8A"
+	pub fn camelot(&self) -> String {
+		let major_tonic = match self.minor {
+			true => (self.tonic + 3) % 12,
+			false => self.tonic,
+		};
+		let number = (major_tonic * 7 + 7) % 12 + 1;
+		let letter = if self.minor { 'A' } else { 'B' };
+		format!("{number}{letter}")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(tonic: u8, minor: bool) -> Option<MusicalKey> {
+		Some(MusicalKey { tonic, minor })
+	}
+
+	#[test]
+	fn parse_test() {
+		assert_eq!(MusicalKey::parse("C"), key(0, false));
+		assert_eq!(MusicalKey::parse("Am"), key(9, true));
+		assert_eq!(MusicalKey::parse(" F# "), key(6, false));
+		assert_eq!(MusicalKey::parse("Eb minor"), key(3, true));
+		assert_eq!(MusicalKey::parse("Bbm"), key(10, true));
+		assert_eq!(MusicalKey::parse("bb min"), key(10, true));
+		assert_eq!(MusicalKey::parse("C♯ Major"), key(1, false));
+		assert_eq!(MusicalKey::parse("Cb"), key(11, false));
+		assert_eq!(MusicalKey::parse("B#m"), key(0, true));
+
+		assert_eq!(MusicalKey::parse("8A"), key(9, true));
+		assert_eq!(MusicalKey::parse("8b"), key(0, false));
+		assert_eq!(MusicalKey::parse("12B"), key(4, false));
+		assert_eq!(MusicalKey::parse("1A"), key(8, true));
+		assert_eq!(MusicalKey::parse("1d"), key(0, false));
+		assert_eq!(MusicalKey::parse("1m"), key(9, true));
+		assert_eq!(MusicalKey::parse("6m"), key(8, true));
+
+		for invalid in ["", "H", "Cx", "C mi nor", "0A", "13B", "8", "8C", "A8"] {
+			assert_eq!(MusicalKey::parse(invalid), None, "{invalid:?}");
+		}
+	}
+
+	#[test]
+	fn name_test() {
+		assert_eq!(key(0, false).unwrap().name(), "C");
+		assert_eq!(key(1, false).unwrap().name(), "Db");
+		assert_eq!(key(1, true).unwrap().name(), "C#m");
+		assert_eq!(key(8, true).unwrap().name(), "G#m");
+		assert_eq!(key(10, false).unwrap().name(), "Bb");
+	}
+
+	#[test]
+	fn camelot_test() {
+		assert_eq!(key(0, false).unwrap().camelot(), "8B");
+		assert_eq!(key(9, true).unwrap().camelot(), "8A");
+		assert_eq!(key(7, false).unwrap().camelot(), "9B");
+		assert_eq!(key(5, false).unwrap().camelot(), "7B");
+		assert_eq!(key(4, false).unwrap().camelot(), "12B");
+		assert_eq!(key(11, false).unwrap().camelot(), "1B");
+		assert_eq!(key(8, true).unwrap().camelot(), "1A");
+		assert_eq!(key(3, true).unwrap().camelot(), "2A");
+	}
+
+	#[test]
+	fn round_trip_test() {
+		for tonic in 0..12 {
+			for minor in [false, true] {
+				let musical_key = MusicalKey { tonic, minor };
+				assert_eq!(MusicalKey::parse(&musical_key.name()), Some(musical_key));
+				assert_eq!(MusicalKey::parse(&musical_key.camelot()), Some(musical_key));
+			}
+		}
+	}
+}
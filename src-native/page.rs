@@ -61,6 +61,13 @@ pub fn get_tracks_page_from_library(
 			playlist_length: tracklist_length as u32,
 			item_ids,
 		},
+		TrackList::Smart(smart) => TracksPage {
+			playlist_kind: tracklist.kind().to_string(),
+			playlist_name: smart.name.clone(),
+			playlist_description: smart.description.clone(),
+			playlist_length: tracklist_length as u32,
+			item_ids,
+		},
 		TrackList::Special(special) => TracksPage {
 			playlist_kind: tracklist.kind().to_string(),
 			playlist_name: special.name.to_string(),
@@ -0,0 +1,392 @@
+use crate::data::Data;
+use crate::data_js::get_data;
+use crate::library::Paths;
+use crate::library::get_tracklist_item_ids;
+use crate::library_types::{Library, TrackID, TrackList, get_track_ids_from_item_ids};
+use crate::tracks::sanitize_filename;
+use anyhow::{Context, Result, bail};
+use napi::Env;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+	M3u8,
+	Xspf,
+	Pls,
+}
+impl ExportFormat {
+	fn from_str(format: &str) -> Result<Self> {
+		match format {
+			"m3u8" => Ok(ExportFormat::M3u8),
+			"xspf" => Ok(ExportFormat::Xspf),
+			"pls" => Ok(ExportFormat::Pls),
+			_ => bail!("Unknown playlist format {format}"),
+		}
+	}
+	fn file_extension(&self) -> &'static str {
+		match self {
+			ExportFormat::M3u8 => "m3u8",
+			ExportFormat::Xspf => "xspf",
+			ExportFormat::Pls => "pls",
+		}
+	}
+}
+
+/// Returns `path` relative to `dir`, or `None` if there's no relative path,
+/// like for files on another drive
+fn relative_path(dir: &Path, path: &Path) -> Option<PathBuf> {
+	let mut dir_components = dir.components().peekable();
+	let mut path_components = path.components().peekable();
+	while let (Some(a), Some(b)) = (dir_components.peek(), path_components.peek()) {
+		if a != b {
+			break;
+		}
+		dir_components.next();
+		path_components.next();
+	}
+	let mut relative = PathBuf::new();
+	for component in dir_components {
+		match component {
+			Component::Normal(_) => relative.push(".."),
+			Component::CurDir => {}
+			_ => return None,
+		}
+	}
+	for component in path_components {
+		match component {
+			Component::Normal(c) => relative.push(c),
+			_ => return None,
+		}
+	}
+	Some(relative)
+}
+
+fn escape_xml(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&apos;")
+}
+
+/// XSPF locations are URIs
+fn xspf_location(path: &Path, is_relative: bool) -> String {
+	if is_relative {
+		let segments: Vec<String> = path
+			.components()
+			.map(|c| {
+				url::form_urlencoded::byte_serialize(c.as_os_str().as_encoded_bytes())
+					.collect::<String>()
+					.replace('+', "%20")
+			})
+			.collect();
+		segments.join("/")
+	} else {
+		match url::Url::from_file_path(path) {
+			Ok(url) => url.to_string(),
+			Err(_) => path.to_string_lossy().into_owned(),
+		}
+	}
+}
+
+struct ExportTrack {
+	path: PathBuf,
+	is_relative: bool,
+	artist: String,
+	title: String,
+	album: Option<String>,
+	duration: f64,
+}
+impl ExportTrack {
+	fn display_title(&self) -> String {
+		match self.artist.as_str() {
+			"" => self.title.clone(),
+			artist => format!("{artist} - {}", self.title),
+		}
+	}
+}
+
+fn get_export_tracks(
+	library: &Library,
+	paths: &Paths,
+	track_ids: &[TrackID],
+	playlist_dir: &Path,
+	relative_paths: bool,
+) -> Result<Vec<ExportTrack>> {
+	let mut tracks = Vec::new();
+	for track_id in track_ids {
+		let track = library.get_track(track_id)?;
+		let path = std::path::absolute(paths.get_track_file_path(&track.file))
+			.context("Invalid track path")?;
+		let relative = match relative_paths {
+			true => relative_path(playlist_dir, &path),
+			false => None,
+		};
+		tracks.push(ExportTrack {
+			is_relative: relative.is_some(),
+			path: relative.unwrap_or(path),
+			artist: track.artist.clone(),
+			title: track.name.clone(),
+			album: track.albumName.clone(),
+			duration: track.duration,
+		});
+	}
+	Ok(tracks)
+}
+
+fn write_m3u8(name: &str, tracks: &[ExportTrack]) -> String {
+	let mut s = String::from("#EXTM3U\n");
+	writeln!(s, "#PLAYLIST:{name}").unwrap();
+	for track in tracks {
+		let duration = track.duration.round() as i64;
+		writeln!(s, "#EXTINF:{duration},{}", track.display_title()).unwrap();
+		writeln!(s, "{}", track.path.to_string_lossy()).unwrap();
+	}
+	s
+}
+
+fn write_xspf(name: &str, tracks: &[ExportTrack]) -> String {
+	let mut s = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+	s.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+	writeln!(s, "\t<title>{}</title>", escape_xml(name)).unwrap();
+	s.push_str("\t<trackList>\n");
+	for track in tracks {
+		s.push_str("\t\t<track>\n");
+		let location = xspf_location(&track.path, track.is_relative);
+		writeln!(s, "\t\t\t<location>{}</location>", escape_xml(&location)).unwrap();
+		writeln!(s, "\t\t\t<title>{}</title>", escape_xml(&track.title)).unwrap();
+		if !track.artist.is_empty() {
+			writeln!(s, "\t\t\t<creator>{}</creator>", escape_xml(&track.artist)).unwrap();
+		}
+		if let Some(album) = &track.album {
+			writeln!(s, "\t\t\t<album>{}</album>", escape_xml(album)).unwrap();
+		}
+		let duration_ms = (track.duration * 1000.0).round() as i64;
+		writeln!(s, "\t\t\t<duration>{duration_ms}</duration>").unwrap();
+		s.push_str("\t\t</track>\n");
+	}
+	s.push_str("\t</trackList>\n");
+	s.push_str("</playlist>\n");
+	s
+}
+
+fn write_pls(tracks: &[ExportTrack]) -> String {
+	let mut s = String::from("[playlist]\n");
+	for (i, track) in tracks.iter().enumerate() {
+		let n = i + 1;
+		writeln!(s, "File{n}={}", track.path.to_string_lossy()).unwrap();
+		writeln!(s, "Title{n}={}", track.display_title()).unwrap();
+		writeln!(s, "Length{n}={}", track.duration.round() as i64).unwrap();
+	}
+	writeln!(s, "NumberOfEntries={}", tracks.len()).unwrap();
+	s.push_str("Version=2\n");
+	s
+}
+
+struct Exporter<'a> {
+	library: &'a Library,
+	paths: &'a Paths,
+	format: ExportFormat,
+	relative_paths: bool,
+	files_count: i64,
+}
+impl Exporter<'_> {
+	/// Returns a sanitized file name that isn't already used in the directory,
+	/// either by this export or by an existing file
+	fn unique_name(
+		name: &str,
+		ext: Option<&str>,
+		dir: &Path,
+		used: &mut HashSet<String>,
+	) -> String {
+		let base = match sanitize_filename(&name.to_string()).trim() {
+			"" => "Untitled".to_string(),
+			base => base.to_string(),
+		};
+		let mut num = 1;
+		loop {
+			let mut file_name = match num {
+				1 => base.clone(),
+				_ => format!("{base} {num}"),
+			};
+			if let Some(ext) = ext {
+				file_name = file_name + "." + ext;
+			}
+			let exists = fs::symlink_metadata(dir.join(&file_name)).is_ok();
+			if !exists && used.insert(file_name.to_lowercase()) {
+				return file_name;
+			}
+			num += 1;
+		}
+	}
+	fn export_playlist(
+		&mut self,
+		name: &str,
+		track_ids: &[TrackID],
+		file_path: &Path,
+	) -> Result<()> {
+		let dir = file_path.parent().context("Invalid export path")?;
+		let dir = std::path::absolute(dir).context("Invalid export path")?;
+		let tracks = get_export_tracks(
+			self.library,
+			self.paths,
+			track_ids,
+			&dir,
+			self.relative_paths,
+		)?;
+		let contents = match self.format {
+			ExportFormat::M3u8 => write_m3u8(name, &tracks),
+			ExportFormat::Xspf => write_xspf(name, &tracks),
+			ExportFormat::Pls => write_pls(&tracks),
+		};
+		// Never overwrite existing files
+		let mut file = fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(file_path)
+			.with_context(|| format!("Unable to create {}", file_path.to_string_lossy()))?;
+		file.write_all(contents.as_bytes())
+			.with_context(|| format!("Unable to write {}", file_path.to_string_lossy()))?;
+		self.files_count += 1;
+		Ok(())
+	}
+	/// Exports a playlist as a file in `dir`, or a folder as a directory in `dir`
+	fn export_list(
+		&mut self,
+		tracklist: &TrackList,
+		dir: &Path,
+		used_names: &mut HashSet<String>,
+	) -> Result<()> {
+		let ext = self.format.file_extension();
+		match tracklist {
+			TrackList::Playlist(playlist) => {
+				let name = Self::unique_name(&playlist.name, Some(ext), dir, used_names);
+				let track_ids = playlist.get_track_ids();
+				self.export_playlist(&playlist.name, &track_ids, &dir.join(name))?;
+			}
+			TrackList::Smart(smart) => {
+				let name = Self::unique_name(&smart.name, Some(ext), dir, used_names);
+				let item_ids = get_tracklist_item_ids(self.library, &smart.id)?;
+				let track_ids = get_track_ids_from_item_ids(&item_ids);
+				self.export_playlist(&smart.name, &track_ids, &dir.join(name))?;
+			}
+			TrackList::Folder(folder) => {
+				let name = Self::unique_name(&folder.name, None, dir, used_names);
+				self.export_children(&folder.children, &dir.join(name))?;
+			}
+			TrackList::Special(_) => bail!("Unexpected special playlist"),
+		}
+		Ok(())
+	}
+	/// Exports the children of a folder into `dir`
+	fn export_children(&mut self, children: &[String], dir: &Path) -> Result<()> {
+		fs::create_dir_all(dir)
+			.with_context(|| format!("Unable to create folder {}", dir.to_string_lossy()))?;
+		let mut used_names = HashSet::new();
+		for child_id in children {
+			let tracklist = self.library.get_tracklist(child_id)?;
+			self.export_list(tracklist, dir, &mut used_names)?;
+		}
+		Ok(())
+	}
+}
+
+/// Exports a playlist to a file in `dir`, or a folder as a directory of
+/// playlist files inside `dir`. Exporting the root exports all playlists
+/// directly into `dir`. Returns the number of files written.
+pub fn export_tracklist(
+	data: &Data,
+	id: &str,
+	dir: &Path,
+	format: &str,
+	relative_paths: bool,
+) -> Result<i64> {
+	let mut exporter = Exporter {
+		library: &data.library,
+		paths: &data.paths,
+		format: ExportFormat::from_str(format)?,
+		relative_paths,
+		files_count: 0,
+	};
+	match data.library.get_tracklist(id)? {
+		TrackList::Special(special) => {
+			exporter.export_children(&special.children, dir)?;
+		}
+		tracklist => {
+			fs::create_dir_all(dir)
+				.with_context(|| format!("Unable to create folder {}", dir.to_string_lossy()))?;
+			exporter.export_list(tracklist, dir, &mut HashSet::new())?;
+		}
+	}
+	Ok(exporter.files_count)
+}
+
+#[napi(js_name = "export_playlist")]
+#[allow(dead_code)]
+pub fn export_playlist(
+	id: String,
+	dir: String,
+	format: String,
+	relative_paths: bool,
+	env: Env,
+) -> Result<i64> {
+	let data: &Data = get_data(&env);
+	export_tracklist(data, &id, Path::new(&dir), &format, relative_paths)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(unix)]
+	fn relative_path_test() {
+		let dir = Path::new("/music/playlists");
+		assert_eq!(
+			relative_path(dir, Path::new("/music/playlists/a.mp3")),
+			Some(PathBuf::from("a.mp3"))
+		);
+		assert_eq!(
+			relative_path(dir, Path::new("/music/tracks/b.mp3")),
+			Some(PathBuf::from("../tracks/b.mp3"))
+		);
+		assert_eq!(
+			relative_path(dir, Path::new("/other/c.mp3")),
+			Some(PathBuf::from("../../other/c.mp3"))
+		);
+		assert_eq!(relative_path(dir, Path::new("relative/d.mp3")), None);
+	}
+
+	#[test]
+	#[cfg(windows)]
+	fn relative_path_windows_test() {
+		let dir = Path::new(r"C:\music\playlists");
+		assert_eq!(
+			relative_path(dir, Path::new(r"C:\music\tracks\a.mp3")),
+			Some(PathBuf::from(r"..\tracks\a.mp3"))
+		);
+		assert_eq!(relative_path(dir, Path::new(r"D:\b.mp3")), None);
+	}
+
+	#[test]
+	fn unique_name_test() {
+		let dir = std::env::temp_dir().join("ferrum_unique_name_test");
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("Existing.m3u8"), "").unwrap();
+
+		let mut used = HashSet::new();
+		let name = |name: &str, used: &mut HashSet<String>| {
+			Exporter::unique_name(name, Some("m3u8"), &dir, used)
+		};
+		assert_eq!(name("Mix", &mut used), "Mix.m3u8");
+		assert_eq!(name("mix", &mut used), "mix 2.m3u8");
+		assert_eq!(name("Existing", &mut used), "Existing 2.m3u8");
+		assert_eq!(name(" ", &mut used), "Untitled.m3u8");
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -1,8 +1,9 @@
 use crate::data::Data;
 use crate::data_js::get_data;
+use crate::library::validate_smart_playlist_rules;
 use crate::library_types::{
-	ItemId, Library, SpecialTrackListName, TRACK_ID_MAP, TrackID, TrackList,
-	get_track_ids_from_item_ids, new_item_ids_from_track_ids,
+	ItemId, Library, SmartPlaylistRules, SpecialTrackListName, TRACK_ID_MAP, TrackID, TrackList,
+	TrackListID, get_track_ids_from_item_ids, new_item_ids_from_track_ids,
 };
 use crate::str_to_option;
 use anyhow::{Context, Result, bail};
@@ -39,11 +40,13 @@ pub fn get_track_lists_details(env: Env) -> HashMap<String, TrackListDetails> {
 						TrackList::Special(_) => "special".to_string(),
 						TrackList::Folder(_) => "folder".to_string(),
 						TrackList::Playlist(_) => "playlist".to_string(),
+						TrackList::Smart(_) => "smart".to_string(),
 					},
 					name: match tracklist {
 						TrackList::Special(tracklist) => tracklist.name.to_string(),
 						TrackList::Folder(tracklist) => tracklist.name.clone(),
 						TrackList::Playlist(tracklist) => tracklist.name.clone(),
+						TrackList::Smart(tracklist) => tracklist.name.clone(),
 					},
 					children: match tracklist {
 						TrackList::Special(tracklist) => Some(tracklist.children.clone()),
@@ -70,7 +73,7 @@ fn get_child_ids_recursive(
 	ids: &mut HashSet<String>,
 ) -> Result<()> {
 	let folder_children = match library.trackLists.get(id) {
-		Some(TrackList::Playlist(_)) => return Ok(()),
+		Some(TrackList::Playlist(_) | TrackList::Smart(_)) => return Ok(()),
 		Some(TrackList::Folder(folder)) => folder.children.clone(),
 		Some(TrackList::Special(_)) => bail!("Cannot delete special track list"),
 		None => bail!("No track list with id {}", id),
@@ -93,7 +96,7 @@ fn remove_child_id(library: &mut Library, parent_id: &String, child_id: &String)
 		.get_mut(parent_id)
 		.with_context(|| format!("Parent id {parent_id} not found"))?;
 	let children = match parent {
-		TrackList::Playlist(_) => bail!("Parent id {parent_id} not found"),
+		TrackList::Playlist(_) | TrackList::Smart(_) => bail!("Parent id {parent_id} not found"),
 		TrackList::Folder(folder) => folder.children.clone(),
 		TrackList::Special(special) => match special.name {
 			SpecialTrackListName::Root => special.children.clone(),
@@ -110,7 +113,7 @@ fn remove_child_id(library: &mut Library, parent_id: &String, child_id: &String)
 		_ => bail!("Child id {child_id} found multiple times"),
 	};
 	match parent {
-		TrackList::Playlist(_) => panic!(),
+		TrackList::Playlist(_) | TrackList::Smart(_) => panic!(),
 		TrackList::Folder(folder) => folder.children = new_children,
 		TrackList::Special(special) => match special.name {
 			SpecialTrackListName::Root => special.children = new_children,
@@ -148,6 +151,7 @@ pub fn add_tracks(playlist_id: String, track_ids: Vec<String>, env: Env) -> Resu
 		TrackList::Playlist(playlist) => playlist,
 		TrackList::Folder(_) => bail!("Cannot add track to folder"),
 		TrackList::Special(_) => bail!("Cannot add track to special playlist"),
+		TrackList::Smart(_) => bail!("Cannot add track to smart playlist"),
 	};
 	let mut new_item_ids = new_item_ids_from_track_ids(&track_ids);
 	playlist.tracks.append(&mut new_item_ids);
@@ -259,6 +263,29 @@ pub fn delete_tracks_with_item_ids(item_ids: Vec<ItemId>, env: Env) -> Result<()
 	return Ok(());
 }
 
+/// Inserts a new playlist or folder at the end of a parent folder
+pub fn insert_tracklist(library: &mut Library, list: TrackList, parent_id: &str) -> Result<()> {
+	let parent = library
+		.trackLists
+		.get_mut(parent_id)
+		.context("Parent not found")?;
+
+	match parent {
+		TrackList::Playlist(_) | TrackList::Smart(_) => bail!("Parent cannot be playlist"),
+		TrackList::Folder(folder) => {
+			folder.children.push(list.id().to_string());
+			library.trackLists.insert(list.id().to_string(), list);
+		}
+		TrackList::Special(special) => match special.name {
+			SpecialTrackListName::Root => {
+				special.children.push(list.id().to_string());
+				library.trackLists.insert(list.id().to_string(), list);
+			}
+		},
+	};
+	Ok(())
+}
+
 #[napi(js_name = "new_playlist")]
 #[allow(dead_code)]
 pub fn new_playlist(
@@ -282,28 +309,41 @@ pub fn new_playlist(
 		}
 	};
 
-	let parent = library
-		.trackLists
-		.get_mut(&parent_id)
-		.context("Parent not found")?;
-
-	match parent {
-		TrackList::Playlist(_) => bail!("Parent cannot be playlist"),
-		TrackList::Folder(folder) => {
-			folder.children.push(list.id().to_string());
-			library.trackLists.insert(list.id().to_string(), list);
-		}
-		TrackList::Special(special) => match special.name {
-			SpecialTrackListName::Root => {
-				special.children.push(list.id().to_string());
-				library.trackLists.insert(list.id().to_string(), list);
-			}
-		},
-	};
+	insert_tracklist(library, list, &parent_id)?;
 
 	return Ok(());
 }
 
+#[napi(js_name = "new_smart_playlist")]
+#[allow(dead_code)]
+pub fn new_smart_playlist(
+	name: String,
+	description: String,
+	rules: SmartPlaylistRules,
+	parent_id: String,
+	env: Env,
+) -> Result<TrackListID> {
+	let data: &mut Data = get_data(&env);
+	let library = &mut data.library;
+	validate_smart_playlist_rules(&rules)?;
+	let smart = library.new_smart_playlist(name, str_to_option(description), rules);
+	let id = smart.id.clone();
+	insert_tracklist(library, TrackList::Smart(smart), &parent_id)?;
+	Ok(id)
+}
+
+#[napi(js_name = "update_smart_playlist_rules")]
+#[allow(dead_code)]
+pub fn update_smart_playlist_rules(id: String, rules: SmartPlaylistRules, env: Env) -> Result<()> {
+	let data: &mut Data = get_data(&env);
+	validate_smart_playlist_rules(&rules)?;
+	match data.library.get_tracklist_mut(&id)? {
+		TrackList::Smart(smart) => smart.rules = rules,
+		_ => bail!("Not a smart playlist"),
+	};
+	Ok(())
+}
+
 #[napi(js_name = "update_playlist")]
 #[allow(dead_code)]
 pub fn update_playlist(id: String, name: String, description: String, env: Env) -> Result<()> {
@@ -319,6 +359,10 @@ pub fn update_playlist(id: String, name: String, description: String, env: Env)
 			folder.name = name;
 			folder.description = str_to_option(description);
 		}
+		Some(TrackList::Smart(smart)) => {
+			smart.name = name;
+			smart.description = str_to_option(description);
+		}
 		None => bail!("Playlist not found"),
 	};
 
@@ -329,13 +373,13 @@ fn get_all_tracklist_children(data: &Data, playlist_id: &str) -> Result<Vec<Trac
 	let direct_children = match data.library.get_tracklist(playlist_id)? {
 		TrackList::Folder(folder) => &folder.children,
 		TrackList::Special(special) => &special.children,
-		TrackList::Playlist(_) => return Ok(Vec::new()),
+		TrackList::Playlist(_) | TrackList::Smart(_) => return Ok(Vec::new()),
 	};
 	let mut all_children = Vec::new();
 	for child_id in direct_children {
 		all_children.push(child_id.clone());
 		match data.library.get_tracklist(child_id)? {
-			TrackList::Playlist(_) => {}
+			TrackList::Playlist(_) | TrackList::Smart(_) => {}
 			TrackList::Folder(folder) => {
 				all_children.extend(get_all_tracklist_children(data, &folder.id)?)
 			}
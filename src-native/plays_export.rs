@@ -0,0 +1,175 @@
+use crate::data::Data;
+use crate::data_js::get_data;
+use crate::library_types::{Library, MsSinceUnixEpoch, Track};
+use anyhow::{Context, Result, bail};
+use napi::Env;
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+	ListenBrainz,
+	ScrobblerLog,
+}
+impl ExportFormat {
+	fn from_str(format: &str) -> Result<Self> {
+		match format {
+			"listenbrainz" => Ok(ExportFormat::ListenBrainz),
+			"scrobbler_log" => Ok(ExportFormat::ScrobblerLog),
+			_ => bail!("Unknown listens format {format}"),
+		}
+	}
+}
+
+struct Listen<'a> {
+	track: &'a Track,
+	/// Seconds since Unix epoch when the track started playing
+	listened_at: i64,
+}
+
+#[derive(Serialize)]
+struct ListenBrainzListen<'a> {
+	listened_at: i64,
+	track_metadata: ListenBrainzTrackMetadata<'a>,
+}
+
+#[derive(Serialize)]
+struct ListenBrainzTrackMetadata<'a> {
+	artist_name: &'a str,
+	track_name: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	release_name: Option<&'a str>,
+	additional_info: ListenBrainzAdditionalInfo,
+}
+
+#[derive(Serialize)]
+struct ListenBrainzAdditionalInfo {
+	duration_ms: i64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	tracknumber: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	discnumber: Option<u32>,
+	media_player: &'static str,
+	submission_client: &'static str,
+}
+
+/// A JSON array of listens in the ListenBrainz submission format
+fn write_listenbrainz(listens: &[Listen]) -> Result<String> {
+	let listens: Vec<_> = listens
+		.iter()
+		.map(|listen| ListenBrainzListen {
+			listened_at: listen.listened_at,
+			track_metadata: ListenBrainzTrackMetadata {
+				artist_name: &listen.track.artist,
+				track_name: &listen.track.name,
+				release_name: listen.track.albumName.as_deref(),
+				additional_info: ListenBrainzAdditionalInfo {
+					duration_ms: (listen.track.duration * 1000.0).round() as i64,
+					tracknumber: listen.track.trackNum,
+					discnumber: listen.track.discNum,
+					media_player: "Ferrum",
+					submission_client: "Ferrum",
+				},
+			},
+		})
+		.collect();
+	serde_json::to_string_pretty(&listens).context("Unable to serialize listens")
+}
+
+/// Tabs separate the fields, so they can't be in values
+fn scrobbler_field(value: &str) -> String {
+	value.replace(['\t', '\n', '\r'], " ")
+}
+
+fn write_scrobbler_log(listens: &[Listen]) -> String {
+	let mut s = String::from("#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/Ferrum\n");
+	for listen in listens {
+		let track = listen.track;
+		writeln!(
+			s,
+			"{}\t{}\t{}\t{}\t{}\tL\t{}\t",
+			scrobbler_field(&track.artist),
+			scrobbler_field(track.albumName.as_deref().unwrap_or_default()),
+			scrobbler_field(&track.name),
+			track.trackNum.map(|n| n.to_string()).unwrap_or_default(),
+			track.duration.round() as i64,
+			listen.listened_at,
+		)
+		.unwrap();
+	}
+	s
+}
+
+#[napi(object)]
+pub struct PlaysExportStatus {
+	pub listens_count: i64,
+	/// Plays from `playsImported` in the date range. They have no timestamps,
+	/// so they are not exported.
+	pub imported_plays_count: i64,
+}
+
+/// Exports plays between `from` and `to` as listens. Plays are recorded when
+/// tracks finish, so the track duration is subtracted to get the time the
+/// listen started.
+pub fn export_listens(
+	library: &Library,
+	path: &Path,
+	format: &str,
+	from: Option<MsSinceUnixEpoch>,
+	to: Option<MsSinceUnixEpoch>,
+) -> Result<PlaysExportStatus> {
+	let format = ExportFormat::from_str(format)?;
+	let in_range = |timestamp: MsSinceUnixEpoch| {
+		from.is_none_or(|from| timestamp >= from) && to.is_none_or(|to| timestamp <= to)
+	};
+	let mut listens = Vec::new();
+	let mut imported_plays_count = 0;
+	for track in library.get_tracks().values() {
+		for play in track.plays.iter().flatten() {
+			if in_range(*play) {
+				let duration_ms = (track.duration * 1000.0).round() as i64;
+				listens.push(Listen {
+					track,
+					listened_at: (play - duration_ms).div_euclid(1000),
+				});
+			}
+		}
+		// Imported counts only say that the plays happened sometime in a
+		// period, so count those that overlap the date range
+		for count_object in track.playsImported.iter().flatten() {
+			let overlaps = from.is_none_or(|from| count_object.toDate >= from)
+				&& to.is_none_or(|to| count_object.fromDate <= to);
+			if overlaps {
+				imported_plays_count += count_object.count;
+			}
+		}
+	}
+	listens.sort_by_key(|listen| listen.listened_at);
+
+	let contents = match format {
+		ExportFormat::ListenBrainz => write_listenbrainz(&listens)?,
+		ExportFormat::ScrobblerLog => write_scrobbler_log(&listens),
+	};
+	fs::write(path, contents)
+		.with_context(|| format!("Unable to write {}", path.to_string_lossy()))?;
+	Ok(PlaysExportStatus {
+		listens_count: listens.len() as i64,
+		imported_plays_count,
+	})
+}
+
+/// `format` is "listenbrainz" or "scrobbler_log"
+#[napi(js_name = "export_plays")]
+#[allow(dead_code)]
+pub fn export_plays(
+	path: String,
+	format: String,
+	from_date: Option<MsSinceUnixEpoch>,
+	to_date: Option<MsSinceUnixEpoch>,
+	env: Env,
+) -> Result<PlaysExportStatus> {
+	let data: &Data = get_data(&env);
+	export_listens(&data.library, Path::new(&path), &format, from_date, to_date)
+}
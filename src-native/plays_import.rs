@@ -0,0 +1,841 @@
+use crate::data::Data;
+use crate::data_js::get_data;
+use crate::library_types::{HistoryImport, Library, MsSinceUnixEpoch, TrackID};
+use crate::path_to_json;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use napi::Env;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A play from a log file or listening history
+struct Scrobble {
+	line_num: usize,
+	artist: String,
+	album: Option<String>,
+	title: String,
+	/// Seconds
+	duration: Option<f64>,
+	timestamp: MsSinceUnixEpoch,
+}
+impl Scrobble {
+	fn describe(&self) -> String {
+		format!("Line {}: {} - {}", self.line_num, self.artist, self.title)
+	}
+}
+
+fn filled(value: &str) -> Option<String> {
+	match value.trim() {
+		"" => None,
+		value => Some(value.to_string()),
+	}
+}
+
+/// Parses the Audioscrobbler portable player format used by Rockbox:
+/// `artist\talbum\ttitle\ttracknum\tduration\trating\ttimestamp\tmbid`.
+/// Entries rated `S` were skipped on the player, so they are left out.
+fn parse_scrobbler_log(text: &str, errors: &mut Vec<String>) -> Vec<Scrobble> {
+	let mut scrobbles = Vec::new();
+	// Timestamps are in local time unless the header says otherwise
+	let mut is_utc = false;
+	for (i, line) in text.lines().enumerate() {
+		let line_num = i + 1;
+		let line = line.trim_start_matches('\u{feff}');
+		if let Some(tz) = line.strip_prefix("#TZ/") {
+			is_utc = tz.trim() == "UTC";
+			continue;
+		} else if line.starts_with('#') || line.trim().is_empty() {
+			continue;
+		}
+		let fields: Vec<&str> = line.split('\t').collect();
+		if fields.len() < 7 {
+			errors.push(format!("Line {line_num}: Expected at least 7 fields"));
+			continue;
+		}
+		if fields[5] == "S" {
+			continue;
+		}
+		let timestamp = match fields[6].trim().parse::<i64>() {
+			Ok(secs) if is_utc => secs * 1000,
+			Ok(secs) => match local_seconds_to_ms(secs) {
+				Some(timestamp) => timestamp,
+				None => {
+					errors.push(format!("Line {line_num}: Invalid timestamp"));
+					continue;
+				}
+			},
+			Err(_) => {
+				errors.push(format!("Line {line_num}: Invalid timestamp"));
+				continue;
+			}
+		};
+		scrobbles.push(Scrobble {
+			line_num,
+			artist: fields[0].trim().to_string(),
+			album: filled(fields[1]),
+			title: fields[2].trim().to_string(),
+			duration: fields[4].trim().parse().ok().filter(|d| *d > 0.0),
+			timestamp,
+		});
+	}
+	scrobbles
+}
+
+/// Converts seconds since the epoch in local time to UTC milliseconds
+fn local_seconds_to_ms(secs: i64) -> Option<MsSinceUnixEpoch> {
+	let naive = DateTime::from_timestamp(secs, 0)?.naive_utc();
+	let local = Local.from_local_datetime(&naive).earliest()?;
+	Some(local.timestamp_millis())
+}
+
+/// Splits a CSV line into fields. Quoted fields cannot span multiple lines.
+fn split_csv_line(line: &str) -> Vec<String> {
+	let mut fields = Vec::new();
+	let mut field = String::new();
+	let mut in_quotes = false;
+	let mut chars = line.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' if in_quotes && chars.peek() == Some(&'"') => {
+				field.push('"');
+				chars.next();
+			}
+			'"' => in_quotes = !in_quotes,
+			',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+			c => field.push(c),
+		}
+	}
+	fields.push(field);
+	fields
+}
+
+/// Parses Unix timestamps in seconds or milliseconds, and the date formats
+/// of Last.fm exports. Dates without a time zone are UTC.
+fn parse_csv_date(value: &str) -> Option<MsSinceUnixEpoch> {
+	let value = value.trim();
+	if let Ok(number) = value.parse::<i64>() {
+		return match number {
+			// Too big to be seconds
+			100_000_000_000.. => Some(number),
+			_ => Some(number * 1000),
+		};
+	}
+	if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+		return Some(date.timestamp_millis());
+	}
+	let formats = [
+		"%d %b %Y %H:%M",
+		"%d %b %Y, %H:%M",
+		"%Y-%m-%d %H:%M:%S",
+		"%Y-%m-%dT%H:%M:%S",
+		"%Y-%m-%d %H:%M",
+	];
+	for format in formats {
+		if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+			return Some(date.and_utc().timestamp_millis());
+		}
+	}
+	None
+}
+
+struct CsvColumns {
+	artist: usize,
+	album: Option<usize>,
+	title: usize,
+	date: usize,
+}
+impl CsvColumns {
+	/// Returns `None` if the row is not a header
+	fn from_header(row: &[String]) -> Option<Self> {
+		let find = |names: &[&str]| {
+			names.iter().find_map(|name| {
+				row.iter()
+					.position(|column| column.trim().eq_ignore_ascii_case(name))
+			})
+		};
+		Some(CsvColumns {
+			artist: find(&["artist", "artist_name", "artist name"])?,
+			album: find(&["album", "album_name", "album name"]),
+			title: find(&["track", "title", "track_name", "track name", "name"])?,
+			date: find(&["uts", "timestamp", "date", "utc_time", "time"])?,
+		})
+	}
+}
+
+/// Parses Last.fm CSV exports. Exports without a header have the columns
+/// artist, album, title and date.
+fn parse_lastfm_csv(text: &str, errors: &mut Vec<String>) -> Vec<Scrobble> {
+	let mut scrobbles = Vec::new();
+	let mut columns = None;
+	for (i, line) in text.lines().enumerate() {
+		let line_num = i + 1;
+		let line = line.trim_start_matches('\u{feff}');
+		if line.trim().is_empty() {
+			continue;
+		}
+		let row = split_csv_line(line);
+		let columns = match &columns {
+			Some(columns) => columns,
+			None => {
+				if let Some(header) = CsvColumns::from_header(&row) {
+					columns = Some(header);
+					continue;
+				}
+				columns.insert(CsvColumns {
+					artist: 0,
+					album: Some(1),
+					title: 2,
+					date: 3,
+				})
+			}
+		};
+		let get = |i: usize| row.get(i).map(|field| field.trim()).unwrap_or_default();
+		let Some(timestamp) = parse_csv_date(get(columns.date)) else {
+			errors.push(format!("Line {line_num}: Invalid date"));
+			continue;
+		};
+		let (artist, title) = (get(columns.artist), get(columns.title));
+		if artist.is_empty() || title.is_empty() {
+			errors.push(format!("Line {line_num}: Missing artist or title"));
+			continue;
+		}
+		scrobbles.push(Scrobble {
+			line_num,
+			artist: artist.to_string(),
+			album: columns.album.and_then(|i| filled(get(i))),
+			title: title.to_string(),
+			duration: None,
+			timestamp,
+		});
+	}
+	scrobbles
+}
+
+/// Lowercase with only letters and numbers, so that "AC/DC" matches "ac-dc"
+fn normalize(text: &str) -> String {
+	text.chars()
+		.flat_map(char::to_lowercase)
+		.filter(|c| c.is_alphanumeric())
+		.collect()
+}
+
+/// Removes suffixes like " (Remastered 2011)", " [Live]", " - Radio Edit" and
+/// featured artists
+fn strip_suffixes(text: &str) -> &str {
+	let lowercase = text.to_lowercase();
+	let separators = [
+		" (",
+		" [",
+		" - ",
+		" feat. ",
+		" feat ",
+		" ft. ",
+		" featuring ",
+	];
+	let end = separators
+		.iter()
+		.filter_map(|separator| lowercase.find(separator))
+		.filter(|i| *i > 0)
+		.min();
+	match end {
+		// Lowercasing can change byte offsets
+		Some(end) if text.is_char_boundary(end) => &text[..end],
+		_ => text,
+	}
+}
+
+/// Finds tracks by artist and title, and falls back to comparing them without
+/// suffixes like " (Remastered)"
+struct TrackMatcher<'a> {
+	library: &'a Library,
+	exact: HashMap<(String, String), Vec<TrackID>>,
+	stripped: HashMap<(String, String), Vec<TrackID>>,
+}
+impl<'a> TrackMatcher<'a> {
+	fn new(library: &'a Library) -> Self {
+		let mut matcher = TrackMatcher {
+			library,
+			exact: HashMap::new(),
+			stripped: HashMap::new(),
+		};
+		for (id, track) in library.get_tracks() {
+			let mut artists = vec![&track.artist];
+			if let Some(album_artist) = &track.albumArtist
+				&& album_artist != &track.artist
+			{
+				artists.push(album_artist);
+			}
+			for artist in artists {
+				let key = (normalize(artist), normalize(&track.name));
+				matcher.exact.entry(key).or_default().push(id.clone());
+				let key = (
+					normalize(strip_suffixes(artist)),
+					normalize(strip_suffixes(&track.name)),
+				);
+				matcher.stripped.entry(key).or_default().push(id.clone());
+			}
+		}
+		matcher
+	}
+	/// When several tracks match, the one with the same album and a similar
+	/// duration is preferred
+	fn find(&self, scrobble: &Scrobble) -> Option<TrackID> {
+		let key = (normalize(&scrobble.artist), normalize(&scrobble.title));
+		let stripped_key = (
+			normalize(strip_suffixes(&scrobble.artist)),
+			normalize(strip_suffixes(&scrobble.title)),
+		);
+		let candidates = match self.exact.get(&key) {
+			Some(candidates) => candidates,
+			None => self.stripped.get(&stripped_key)?,
+		};
+		let album = scrobble.album.as_deref().map(normalize);
+		let mut best: Option<(u32, &TrackID)> = None;
+		for id in candidates {
+			let track = self.library.get_track(id).ok()?;
+			let mut score = 0;
+			if album.is_some() && track.albumName.as_deref().map(normalize) == album {
+				score += 2;
+			}
+			if scrobble
+				.duration
+				.is_some_and(|duration| (track.duration - duration).abs() <= 3.0)
+			{
+				score += 1;
+			}
+			if best.is_none_or(|(best_score, _)| score > best_score) {
+				best = Some((score, id));
+			}
+		}
+		best.map(|(_, id)| id.clone())
+	}
+}
+
+#[napi(object)]
+pub struct PlaysImportStatus {
+	/// Lines that could not be parsed
+	pub errors: Vec<String>,
+	/// Entries that did not match any track
+	pub unmatched: Vec<String>,
+	/// Entries that matched a track
+	pub matched_count: i64,
+	/// Matched entries that were already in the track's plays
+	pub duplicate_count: i64,
+	pub added_plays_count: i64,
+	pub updated_tracks_count: i64,
+}
+
+/// Imports plays from a `.scrobbler.log` file or a Last.fm CSV export.
+///
+/// Ferrum records plays when they finish while scrobbles have the start time,
+/// so plays are stored at the start time plus the scrobbled duration, or the
+/// track duration if the entry has none. A play is considered already
+/// recorded if the track has an existing play within a minute of that. Each
+/// existing play can only cover one entry. Plays that are only counted in
+/// `playsImported` have no timestamps, so they cannot be matched.
+pub fn import_plays_file(library: &mut Library, path: &Path) -> Result<PlaysImportStatus> {
+	let bytes = fs::read(path).context("Unable to read file")?;
+	let text = String::from_utf8_lossy(&bytes);
+	let mut errors = Vec::new();
+	let is_scrobbler_log = text
+		.trim_start_matches('\u{feff}')
+		.starts_with("#AUDIOSCROBBLER")
+		|| path.extension().is_some_and(|ext| ext == "log");
+	let scrobbles = match is_scrobbler_log {
+		true => parse_scrobbler_log(&text, &mut errors),
+		false => parse_lastfm_csv(&text, &mut errors),
+	};
+	if scrobbles.is_empty() && !errors.is_empty() {
+		bail!("Unable to parse file: {}", errors[0]);
+	}
+
+	let mut unmatched = Vec::new();
+	// Track ID -> new play finish times, in the order tracks were first matched
+	let mut track_plays = Vec::<(TrackID, Vec<MsSinceUnixEpoch>)>::new();
+	let mut track_plays_indexes = HashMap::<TrackID, usize>::new();
+	let matcher = TrackMatcher::new(library);
+	for scrobble in &scrobbles {
+		let Some(id) = matcher.find(scrobble) else {
+			unmatched.push(scrobble.describe());
+			continue;
+		};
+		let duration = match scrobble.duration {
+			Some(duration) => duration,
+			None => library.get_track(&id)?.duration,
+		};
+		let finished = scrobble.timestamp + (duration * 1000.0).round() as i64;
+		let i = *track_plays_indexes.entry(id.clone()).or_insert_with(|| {
+			track_plays.push((id, Vec::new()));
+			track_plays.len() - 1
+		});
+		track_plays[i].1.push(finished);
+	}
+
+	let matched_count = (scrobbles.len() - unmatched.len()) as i64;
+	let mut added_plays_count = 0;
+	let mut updated_tracks_count = 0;
+	for (id, mut timestamps) in track_plays {
+		let track = library.get_track_mut(&id)?;
+		let plays = track.plays.get_or_insert_with(Vec::new);
+		let mut used = vec![false; plays.len()];
+		let mut new_plays = Vec::new();
+		timestamps.sort_unstable();
+		for timestamp in timestamps {
+			let existing = plays
+				.iter()
+				.zip(&used)
+				.position(|(play, used)| !used && (play - timestamp).abs() <= 60_000);
+			match existing {
+				Some(i) => used[i] = true,
+				None => new_plays.push(timestamp),
+			}
+		}
+		if new_plays.is_empty() {
+			if plays.is_empty() {
+				track.plays = None;
+			}
+			continue;
+		}
+		let added = new_plays.len() as u32;
+		plays.extend(new_plays);
+		plays.sort_unstable();
+		*track.playCount.get_or_insert(0) += added;
+		added_plays_count += i64::from(added);
+		updated_tracks_count += 1;
+	}
+
+	Ok(PlaysImportStatus {
+		errors,
+		unmatched,
+		matched_count,
+		duplicate_count: matched_count - added_plays_count,
+		added_plays_count,
+		updated_tracks_count,
+	})
+}
+
+#[napi(js_name = "import_plays")]
+#[allow(dead_code)]
+pub fn import_plays(path: String, env: Env) -> Result<PlaysImportStatus> {
+	let data: &mut Data = get_data(&env);
+	import_plays_file(&mut data.library, Path::new(&path))
+}
+
+/// An entry in `Streaming_History_Audio_*.json` from Spotify's extended
+/// streaming history export
+#[derive(Deserialize)]
+struct SpotifyEntry {
+	/// When the stream ended, like "2021-03-04T10:21:33Z"
+	ts: String,
+	ms_played: i64,
+	/// Not set for podcast episodes
+	master_metadata_track_name: Option<String>,
+	master_metadata_album_artist_name: Option<String>,
+	master_metadata_album_album_name: Option<String>,
+}
+
+#[napi(object)]
+pub struct SpotifyImportStatus {
+	pub errors: Vec<String>,
+	/// Artist and title of tracks that were not found, with the number of
+	/// entries
+	pub unmatched: Vec<String>,
+	/// Entries within periods that were imported before
+	pub already_imported_count: i64,
+	pub added_plays_count: i64,
+	pub added_skips_count: i64,
+	/// Milliseconds of listening time added
+	pub added_play_time: i64,
+	pub updated_tracks_count: i64,
+}
+
+const SPOTIFY_SOURCE: &str = "spotify";
+
+/// Imports Spotify streaming history files. Entries where at least
+/// `min_ms_played` was played become plays, and shorter ones become skips.
+/// The period each file covers is remembered in `Library.historyImports`, so
+/// importing the same or overlapping files again doesn't duplicate anything.
+pub fn import_spotify_files(
+	library: &mut Library,
+	paths: &[&Path],
+	min_ms_played: i64,
+) -> Result<SpotifyImportStatus> {
+	let mut status = SpotifyImportStatus {
+		errors: Vec::new(),
+		unmatched: Vec::new(),
+		already_imported_count: 0,
+		added_plays_count: 0,
+		added_skips_count: 0,
+		added_play_time: 0,
+		updated_tracks_count: 0,
+	};
+	// Includes files imported earlier in this call, in case they overlap
+	let mut imported_periods: Vec<(MsSinceUnixEpoch, MsSinceUnixEpoch)> =
+		(library.historyImports.iter())
+			.filter(|import| import.source == SPOTIFY_SOURCE)
+			.map(|import| (import.fromDate, import.toDate))
+			.collect();
+	let is_imported = |periods: &[(MsSinceUnixEpoch, MsSinceUnixEpoch)], timestamp| {
+		(periods.iter()).any(|(from, to)| (*from..=*to).contains(&timestamp))
+	};
+
+	// Track ID -> (plays, skips)
+	let mut track_counts =
+		HashMap::<TrackID, (Vec<MsSinceUnixEpoch>, Vec<MsSinceUnixEpoch>)>::new();
+	// Artist and title -> number of entries
+	let mut unmatched = Vec::<(String, i64)>::new();
+	let mut unmatched_indexes = HashMap::<String, usize>::new();
+	let mut new_imports = Vec::new();
+	let matcher = TrackMatcher::new(library);
+	let mut play_time = Vec::new();
+	for path in paths {
+		let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+		let entries: Vec<SpotifyEntry> = path_to_json(&path.to_string_lossy())
+			.with_context(|| format!("Unable to read {file_name}"))?;
+		let mut period: Option<(MsSinceUnixEpoch, MsSinceUnixEpoch)> = None;
+		for (i, entry) in entries.into_iter().enumerate() {
+			let Ok(end) = DateTime::parse_from_rfc3339(&entry.ts) else {
+				status
+					.errors
+					.push(format!("{file_name} entry {}: Invalid date", i + 1));
+				continue;
+			};
+			let end = end.timestamp_millis();
+			period = match period {
+				Some((from, to)) => Some((from.min(end), to.max(end))),
+				None => Some((end, end)),
+			};
+			if is_imported(&imported_periods, end) {
+				status.already_imported_count += 1;
+				continue;
+			}
+			let (Some(title), Some(artist)) = (
+				entry.master_metadata_track_name,
+				entry.master_metadata_album_artist_name,
+			) else {
+				continue;
+			};
+			if entry.ms_played <= 0 {
+				continue;
+			}
+			let scrobble = Scrobble {
+				line_num: i + 1,
+				artist,
+				album: entry.master_metadata_album_album_name,
+				title,
+				duration: None,
+				timestamp: end,
+			};
+			let Some(id) = matcher.find(&scrobble) else {
+				let artist_title = format!("{} - {}", scrobble.artist, scrobble.title);
+				let i = *unmatched_indexes
+					.entry(artist_title.clone())
+					.or_insert_with(|| {
+						unmatched.push((artist_title, 0));
+						unmatched.len() - 1
+					});
+				unmatched[i].1 += 1;
+				continue;
+			};
+			let (plays, skips) = track_counts.entry(id.clone()).or_default();
+			match entry.ms_played >= min_ms_played {
+				true => plays.push(end),
+				false => skips.push(end),
+			}
+			play_time.push((id, end - entry.ms_played, entry.ms_played));
+		}
+		if let Some((from, to)) = period
+			&& !(is_imported(&imported_periods, from) && is_imported(&imported_periods, to))
+		{
+			imported_periods.push((from, to));
+			new_imports.push(HistoryImport {
+				source: SPOTIFY_SOURCE.to_string(),
+				fromDate: from,
+				toDate: to,
+			});
+		}
+	}
+
+	for (id, (new_plays, new_skips)) in track_counts {
+		let track = library.get_track_mut(&id)?;
+		if !new_plays.is_empty() {
+			let added = new_plays.len() as u32;
+			let plays = track.plays.get_or_insert_with(Vec::new);
+			plays.extend(new_plays);
+			plays.sort_unstable();
+			*track.playCount.get_or_insert(0) += added;
+			status.added_plays_count += i64::from(added);
+		}
+		if !new_skips.is_empty() {
+			let added = new_skips.len() as u32;
+			let skips = track.skips.get_or_insert_with(Vec::new);
+			skips.extend(new_skips);
+			skips.sort_unstable();
+			*track.skipCount.get_or_insert(0) += added;
+			status.added_skips_count += i64::from(added);
+		}
+		status.updated_tracks_count += 1;
+	}
+	status.added_play_time = play_time.iter().map(|(_, _, duration)| duration).sum();
+	library.playTime.extend(play_time);
+	library.historyImports.extend(new_imports);
+	for (artist_title, count) in unmatched {
+		status.unmatched.push(format!("{artist_title} ({count})"));
+	}
+	Ok(status)
+}
+
+#[napi(js_name = "import_spotify_history")]
+#[allow(dead_code)]
+pub fn import_spotify_history(
+	paths: Vec<String>,
+	min_ms_played: Option<i64>,
+	env: Env,
+) -> Result<SpotifyImportStatus> {
+	let data: &mut Data = get_data(&env);
+	let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+	// Spotify counts a stream after 30 seconds
+	let min_ms_played = min_ms_played.unwrap_or(30_000);
+	import_spotify_files(&mut data.library, &paths, min_ms_played)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::library_types::Track;
+	use crate::plays_export::export_listens;
+
+	#[test]
+	fn split_csv_line_test() {
+		assert_eq!(
+			split_csv_line(r#"a,"b, c","say ""hi""","#),
+			["a", "b, c", "say \"hi\"", ""]
+		);
+		assert_eq!(split_csv_line(""), [""]);
+	}
+
+	#[test]
+	fn parse_csv_date_test() {
+		let expected = Some(1_609_459_200_000);
+		assert_eq!(parse_csv_date("1609459200"), expected);
+		assert_eq!(parse_csv_date("1609459200000"), expected);
+		assert_eq!(parse_csv_date(" 01 Jan 2021 00:00 "), expected);
+		assert_eq!(parse_csv_date("01 Jan 2021, 00:00"), expected);
+		assert_eq!(parse_csv_date("2021-01-01T01:00:00+01:00"), expected);
+		assert_eq!(parse_csv_date("2021-01-01 00:00:00"), expected);
+		assert_eq!(parse_csv_date("2021-01-01T00:00:00"), expected);
+		assert_eq!(parse_csv_date("2021-01-01 00:00"), expected);
+		assert_eq!(parse_csv_date("yesterday"), None);
+	}
+
+	#[test]
+	fn parse_scrobbler_log_test() {
+		let text = "\u{feff}#AUDIOSCROBBLER/1.1\n\
+			#TZ/UTC\n\
+			#CLIENT/Rockbox\n\
+			Artist\tAlbum\tTitle\t1\t200\tL\t1609459200\t\n\
+			Artist\t\tSkipped\t2\t180\tS\t1609459400\t\n\
+			Artist\tAlbum\tNo Duration\t3\t\tL\t1609459600\n\
+			Too\tFew\tFields\n\
+			Artist\tAlbum\tBad Time\t4\t200\tL\tnever\t\n";
+		let mut errors = Vec::new();
+		let scrobbles = parse_scrobbler_log(text, &mut errors);
+		assert_eq!(scrobbles.len(), 2);
+		assert_eq!(scrobbles[0].line_num, 4);
+		assert_eq!(scrobbles[0].artist, "Artist");
+		assert_eq!(scrobbles[0].album.as_deref(), Some("Album"));
+		assert_eq!(scrobbles[0].title, "Title");
+		assert_eq!(scrobbles[0].duration, Some(200.0));
+		assert_eq!(scrobbles[0].timestamp, 1_609_459_200_000);
+		assert_eq!(scrobbles[1].title, "No Duration");
+		assert_eq!(scrobbles[1].duration, None);
+		assert_eq!(
+			errors,
+			[
+				"Line 7: Expected at least 7 fields",
+				"Line 8: Invalid timestamp"
+			]
+		);
+	}
+
+	#[test]
+	fn parse_lastfm_csv_test() {
+		let with_header = "uts,utc_time,artist,artist_mbid,album,album_mbid,track,track_mbid\n\
+			1609459200,\"01 Jan 2021, 00:00\",Artist,,\"Album, Vol. 1\",,Title,\n\
+			,,Artist,,Album,,Title,\n\
+			1609459300,,,,Album,,Title,\n";
+		let mut errors = Vec::new();
+		let scrobbles = parse_lastfm_csv(with_header, &mut errors);
+		assert_eq!(scrobbles.len(), 1);
+		assert_eq!(scrobbles[0].line_num, 2);
+		assert_eq!(scrobbles[0].album.as_deref(), Some("Album, Vol. 1"));
+		assert_eq!(scrobbles[0].title, "Title");
+		assert_eq!(scrobbles[0].timestamp, 1_609_459_200_000);
+		assert_eq!(
+			errors,
+			["Line 3: Invalid date", "Line 4: Missing artist or title"]
+		);
+
+		let without_header = "Artist,,Title,01 Jan 2021 00:00\n";
+		let mut errors = Vec::new();
+		let scrobbles = parse_lastfm_csv(without_header, &mut errors);
+		assert_eq!(scrobbles.len(), 1);
+		assert_eq!(scrobbles[0].artist, "Artist");
+		assert_eq!(scrobbles[0].album, None);
+		assert_eq!(scrobbles[0].timestamp, 1_609_459_200_000);
+		assert!(errors.is_empty());
+	}
+
+	#[test]
+	fn export_import_round_trip_test() {
+		let track: Track = serde_json::from_value(serde_json::json!({
+			"size": 0,
+			"duration": 200.0,
+			"bitrate": 0.0,
+			"sampleRate": 0.0,
+			"file": "round_trip.mp3",
+			"dateModified": 0,
+			"dateAdded": 0,
+			"name": "Round Trip",
+			"artist": "Ferrum",
+			"albumName": "Tests",
+			"playCount": 2,
+			"plays": [1_609_459_200_000i64, 1_609_462_800_000i64],
+		}))
+		.unwrap();
+		let id = "round_trip_test".to_string();
+		let mut library = Library::new();
+		library.insert_track(id.clone(), track);
+		let plays = library.get_track(&id).unwrap().plays.clone();
+		let path = std::env::temp_dir().join("ferrum_round_trip_test.log");
+		export_listens(&library, &path, "scrobbler_log", None, None).unwrap();
+
+		// Importing into the same library finds the existing plays
+		let status = import_plays_file(&mut library, &path).unwrap();
+		assert_eq!(status.matched_count, 2);
+		assert_eq!(status.duplicate_count, 2);
+		assert_eq!(status.added_plays_count, 0);
+
+		// Importing into a library without the plays restores them
+		let track = library.get_track_mut(&id).unwrap();
+		track.plays = None;
+		track.playCount = None;
+		let status = import_plays_file(&mut library, &path).unwrap();
+		assert_eq!(status.added_plays_count, 2);
+		let track = library.get_track(&id).unwrap();
+		assert_eq!(track.plays, plays);
+		assert_eq!(track.playCount, Some(2));
+		fs::remove_file(&path).unwrap();
+	}
+
+	fn spotify_entry(ts: &str, ms_played: i64, title: Option<&str>) -> serde_json::Value {
+		serde_json::json!({
+			"ts": ts,
+			"ms_played": ms_played,
+			"master_metadata_track_name": title,
+			"master_metadata_album_artist_name": title.map(|_| "Artist"),
+			"master_metadata_album_album_name": title.map(|_| "Album"),
+		})
+	}
+
+	fn spotify_library(id: &str) -> Library {
+		let track: Track = serde_json::from_value(serde_json::json!({
+			"size": 0,
+			"duration": 200.0,
+			"bitrate": 0.0,
+			"sampleRate": 0.0,
+			"file": "spotify.mp3",
+			"dateModified": 0,
+			"dateAdded": 0,
+			"name": "Song",
+			"artist": "Artist",
+		}))
+		.unwrap();
+		let mut library = Library::new();
+		library.insert_track(id.to_string(), track);
+		library
+	}
+
+	#[test]
+	fn import_spotify_files_test() {
+		let id = "spotify_test".to_string();
+		let mut library = spotify_library(&id);
+
+		let first_path = std::env::temp_dir().join("ferrum_spotify_test_1.json");
+		let first = serde_json::json!([
+			spotify_entry("2021-01-01T10:00:00Z", 200_000, Some("Song")),
+			spotify_entry("2021-01-01T10:05:00Z", 10_000, Some("Song")),
+			// Podcast episode
+			spotify_entry("2021-01-01T10:10:00Z", 600_000, None),
+			spotify_entry("2021-01-01T10:15:00Z", 100_000, Some("Unknown")),
+		]);
+		fs::write(&first_path, first.to_string()).unwrap();
+		let second_path = std::env::temp_dir().join("ferrum_spotify_test_2.json");
+		let second = serde_json::json!([
+			spotify_entry("2021-01-01T10:05:00Z", 10_000, Some("Song")),
+			spotify_entry("2021-01-01T10:20:00Z", 60_000, Some("Song")),
+			spotify_entry("2021-01-01T10:25:00Z", 5_000, Some("Song")),
+		]);
+		fs::write(&second_path, second.to_string()).unwrap();
+
+		let status = import_spotify_files(&mut library, &[&first_path], 30_000).unwrap();
+		assert_eq!(status.added_plays_count, 1);
+		assert_eq!(status.added_skips_count, 1);
+		assert_eq!(status.added_play_time, 210_000);
+		assert_eq!(status.already_imported_count, 0);
+		assert_eq!(status.unmatched, ["Artist - Unknown (1)"]);
+		let track = library.get_track(&id).unwrap();
+		assert_eq!(track.plays, Some(vec![1_609_495_200_000]));
+		assert_eq!(track.skips, Some(vec![1_609_495_500_000]));
+		assert_eq!(
+			library.playTime,
+			[
+				(id.clone(), 1_609_495_000_000, 200_000),
+				(id.clone(), 1_609_495_490_000, 10_000),
+			]
+		);
+		assert_eq!(library.historyImports.len(), 1);
+
+		// Importing the same file again adds nothing
+		let status = import_spotify_files(&mut library, &[&first_path], 30_000).unwrap();
+		assert_eq!(status.added_plays_count, 0);
+		assert_eq!(status.added_skips_count, 0);
+		assert_eq!(status.added_play_time, 0);
+		assert_eq!(status.already_imported_count, 4);
+		let track = library.get_track(&id).unwrap();
+		assert_eq!((track.playCount, track.skipCount), (Some(1), Some(1)));
+		assert_eq!(library.playTime.len(), 2);
+		assert_eq!(library.historyImports.len(), 1);
+
+		// Only the entries of an overlapping file that are outside the
+		// imported period are added
+		let status = import_spotify_files(&mut library, &[&second_path], 30_000).unwrap();
+		assert_eq!(status.already_imported_count, 1);
+		assert_eq!(status.added_plays_count, 1);
+		assert_eq!(status.added_skips_count, 1);
+		assert_eq!(status.added_play_time, 65_000);
+		let track = library.get_track(&id).unwrap();
+		assert_eq!((track.playCount, track.skipCount), (Some(2), Some(2)));
+		assert_eq!(
+			track.skips,
+			Some(vec![1_609_495_500_000, 1_609_496_700_000])
+		);
+		assert_eq!(library.playTime.len(), 4);
+		assert_eq!(library.historyImports.len(), 2);
+
+		// Files that overlap within the same import are only counted once
+		let mut library = spotify_library(&id);
+		let paths = [first_path.as_path(), &first_path, &second_path];
+		let status = import_spotify_files(&mut library, &paths, 30_000).unwrap();
+		assert_eq!(status.already_imported_count, 5);
+		assert_eq!(status.added_plays_count, 2);
+		assert_eq!(status.added_skips_count, 2);
+		assert_eq!(status.added_play_time, 275_000);
+		assert_eq!(library.historyImports.len(), 2);
+
+		fs::remove_file(&first_path).unwrap();
+		fs::remove_file(&second_path).unwrap();
+	}
+}
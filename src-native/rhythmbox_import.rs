@@ -0,0 +1,774 @@
+use crate::data_js::get_data;
+use crate::filter::format_keyword;
+use crate::get_now_timestamp;
+use crate::itunes_import::{ImportStatus, parse_file_url};
+use crate::library::Paths;
+use crate::library_types::{
+	CountObject, Library, MsSinceUnixEpoch, PercentInteger, Playlist, SmartPlaylist,
+	SmartPlaylistRules, Track, TrackID, TrackList, new_item_ids_from_track_ids,
+};
+use crate::sys_time_to_timestamp;
+use crate::tracks::import::{FileType, ImportMode, read_audio_properties, read_file_metadata};
+use anyhow::{Context, Result, bail};
+use chrono::{Datelike, NaiveDate};
+use napi::Env;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Deserialize)]
+struct XmlDb {
+	#[serde(rename = "entry", default)]
+	entries: Vec<XmlEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct XmlEntry {
+	/// "song", "iradio", "podcast-post", "ignore" etc
+	#[serde(rename = "@type")]
+	entry_type: String,
+	title: Option<String>,
+	genre: Option<String>,
+	artist: Option<String>,
+	album: Option<String>,
+	album_artist: Option<String>,
+	composer: Option<String>,
+	comment: Option<String>,
+	artist_sortname: Option<String>,
+	album_sortname: Option<String>,
+	album_artist_sortname: Option<String>,
+	composer_sortname: Option<String>,
+	track_number: Option<u32>,
+	disc_number: Option<u32>,
+	/// Julian day of the release date
+	date: Option<i32>,
+	bpm: Option<f64>,
+	/// File URL
+	location: Option<String>,
+	/// Seconds since Unix epoch
+	mtime: Option<i64>,
+	/// Seconds since Unix epoch
+	first_seen: Option<i64>,
+	/// Seconds since Unix epoch
+	last_played: Option<i64>,
+	play_count: Option<u32>,
+	/// 0 to 5
+	rating: Option<f64>,
+}
+impl XmlEntry {
+	fn artist_title(&self) -> String {
+		self.artist.as_deref().unwrap_or_default().to_string()
+			+ " - " + self.title.as_deref().unwrap_or_default()
+	}
+}
+
+#[derive(Deserialize)]
+struct XmlPlaylists {
+	#[serde(rename = "playlist", default)]
+	playlists: Vec<XmlPlaylist>,
+}
+
+#[derive(Deserialize)]
+struct XmlPlaylist {
+	#[serde(rename = "@name")]
+	name: String,
+	/// "static", "automatic" or "queue"
+	#[serde(rename = "@type")]
+	playlist_type: String,
+	#[serde(rename = "@sort-key")]
+	sort_key: Option<String>,
+	/// 1 for descending
+	#[serde(rename = "@sort-direction")]
+	sort_direction: Option<u32>,
+	#[serde(rename = "@limit-count")]
+	limit_count: Option<u32>,
+	/// Megabytes
+	#[serde(rename = "@limit-size")]
+	limit_size: Option<u64>,
+	/// Seconds
+	#[serde(rename = "@limit-time")]
+	limit_time: Option<u64>,
+	/// Track locations of static playlists
+	#[serde(default)]
+	location: Vec<String>,
+	/// Query of automatic playlists
+	conjunction: Option<XmlConjunction>,
+}
+
+/// Criteria that all have to match, except that `<disjunction/>` separates
+/// groups of criteria where any group has to match
+#[derive(Deserialize)]
+struct XmlConjunction {
+	#[serde(rename = "$value", default)]
+	criteria: Vec<XmlCriterion>,
+}
+
+#[derive(Deserialize)]
+struct XmlSubquery {
+	conjunction: Option<XmlConjunction>,
+}
+
+#[derive(Deserialize)]
+struct XmlProperty {
+	#[serde(rename = "@prop")]
+	prop: String,
+	#[serde(rename = "$text", default)]
+	value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum XmlCriterion {
+	Disjunction,
+	Subquery(XmlSubquery),
+	Equals(XmlProperty),
+	NotEqual(XmlProperty),
+	Like(XmlProperty),
+	NotLike(XmlProperty),
+	Prefix(XmlProperty),
+	Suffix(XmlProperty),
+	Greater(XmlProperty),
+	Less(XmlProperty),
+	CurrentTimeWithin(XmlProperty),
+	CurrentTimeNotWithin(XmlProperty),
+	YearEquals(XmlProperty),
+	YearNotEqual(XmlProperty),
+	YearGreater(XmlProperty),
+	YearLess(XmlProperty),
+	#[serde(other)]
+	Other,
+}
+
+fn seconds_to_ms(seconds: i64) -> MsSinceUnixEpoch {
+	seconds * 1000
+}
+fn keep_filled(value: Option<String>) -> Option<String> {
+	value.filter(|v| !v.is_empty())
+}
+
+/// Rhythmbox dates are GLib Julian days, where 1 is January 1st of year 1
+fn julian_day_to_year(day: i32) -> Option<i64> {
+	NaiveDate::from_num_days_from_ce_opt(day).map(|date| date.year().into())
+}
+
+/// Rhythmbox only stores the last play, so the other plays are added as
+/// imported plays between `from` and `to`
+fn get_plays(
+	count: Option<u32>,
+	last_played: Option<i64>,
+	from: MsSinceUnixEpoch,
+	to: MsSinceUnixEpoch,
+) -> (Option<Vec<MsSinceUnixEpoch>>, Option<Vec<CountObject>>) {
+	let count = count.unwrap_or(0);
+	if count == 0 {
+		return (None, None);
+	}
+	let plays = last_played.map(|secs| vec![seconds_to_ms(secs)]);
+	let imported_count = match plays {
+		Some(_) => count - 1,
+		None => count,
+	};
+	let plays_imported = match imported_count {
+		0 => None,
+		_ => Some(vec![CountObject {
+			count: imported_count.into(),
+			fromDate: from,
+			toDate: to,
+			source: Some("rhythmbox".to_string()),
+		}]),
+	};
+	(plays, plays_imported)
+}
+
+/// Parses track but does not move it to `tracks_dir`. Problems with the file
+/// that were worked around are added to `warnings`.
+fn parse_track(
+	entry: XmlEntry,
+	start_time: i64,
+	paths: &Paths,
+	mode: ImportMode,
+	warnings: &mut Vec<String>,
+) -> Result<(PathBuf, Track)> {
+	let location = entry
+		.location
+		.as_deref()
+		.context("Missing track location")?;
+	let path = parse_file_url(location)?;
+
+	// this will also checks if the file exists
+	let file_md = read_file_metadata(&path)?;
+
+	let size = file_md.len() as i64;
+	let audio = read_audio_properties(&path, size, warnings)?;
+	let file_type = FileType::from_path(&path)?;
+
+	let name = entry.title.unwrap_or_default();
+	let artist = entry.artist.unwrap_or_default();
+	let filename = mode.track_file(paths, &path, &artist, &name, &file_type)?;
+
+	let date_added = entry.first_seen.map_or(start_time, seconds_to_ms);
+	let date_modified = match entry.mtime {
+		Some(mtime) => seconds_to_ms(mtime),
+		None => match file_md.modified() {
+			Ok(modified) => sys_time_to_timestamp(&modified),
+			Err(_) => start_time,
+		},
+	};
+	let play_count = entry.play_count.filter(|count| *count > 0);
+	let (plays, plays_imported) = get_plays(play_count, entry.last_played, date_added, start_time);
+
+	let track = Track {
+		size,
+		duration: audio.duration,
+		bitrate: audio.bitrate,
+		sampleRate: audio.sample_rate,
+		file: filename,
+		dateModified: date_modified,
+		dateAdded: date_added,
+		name,
+		importedFrom: Some("rhythmbox".to_string()),
+		originalId: Some(location.to_string()),
+		artist,
+		composer: keep_filled(entry.composer),
+		sortName: None,
+		sortArtist: keep_filled(entry.artist_sortname),
+		sortComposer: keep_filled(entry.composer_sortname),
+		genre: keep_filled(entry.genre),
+		rating: entry
+			.rating
+			.filter(|rating| *rating > 0.0)
+			.map(|rating| (rating.min(5.0) * 20.0).round() as PercentInteger),
+		year: entry
+			.date
+			.filter(|day| *day > 0)
+			.and_then(julian_day_to_year),
+		bpm: entry.bpm.filter(|bpm| *bpm > 0.0),
+		key: None,
+		comments: keep_filled(entry.comment),
+		grouping: None,
+		liked: None,
+		disliked: None,
+		disabled: None,
+		compilation: None,
+		albumName: keep_filled(entry.album),
+		albumArtist: keep_filled(entry.album_artist),
+		sortAlbumName: keep_filled(entry.album_sortname),
+		sortAlbumArtist: keep_filled(entry.album_artist_sortname),
+		trackNum: entry.track_number.filter(|n| *n > 0),
+		trackCount: None,
+		discNum: entry.disc_number.filter(|n| *n > 0),
+		discCount: None,
+		dateImported: Some(start_time),
+		playCount: play_count,
+		plays,
+		playsImported: plays_imported,
+		skipCount: None,
+		skips: None,
+		skipsImported: None,
+		volume: None,
+		startTime: None,
+		stopTime: None,
+		albumRating: None,
+		albumLiked: None,
+		equalizer: None,
+		loudness: None,
+		peak: None,
+		albumLoudness: None,
+		albumPeak: None,
+	};
+
+	Ok((path, track))
+}
+
+/// Text properties. Folded properties are the lowercase versions that
+/// Rhythmbox uses for case-insensitive matching.
+fn get_text_keyword(prop: &str) -> Option<&'static str> {
+	match prop.strip_suffix("-folded").unwrap_or(prop) {
+		"title" => Some("title"),
+		"artist" => Some("artist"),
+		"album" => Some("album"),
+		"album-artist" => Some("albumartist"),
+		"genre" => Some("genre"),
+		"composer" => Some("composer"),
+		"comment" => Some("comment"),
+		_ => None,
+	}
+}
+
+/// Returns the filter query keyword for the criterion, or `Ok(None)` if the
+/// criterion can be ignored
+fn translate_criterion(
+	criterion: &XmlCriterion,
+	errors: &mut Vec<String>,
+) -> Result<Option<String>> {
+	let (op, property) = match criterion {
+		XmlCriterion::Equals(p) => ("equals", p),
+		XmlCriterion::NotEqual(p) => ("not equal", p),
+		XmlCriterion::Like(p) => ("like", p),
+		XmlCriterion::NotLike(p) => ("not like", p),
+		XmlCriterion::Prefix(p) => ("prefix", p),
+		XmlCriterion::Suffix(p) => ("suffix", p),
+		XmlCriterion::Greater(p) => ("greater", p),
+		XmlCriterion::Less(p) => ("less", p),
+		XmlCriterion::CurrentTimeWithin(p) => ("current time within", p),
+		XmlCriterion::CurrentTimeNotWithin(p) => ("current time not within", p),
+		XmlCriterion::YearEquals(p) => ("year equals", p),
+		XmlCriterion::YearNotEqual(p) => ("year not equal", p),
+		XmlCriterion::YearGreater(p) => ("year greater", p),
+		XmlCriterion::YearLess(p) => ("year less", p),
+		XmlCriterion::Disjunction | XmlCriterion::Subquery(_) => bail!("Unexpected criterion"),
+		XmlCriterion::Other => bail!("Unknown criterion is not supported"),
+	};
+	let description = format!("\"{} {op}\"", property.prop);
+	let value = property.value.trim();
+	if let Some(keyword) = get_text_keyword(&property.prop) {
+		match criterion {
+			XmlCriterion::Like(_) => {}
+			// The filter query only supports "contains"
+			XmlCriterion::Equals(_) => {
+				errors.push(format!("Criterion {description} was imported as \"like\""));
+			}
+			_ => bail!("Criterion {description} is not supported"),
+		}
+		let keyword = format_keyword(keyword, value)
+			.with_context(|| format!("Criterion {description} has an unsupported value"))?;
+		return Ok(Some(keyword));
+	}
+	match (criterion, property.prop.as_str()) {
+		// Only songs are imported, so this criterion is redundant
+		(XmlCriterion::Equals(_), "type") if value == "song" => Ok(None),
+		(XmlCriterion::Equals(_), "play-count") => {
+			let count: u32 = value.parse().context("Invalid play count")?;
+			Ok(Some(format!("playcount:{count}")))
+		}
+		(XmlCriterion::Equals(_), "bpm") => {
+			let bpm: f64 = value.parse().context("Invalid BPM")?;
+			Ok(Some(format!("bpm:{bpm}")))
+		}
+		(XmlCriterion::YearEquals(_), "date") => {
+			let day: i32 = value.parse().context("Invalid date")?;
+			let year = julian_day_to_year(day).context("Invalid date")?;
+			Ok(Some(format!("year:{year}")))
+		}
+		_ => bail!("Criterion {description} is not supported"),
+	}
+}
+
+/// Returns the filter query keywords for the conjunction. Errors are returned
+/// if any criterion cannot be translated, since leaving it out would match
+/// different tracks. Approximations are added to `errors`.
+fn translate_conjunction(
+	conjunction: &XmlConjunction,
+	errors: &mut Vec<String>,
+) -> Result<Vec<String>> {
+	let mut groups = vec![Vec::new()];
+	for criterion in &conjunction.criteria {
+		match criterion {
+			XmlCriterion::Disjunction => groups.push(Vec::new()),
+			criterion => groups.last_mut().unwrap().push(criterion),
+		}
+	}
+	groups.retain(|group| !group.is_empty());
+	if groups.len() > 1 {
+		bail!("Matching any criteria is not supported");
+	}
+	let mut keywords = Vec::new();
+	for criterion in groups.into_iter().flatten() {
+		match criterion {
+			XmlCriterion::Subquery(subquery) => {
+				if let Some(conjunction) = &subquery.conjunction {
+					keywords.extend(translate_conjunction(conjunction, errors)?);
+				}
+			}
+			criterion => {
+				if let Some(keyword) = translate_criterion(criterion, errors)? {
+					keywords.push(keyword);
+				}
+			}
+		}
+	}
+	Ok(keywords)
+}
+
+fn translate_sort_key(sort_key: &str) -> Result<&'static str> {
+	match sort_key {
+		"Title" => Ok("name"),
+		"Artist" => Ok("artist"),
+		"Album" => Ok("albumName"),
+		"Genre" => Ok("genre"),
+		"Composer" => Ok("composer"),
+		"Comment" => Ok("comments"),
+		"Track" => Ok("trackNum"),
+		"Year" => Ok("year"),
+		"Duration" | "Time" => Ok("duration"),
+		"Quality" => Ok("bitrate"),
+		"BPM" => Ok("bpm"),
+		"Rating" => Ok("rating"),
+		"PlayCount" => Ok("playCount"),
+		"FirstSeen" => Ok("dateAdded"),
+		_ => bail!("Sorting by {sort_key} is not supported"),
+	}
+}
+
+/// Errors are returned for anything that cannot be translated and would
+/// change which tracks match. Approximations are added to `errors`.
+fn translate_automatic_playlist(
+	xml_playlist: &XmlPlaylist,
+	errors: &mut Vec<String>,
+) -> Result<SmartPlaylistRules> {
+	if xml_playlist.limit_size.is_some() {
+		bail!("Limiting by size is not supported");
+	}
+	let mut rules = SmartPlaylistRules {
+		filterQuery: String::new(),
+		sortKey: "index".to_string(),
+		sortDesc: false,
+		limitCount: xml_playlist.limit_count,
+		limitDuration: xml_playlist.limit_time.map(|secs| secs as f64),
+	};
+	if let Some(conjunction) = &xml_playlist.conjunction {
+		rules.filterQuery = translate_conjunction(conjunction, errors)?.join(" ");
+	}
+	if let Some(sort_key) = &xml_playlist.sort_key {
+		match translate_sort_key(sort_key) {
+			Ok(sort_key) => {
+				rules.sortKey = sort_key.to_string();
+				rules.sortDesc = xml_playlist.sort_direction == Some(1);
+			}
+			// Without a limit, the order doesn't change which tracks match
+			Err(e) if rules.limitCount.is_none() && rules.limitDuration.is_none() => {
+				errors.push(e.to_string());
+			}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(rules)
+}
+
+/// Returns `None` for playlists that should not be imported
+fn import_playlist(
+	xml_playlist: &XmlPlaylist,
+	library: &Library,
+	track_ids_by_location: &HashMap<String, TrackID>,
+	start_time: i64,
+	errors: &mut Vec<String>,
+) -> Option<TrackList> {
+	match xml_playlist.playlist_type.as_str() {
+		"static" => {
+			let mut track_ids = Vec::new();
+			for location in &xml_playlist.location {
+				match track_ids_by_location.get(location) {
+					Some(track_id) => track_ids.push(track_id.clone()),
+					None => errors.push(format!(
+						"Track {location} not found in playlist {}",
+						xml_playlist.name
+					)),
+				}
+			}
+			let mut playlist: Playlist = library.new_playlist(xml_playlist.name.clone(), None);
+			playlist.importedFrom = Some("rhythmbox".to_string());
+			playlist.dateImported = Some(start_time);
+			playlist.dateCreated = None;
+			playlist.tracks = new_item_ids_from_track_ids(&track_ids);
+			Some(TrackList::Playlist(playlist))
+		}
+		"automatic" => {
+			let mut rule_errors = Vec::new();
+			let result = translate_automatic_playlist(xml_playlist, &mut rule_errors);
+			for rule_error in rule_errors {
+				errors.push(format!(
+					"Automatic playlist \"{}\": {rule_error}",
+					xml_playlist.name
+				));
+			}
+			// Rhythmbox doesn't store the tracks of automatic playlists, so
+			// they can't be imported as regular playlists
+			let rules = match result {
+				Ok(rules) => rules,
+				Err(e) => {
+					errors.push(format!(
+						"Skipped automatic playlist \"{}\": {e}",
+						xml_playlist.name
+					));
+					return None;
+				}
+			};
+			let mut smart: SmartPlaylist =
+				library.new_smart_playlist(xml_playlist.name.clone(), None, rules);
+			smart.importedFrom = Some("rhythmbox".to_string());
+			smart.dateImported = Some(start_time);
+			smart.dateCreated = None;
+			Some(TrackList::Smart(smart))
+		}
+		// The play queue
+		"queue" => None,
+		playlist_type => {
+			errors.push(format!(
+				"Skipped playlist \"{}\" with type {playlist_type}",
+				xml_playlist.name
+			));
+			None
+		}
+	}
+}
+
+#[napi]
+pub struct RhythmboxImport {
+	new_library: Mutex<Option<Library>>,
+	/// Rhythmbox path -> Ferrum file
+	rhythmbox_track_paths: Mutex<HashMap<PathBuf, String>>,
+	paths: Paths,
+	mode: ImportMode,
+}
+#[napi]
+impl RhythmboxImport {
+	#[napi(factory)]
+	pub fn new(mode: Option<String>, env: Env) -> napi::Result<Self> {
+		let data = get_data(&env);
+		Ok(Self {
+			new_library: Some(data.library.clone()).into(),
+			rhythmbox_track_paths: HashMap::new().into(),
+			paths: data.paths.clone(),
+			mode: ImportMode::from_option(mode)?,
+		})
+	}
+	/// Imports `rhythmdb.xml`, and `playlists.xml` if it's in the same folder
+	#[napi]
+	pub async fn start(&self, path: String) -> napi::Result<ImportStatus> {
+		Ok(import_rhythmbox(self, Path::new(&path)).await?)
+	}
+	#[napi]
+	pub fn finish(&mut self, env: Env) -> napi::Result<()> {
+		let data = get_data(&env);
+		let rhythmbox_track_paths = &mut *self.rhythmbox_track_paths.lock().unwrap();
+		for (rhythmbox_path, ferrum_file) in rhythmbox_track_paths {
+			let new_path = data.paths.get_track_file_path(ferrum_file);
+			self.mode.transfer_file(rhythmbox_path, &new_path)?;
+		}
+		let new_library = &mut self.new_library.lock().unwrap();
+		data.library = new_library.take().context("Not initialized")?;
+		Ok(())
+	}
+}
+
+async fn import_rhythmbox(rhythmbox_import: &RhythmboxImport, path: &Path) -> Result<ImportStatus> {
+	let new_library_lock = &mut *rhythmbox_import.new_library.lock().unwrap();
+	let library = match new_library_lock {
+		Some(library) => library,
+		None => bail!("Not initialized"),
+	};
+	let mut rhythmbox_track_paths = rhythmbox_import.rhythmbox_track_paths.lock().unwrap();
+	let original_tracks_count = library.get_tracks().len();
+	let original_tracklists_count = library.trackLists.len();
+	let text = fs::read_to_string(path).context("Unable to read file")?;
+	let xml_db: XmlDb = quick_xml::de::from_str(&text).context("Unable to parse")?;
+	let mut errors = Vec::new();
+	let start_time = get_now_timestamp();
+
+	// Rhythmbox location -> Ferrum ID
+	let mut track_ids_by_location = HashMap::<String, TrackID>::new();
+
+	// Radio stations, podcasts etc. are not imported
+	let entries: Vec<_> = (xml_db.entries.into_iter())
+		.filter(|entry| entry.entry_type == "song")
+		.collect();
+	let track_count = entries.len();
+	for (i, entry) in entries.into_iter().enumerate() {
+		println!("Parsing tracks {}/{}", i + 1, track_count);
+		let artist_title = entry.artist_title();
+		let location = entry.location.clone();
+		let mut warnings = Vec::new();
+		let result = parse_track(
+			entry,
+			start_time,
+			&rhythmbox_import.paths,
+			rhythmbox_import.mode,
+			&mut warnings,
+		);
+		for warning in warnings {
+			errors.push(format!("[{artist_title}] {warning}"));
+		}
+		match result {
+			Ok((rhythmbox_path, track)) => {
+				let generated_id = library.generate_id();
+				// immediately insert into library so new generated ids are unique
+				if rhythmbox_import.mode != ImportMode::Reference {
+					rhythmbox_track_paths.insert(rhythmbox_path, track.file.clone());
+				}
+				library.insert_track(generated_id.clone(), track);
+				if let Some(location) = location {
+					track_ids_by_location.insert(location, generated_id);
+				}
+			}
+			Err(e) => errors.push(format!("[{artist_title}] Skipped track: {e}")),
+		}
+	}
+
+	let playlists_path = path.with_file_name("playlists.xml");
+	if playlists_path.exists() {
+		let text = fs::read_to_string(&playlists_path).context("Unable to read playlists.xml")?;
+		let xml_playlists: XmlPlaylists =
+			quick_xml::de::from_str(&text).context("Unable to parse playlists.xml")?;
+		for xml_playlist in &xml_playlists.playlists {
+			let tracklist = import_playlist(
+				xml_playlist,
+				library,
+				&track_ids_by_location,
+				start_time,
+				&mut errors,
+			);
+			if let Some(tracklist) = tracklist {
+				let id = tracklist.id().to_string();
+				// immediately insert into library so new generated ids are unique
+				library.trackLists.insert(id.clone(), tracklist);
+				library.get_root_tracklist_mut()?.children.push(id);
+			}
+		}
+	} else {
+		errors.push("No playlists.xml file found next to the database file".into());
+	}
+
+	Ok(ImportStatus {
+		errors,
+		tracks_count: (library.get_tracks().len() - original_tracks_count) as i64,
+		playlists_count: (library.trackLists.len() - original_tracklists_count) as i64,
+		updated_tracks_count: 0,
+		updated_playlists_count: 0,
+		added_plays_count: 0,
+		added_skips_count: 0,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_playlist(xml: &str) -> XmlPlaylist {
+		let xml = format!("<rhythmdb-playlists>{xml}</rhythmdb-playlists>");
+		let mut playlists: XmlPlaylists = quick_xml::de::from_str(&xml).unwrap();
+		playlists.playlists.remove(0)
+	}
+
+	#[test]
+	fn translate_conjunction_test() {
+		let playlist = parse_playlist(
+			r#"<playlist name="Rock" type="automatic">
+				<conjunction>
+					<like prop="genre-folded">rock</like>
+					<equals prop="type">song</equals>
+					<subquery>
+						<conjunction>
+							<equals prop="play-count">3</equals>
+							<year-equals prop="date">730120</year-equals>
+						</conjunction>
+					</subquery>
+					<equals prop="artist-folded">the band</equals>
+				</conjunction>
+			</playlist>"#,
+		);
+		let mut errors = Vec::new();
+		let keywords = translate_conjunction(playlist.conjunction.as_ref().unwrap(), &mut errors);
+		assert_eq!(
+			keywords.unwrap(),
+			[
+				"genre:rock",
+				"playcount:3",
+				"year:2000",
+				"artist:\"the band\""
+			]
+		);
+		// "equals" is approximated as "like"
+		assert_eq!(errors.len(), 1);
+	}
+
+	#[test]
+	fn translate_conjunction_unsupported_test() {
+		let any = parse_playlist(
+			r#"<playlist name="Any" type="automatic">
+				<conjunction>
+					<like prop="genre-folded">rock</like>
+					<disjunction/>
+					<like prop="genre-folded">jazz</like>
+				</conjunction>
+			</playlist>"#,
+		);
+		let mut errors = Vec::new();
+		assert!(translate_conjunction(any.conjunction.as_ref().unwrap(), &mut errors).is_err());
+
+		let nested = parse_playlist(
+			r#"<playlist name="Rated" type="automatic">
+				<conjunction>
+					<like prop="genre-folded">rock</like>
+					<subquery>
+						<conjunction>
+							<greater prop="rating">3</greater>
+						</conjunction>
+					</subquery>
+				</conjunction>
+			</playlist>"#,
+		);
+		assert!(translate_conjunction(nested.conjunction.as_ref().unwrap(), &mut errors).is_err());
+		assert!(errors.is_empty());
+	}
+
+	#[test]
+	fn import_automatic_playlist_test() {
+		let library = Library::new();
+		let mut errors = Vec::new();
+		let mut import = |xml: &str| {
+			import_playlist(
+				&parse_playlist(xml),
+				&library,
+				&HashMap::new(),
+				0,
+				&mut errors,
+			)
+		};
+
+		let ordered = import(
+			r#"<playlist name="Ordered" type="automatic" sort-key="PlayCount" sort-direction="1" limit-count="25">
+				<conjunction><like prop="title-folded">love</like></conjunction>
+			</playlist>"#,
+		);
+		let Some(TrackList::Smart(smart)) = ordered else {
+			panic!("Expected a smart playlist");
+		};
+		assert_eq!(smart.rules.filterQuery, "title:love");
+		assert_eq!(smart.rules.sortKey, "playCount");
+		assert!(smart.rules.sortDesc);
+		assert_eq!(smart.rules.limitCount, Some(25));
+
+		// The order only matters when there is a limit
+		let unordered = import(
+			r#"<playlist name="Unordered" type="automatic" sort-key="LastSeen">
+				<conjunction><like prop="title-folded">love</like></conjunction>
+			</playlist>"#,
+		);
+		assert!(matches!(unordered, Some(TrackList::Smart(_))));
+		let limited = import(
+			r#"<playlist name="Limited" type="automatic" sort-key="LastSeen" limit-count="10">
+				<conjunction><like prop="title-folded">love</like></conjunction>
+			</playlist>"#,
+		);
+		assert!(limited.is_none());
+
+		let unsupported = import(
+			r#"<playlist name="Unsupported" type="automatic">
+				<conjunction><greater prop="rating">3</greater></conjunction>
+			</playlist>"#,
+		);
+		assert!(unsupported.is_none());
+		let size_limit = import(
+			r#"<playlist name="Size" type="automatic" limit-size="100">
+				<conjunction><like prop="title-folded">love</like></conjunction>
+			</playlist>"#,
+		);
+		assert!(size_limit.is_none());
+
+		assert_eq!(errors.len(), 4);
+		assert!(errors[1].starts_with("Skipped automatic playlist \"Limited\""));
+	}
+}
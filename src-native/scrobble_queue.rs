@@ -0,0 +1,668 @@
+use crate::data_js::get_data;
+use crate::get_now_timestamp;
+use crate::library_types::MsSinceUnixEpoch;
+use anyhow::{Context, Result, anyhow};
+use atomicwrites::AtomicFile;
+use atomicwrites::OverwriteBehavior::AllowOverwrite;
+use napi::Env;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.listenbrainz.org";
+/// ListenBrainz accepts up to 1000 listens per request
+const MAX_BATCH_SIZE: usize = 1000;
+const MIN_RETRY_DELAY: i64 = 30_000;
+const MAX_RETRY_DELAY: i64 = 60 * 60_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingListen {
+	/// Unique in the queue, so submitted listens can be removed even if more
+	/// were added during the submission
+	pub id: String,
+	/// Seconds since Unix epoch when the track started playing
+	pub listened_at: i64,
+	pub artist: String,
+	pub title: String,
+	pub album: Option<String>,
+	pub duration_ms: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct ScrobbleQueueState {
+	base_url: Option<String>,
+	token: Option<String>,
+	listens: Vec<PendingListen>,
+	/// Failed submissions in a row
+	failures: u32,
+	retry_at: Option<MsSinceUnixEpoch>,
+}
+
+pub enum SubmitError {
+	/// The server rejected the listens, so retrying won't help
+	Rejected,
+	/// Network errors, authentication errors, rate limits and server errors
+	Retry(String),
+}
+
+pub trait ScrobbleBackend {
+	fn submit(&self, listens: &[PendingListen]) -> Result<(), SubmitError>;
+}
+
+pub struct ListenBrainzBackend {
+	pub base_url: String,
+	pub token: String,
+}
+impl ListenBrainzBackend {
+	fn body(listens: &[PendingListen]) -> serde_json::Value {
+		let payload: Vec<_> = listens
+			.iter()
+			.map(|listen| {
+				serde_json::json!({
+					"listened_at": listen.listened_at,
+					"track_metadata": {
+						"artist_name": listen.artist,
+						"track_name": listen.title,
+						"release_name": listen.album,
+						"additional_info": {
+							"duration_ms": listen.duration_ms,
+							"media_player": "Ferrum",
+							"submission_client": "Ferrum",
+						},
+					},
+				})
+			})
+			.collect();
+		let listen_type = match listens.len() {
+			1 => "single",
+			_ => "import",
+		};
+		serde_json::json!({ "listen_type": listen_type, "payload": payload })
+	}
+}
+impl ScrobbleBackend for ListenBrainzBackend {
+	fn submit(&self, listens: &[PendingListen]) -> Result<(), SubmitError> {
+		let url = format!("{}/1/submit-listens", self.base_url.trim_end_matches('/'));
+		let agent: ureq::Agent = ureq::Agent::config_builder()
+			.timeout_global(Some(Duration::from_secs(30)))
+			.build()
+			.into();
+		let body = Self::body(listens).to_string();
+		let result = agent
+			.post(&url)
+			.header("Authorization", format!("Token {}", self.token))
+			.content_type("application/json")
+			.send(body);
+		match result {
+			Ok(_) => Ok(()),
+			Err(ureq::Error::StatusCode(400)) => Err(SubmitError::Rejected),
+			Err(e) => Err(SubmitError::Retry(e.to_string())),
+		}
+	}
+}
+
+/// Exponential backoff, starting at 30 seconds and capped at an hour
+fn retry_delay(failures: u32) -> i64 {
+	let factor = 2_i64.saturating_pow(failures.saturating_sub(1));
+	MIN_RETRY_DELAY.saturating_mul(factor).min(MAX_RETRY_DELAY)
+}
+
+/// Listens waiting to be submitted. Every change is saved to disk before it
+/// takes effect, so listens survive restarts. Listens are only removed after
+/// the server accepted them, so a crash during a submission can make them get
+/// submitted again, but they keep the same timestamp and ListenBrainz ignores
+/// duplicates of a listen.
+pub struct ScrobbleQueue {
+	file_path: String,
+	state: ScrobbleQueueState,
+	/// Set if the file could not be loaded or moved aside, since saving would
+	/// overwrite it
+	save_error: Option<String>,
+}
+impl ScrobbleQueue {
+	fn read(file_path: &str) -> Result<ScrobbleQueueState> {
+		let bytes = match fs::read(file_path) {
+			Ok(bytes) => bytes,
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Default::default()),
+			Err(e) => return Err(e).context("Error reading scrobble queue"),
+		};
+		serde_cbor::from_slice(&bytes).context("Error decoding scrobble queue")
+	}
+	/// A missing file is an empty queue. If the file can't be read, it's
+	/// moved to a backup so that it isn't overwritten, and the queue starts
+	/// empty. The error is returned along with the queue.
+	pub fn load(file_path: &str) -> (ScrobbleQueue, Option<anyhow::Error>) {
+		let mut queue = ScrobbleQueue {
+			file_path: file_path.to_string(),
+			state: Default::default(),
+			save_error: None,
+		};
+		let error = match Self::read(file_path) {
+			Ok(state) => {
+				queue.state = state;
+				return (queue, None);
+			}
+			Err(e) => e,
+		};
+		let backup_path = format!("{file_path}.{}.bak", get_now_timestamp());
+		let error = match fs::rename(file_path, &backup_path) {
+			Ok(()) => error.context(format!("The file was moved to {backup_path}")),
+			Err(rename_error) => {
+				queue.save_error = Some(format!(
+					"Scrobble queue was not saved, since the file could not be read or moved: {rename_error}"
+				));
+				error
+			}
+		};
+		(queue, Some(error))
+	}
+	fn save(&self) -> Result<()> {
+		if let Some(save_error) = &self.save_error {
+			return Err(anyhow!("{save_error}"));
+		}
+		let bytes = serde_cbor::to_vec(&self.state).context("Error encoding scrobble queue")?;
+		let af = AtomicFile::new(&self.file_path, AllowOverwrite);
+		af.write(|f| f.write_all(&bytes))
+			.context("Error writing scrobble queue")?;
+		Ok(())
+	}
+	pub fn is_enabled(&self) -> bool {
+		self.state.token.is_some()
+	}
+	pub fn configure(&mut self, base_url: Option<String>, token: Option<String>) -> Result<()> {
+		self.state.base_url = base_url.filter(|url| !url.is_empty());
+		self.state.token = token.filter(|token| !token.is_empty());
+		// Retry right away with the new settings
+		self.state.failures = 0;
+		self.state.retry_at = None;
+		self.save()
+	}
+	pub fn backend(&self) -> Option<ListenBrainzBackend> {
+		Some(ListenBrainzBackend {
+			base_url: (self.state.base_url.as_deref())
+				.unwrap_or(DEFAULT_BASE_URL)
+				.to_string(),
+			token: self.state.token.clone()?,
+		})
+	}
+	pub fn add(&mut self, listen: PendingListen) -> Result<()> {
+		self.state.listens.push(listen);
+		self.save()
+	}
+	fn status(&self) -> ScrobbleFlushStatus {
+		ScrobbleFlushStatus {
+			submitted_count: 0,
+			rejected: Vec::new(),
+			pending_count: self.state.listens.len() as i64,
+			error: None,
+			retry_at: self.state.retry_at,
+		}
+	}
+	fn remove(&mut self, ids: &[String]) {
+		self.state
+			.listens
+			.retain(|listen| !ids.contains(&listen.id));
+	}
+}
+
+#[napi(object)]
+pub struct ScrobbleFlushStatus {
+	pub submitted_count: i64,
+	/// Listens that the server rejected, which were removed from the queue
+	pub rejected: Vec<String>,
+	pub pending_count: i64,
+	/// The error of a failed submission
+	pub error: Option<String>,
+	/// When the next submission will be attempted, if it failed
+	pub retry_at: Option<MsSinceUnixEpoch>,
+}
+
+/// Submits the listens that are ready, in batches. If a batch is rejected,
+/// its listens are submitted one by one so that only the invalid ones are
+/// dropped.
+pub fn flush_queue(
+	queue: &Mutex<ScrobbleQueue>,
+	backend: &dyn ScrobbleBackend,
+) -> Result<ScrobbleFlushStatus> {
+	let listens = {
+		let queue = queue.lock().unwrap();
+		match queue.state.retry_at {
+			Some(retry_at) if retry_at > get_now_timestamp() => return Ok(queue.status()),
+			_ => queue.state.listens.clone(),
+		}
+	};
+	let mut submitted = Vec::new();
+	let mut rejected = Vec::new();
+	let mut error = None;
+	'batches: for batch in listens.chunks(MAX_BATCH_SIZE) {
+		match backend.submit(batch) {
+			Ok(()) => submitted.extend(batch.iter().map(|listen| listen.id.clone())),
+			Err(SubmitError::Retry(e)) => {
+				error = Some(e);
+				break;
+			}
+			Err(SubmitError::Rejected) => {
+				for listen in batch {
+					match backend.submit(std::slice::from_ref(listen)) {
+						Ok(()) => submitted.push(listen.id.clone()),
+						Err(SubmitError::Rejected) => rejected.push(listen.clone()),
+						Err(SubmitError::Retry(e)) => {
+							error = Some(e);
+							break 'batches;
+						}
+					}
+				}
+			}
+		}
+	}
+
+	let mut queue = queue.lock().unwrap();
+	queue.remove(&submitted);
+	let rejected_ids: Vec<_> = rejected.iter().map(|listen| listen.id.clone()).collect();
+	queue.remove(&rejected_ids);
+	match error {
+		Some(_) => {
+			queue.state.failures += 1;
+			queue.state.retry_at = Some(get_now_timestamp() + retry_delay(queue.state.failures));
+		}
+		None => {
+			queue.state.failures = 0;
+			queue.state.retry_at = None;
+		}
+	}
+	queue.save()?;
+	Ok(ScrobbleFlushStatus {
+		submitted_count: submitted.len() as i64,
+		rejected: rejected
+			.iter()
+			.map(|listen| format!("{} - {}", listen.artist, listen.title))
+			.collect(),
+		pending_count: queue.state.listens.len() as i64,
+		error,
+		retry_at: queue.state.retry_at,
+	})
+}
+
+#[napi(object)]
+pub struct ScrobblerConfig {
+	pub base_url: Option<String>,
+	pub token: Option<String>,
+}
+
+#[napi]
+pub struct Scrobbler {
+	queue: Arc<Mutex<ScrobbleQueue>>,
+	/// Held while flushing so that listens aren't submitted twice
+	flush_lock: Arc<Mutex<()>>,
+	load_error: Option<String>,
+}
+#[napi]
+impl Scrobbler {
+	/// Loads the queue from `Paths.scrobble_queue_file`
+	#[napi(factory)]
+	pub fn load(env: Env) -> Self {
+		let data = get_data(&env);
+		let (queue, load_error) = ScrobbleQueue::load(&data.paths.scrobble_queue_file);
+		Self {
+			queue: Arc::new(Mutex::new(queue)),
+			flush_lock: Arc::new(Mutex::new(())),
+			load_error: load_error.map(|e| format!("{e:#}")),
+		}
+	}
+	/// Why the queue file could not be loaded, in which case the queue
+	/// started empty
+	#[napi]
+	pub fn load_error(&self) -> Option<String> {
+		self.load_error.clone()
+	}
+	/// Submitting is disabled without a token. The base URL defaults to
+	/// ListenBrainz.
+	#[napi]
+	pub fn configure(&self, base_url: Option<String>, token: Option<String>) -> napi::Result<()> {
+		Ok(self.queue.lock().unwrap().configure(base_url, token)?)
+	}
+	#[napi]
+	pub fn config(&self) -> ScrobblerConfig {
+		let queue = self.queue.lock().unwrap();
+		ScrobblerConfig {
+			base_url: queue.state.base_url.clone(),
+			token: queue.state.token.clone(),
+		}
+	}
+	/// Queues a play of the track that just finished. Does nothing if
+	/// submitting is disabled.
+	#[napi]
+	pub fn add(&self, track_id: String, env: Env) -> napi::Result<()> {
+		let mut queue = self.queue.lock().unwrap();
+		if !queue.is_enabled() {
+			return Ok(());
+		}
+		let data = get_data(&env);
+		let track = data.library.get_track(&track_id)?;
+		let duration_ms = (track.duration * 1000.0).round() as i64;
+		queue.add(PendingListen {
+			id: nanoid::nanoid!(),
+			listened_at: (get_now_timestamp() - duration_ms).div_euclid(1000),
+			artist: track.artist.clone(),
+			title: track.name.clone(),
+			album: track.albumName.clone(),
+			duration_ms,
+		})?;
+		Ok(())
+	}
+	#[napi]
+	pub fn pending_count(&self) -> i64 {
+		self.queue.lock().unwrap().state.listens.len() as i64
+	}
+	/// Submits queued listens, unless a failed submission is waiting to be
+	/// retried
+	#[napi]
+	pub async fn flush(&self) -> napi::Result<ScrobbleFlushStatus> {
+		let queue = self.queue.clone();
+		let flush_lock = self.flush_lock.clone();
+		let status = tokio::task::spawn_blocking(move || {
+			let _flushing = flush_lock.lock().unwrap();
+			let backend = queue.lock().unwrap().backend();
+			match backend {
+				Some(backend) => flush_queue(&queue, &backend),
+				None => Ok(queue.lock().unwrap().status()),
+			}
+		})
+		.await
+		.context("Scrobble thread failed")??;
+		Ok(status)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{BufRead, BufReader, Read};
+	use std::net::TcpListener;
+	use std::path::{Path, PathBuf};
+	use std::thread;
+
+	fn temp_file(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("ferrum_scrobble_{name}.cbor"));
+		let _ = fs::remove_file(&path);
+		path
+	}
+
+	fn listen(id: &str) -> PendingListen {
+		PendingListen {
+			id: id.to_string(),
+			listened_at: 1_609_459_200,
+			artist: "Artist".to_string(),
+			title: id.to_string(),
+			album: None,
+			duration_ms: 200_000,
+		}
+	}
+
+	fn queue_with(path: &Path, ids: &[&str]) -> Mutex<ScrobbleQueue> {
+		let (mut queue, error) = ScrobbleQueue::load(&path.to_string_lossy());
+		assert!(error.is_none());
+		for id in ids {
+			queue.add(listen(id)).unwrap();
+		}
+		Mutex::new(queue)
+	}
+
+	/// Rejects batches that contain a listen with one of `rejected_ids`
+	struct FakeBackend {
+		rejected_ids: Vec<&'static str>,
+		retry_error: Option<&'static str>,
+		/// IDs of each submitted batch
+		calls: Mutex<Vec<Vec<String>>>,
+	}
+	impl FakeBackend {
+		fn new(rejected_ids: Vec<&'static str>, retry_error: Option<&'static str>) -> Self {
+			FakeBackend {
+				rejected_ids,
+				retry_error,
+				calls: Mutex::new(Vec::new()),
+			}
+		}
+	}
+	impl ScrobbleBackend for FakeBackend {
+		fn submit(&self, listens: &[PendingListen]) -> Result<(), SubmitError> {
+			let ids = listens.iter().map(|listen| listen.id.clone()).collect();
+			self.calls.lock().unwrap().push(ids);
+			if let Some(error) = self.retry_error {
+				return Err(SubmitError::Retry(error.to_string()));
+			}
+			match listens
+				.iter()
+				.any(|listen| self.rejected_ids.contains(&listen.id.as_str()))
+			{
+				true => Err(SubmitError::Rejected),
+				false => Ok(()),
+			}
+		}
+	}
+
+	#[test]
+	fn retry_delay_test() {
+		assert_eq!(retry_delay(1), 30_000);
+		assert_eq!(retry_delay(2), 60_000);
+		assert_eq!(retry_delay(3), 120_000);
+		assert_eq!(retry_delay(8), MAX_RETRY_DELAY);
+		assert_eq!(retry_delay(100), MAX_RETRY_DELAY);
+	}
+
+	#[test]
+	fn flush_rejected_batch_test() {
+		let path = temp_file("rejected_batch");
+		let queue = queue_with(&path, &["a", "bad", "c"]);
+		let backend = FakeBackend::new(vec!["bad"], None);
+		let status = flush_queue(&queue, &backend).unwrap();
+
+		// The rejected batch is split up so only the invalid listen is dropped
+		let calls = backend.calls.lock().unwrap().clone();
+		assert_eq!(
+			calls,
+			[vec!["a", "bad", "c"], vec!["a"], vec!["bad"], vec!["c"]]
+		);
+		assert_eq!(status.submitted_count, 2);
+		assert_eq!(status.rejected, ["Artist - bad"]);
+		assert_eq!(status.pending_count, 0);
+		assert_eq!(status.error, None);
+		assert_eq!(status.retry_at, None);
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn flush_batches_test() {
+		let path = temp_file("batches");
+		let ids: Vec<String> = (0..MAX_BATCH_SIZE + 1).map(|i| i.to_string()).collect();
+		let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+		let queue = queue_with(&path, &ids);
+		let backend = FakeBackend::new(Vec::new(), None);
+		let status = flush_queue(&queue, &backend).unwrap();
+		let calls = backend.calls.lock().unwrap();
+		assert_eq!(calls.len(), 2);
+		assert_eq!(calls[0].len(), MAX_BATCH_SIZE);
+		assert_eq!(calls[1].len(), 1);
+		assert_eq!(status.submitted_count, (MAX_BATCH_SIZE + 1) as i64);
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn flush_backoff_test() {
+		let path = temp_file("backoff");
+		let queue = queue_with(&path, &["a"]);
+		let failing = FakeBackend::new(Vec::new(), Some("Server error"));
+
+		let before = get_now_timestamp();
+		let status = flush_queue(&queue, &failing).unwrap();
+		assert_eq!(status.error.as_deref(), Some("Server error"));
+		assert_eq!(status.pending_count, 1);
+		let retry_at = status.retry_at.unwrap();
+		assert!(retry_at >= before + MIN_RETRY_DELAY);
+		assert!(retry_at <= get_now_timestamp() + MIN_RETRY_DELAY);
+
+		// Nothing is submitted until `retry_at`
+		let working = FakeBackend::new(Vec::new(), None);
+		let status = flush_queue(&queue, &working).unwrap();
+		assert!(working.calls.lock().unwrap().is_empty());
+		assert_eq!(status.retry_at, Some(retry_at));
+		assert_eq!(status.pending_count, 1);
+
+		// Each failure in a row doubles the delay
+		queue.lock().unwrap().state.retry_at = Some(0);
+		let before = get_now_timestamp();
+		let status = flush_queue(&queue, &failing).unwrap();
+		assert!(status.retry_at.unwrap() >= before + 2 * MIN_RETRY_DELAY);
+		assert_eq!(queue.lock().unwrap().state.failures, 2);
+
+		// A successful submission resets the backoff
+		queue.lock().unwrap().state.retry_at = Some(0);
+		let status = flush_queue(&queue, &working).unwrap();
+		assert_eq!(status.submitted_count, 1);
+		assert_eq!(status.retry_at, None);
+		assert_eq!(queue.lock().unwrap().state.failures, 0);
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn load_test() {
+		let path = temp_file("load");
+		let path_str = path.to_string_lossy().to_string();
+
+		// A missing file is an empty queue
+		let queue = queue_with(&path, &["a", "b"]);
+		let failing = FakeBackend::new(Vec::new(), Some("Offline"));
+		flush_queue(&queue, &failing).unwrap();
+
+		let (loaded, error) = ScrobbleQueue::load(&path_str);
+		assert!(error.is_none());
+		let ids: Vec<_> = loaded.state.listens.iter().map(|l| l.id.as_str()).collect();
+		assert_eq!(ids, ["a", "b"]);
+		assert_eq!(loaded.state.failures, 1);
+		assert!(loaded.state.retry_at.is_some());
+
+		let loaded = Mutex::new(loaded);
+		loaded.lock().unwrap().state.retry_at = None;
+		flush_queue(&loaded, &FakeBackend::new(Vec::new(), None)).unwrap();
+		let (loaded, error) = ScrobbleQueue::load(&path_str);
+		assert!(error.is_none());
+		assert!(loaded.state.listens.is_empty());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn load_corrupt_test() {
+		let path = temp_file("corrupt");
+		let path_str = path.to_string_lossy().to_string();
+		fs::write(&path, b"not cbor").unwrap();
+
+		let (mut queue, error) = ScrobbleQueue::load(&path_str);
+		let error = format!("{:#}", error.unwrap());
+		assert!(queue.state.listens.is_empty());
+		let backup_path = error
+			.split_once("The file was moved to ")
+			.and_then(|(_, rest)| rest.split_once(':'))
+			.map(|(backup_path, _)| backup_path.to_string())
+			.unwrap();
+		assert_eq!(fs::read(&backup_path).unwrap(), b"not cbor");
+		assert!(!path.exists());
+
+		queue.add(listen("a")).unwrap();
+		assert_eq!(fs::read(&backup_path).unwrap(), b"not cbor");
+		fs::remove_file(&path).unwrap();
+		fs::remove_file(&backup_path).unwrap();
+	}
+
+	/// Request line, authorization header and body
+	type Request = (String, String, String);
+
+	/// Responds to one request per status code, and returns the requests
+	fn serve(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<Request>>) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let base_url = format!("http://{}", listener.local_addr().unwrap());
+		let handle = thread::spawn(move || {
+			let mut requests = Vec::new();
+			for status in statuses {
+				let (stream, _) = listener.accept().unwrap();
+				let mut reader = BufReader::new(&stream);
+				let mut request_line = String::new();
+				reader.read_line(&mut request_line).unwrap();
+				let mut authorization = String::new();
+				let mut content_length = 0;
+				loop {
+					let mut line = String::new();
+					reader.read_line(&mut line).unwrap();
+					let line = line.trim_end();
+					if line.is_empty() {
+						break;
+					}
+					let (name, value) = line.split_once(": ").unwrap();
+					match name.to_lowercase().as_str() {
+						"authorization" => authorization = value.to_string(),
+						"content-length" => content_length = value.parse().unwrap(),
+						_ => {}
+					}
+				}
+				let mut body = vec![0; content_length];
+				reader.read_exact(&mut body).unwrap();
+				let response = format!(
+					"HTTP/1.1 {status} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+				);
+				(&stream).write_all(response.as_bytes()).unwrap();
+				requests.push((
+					request_line.trim_end().to_string(),
+					authorization,
+					String::from_utf8(body).unwrap(),
+				));
+			}
+			requests
+		});
+		(base_url, handle)
+	}
+
+	#[test]
+	fn flush_listenbrainz_test() {
+		let path = temp_file("listenbrainz");
+		let queue = queue_with(&path, &["a", "bad"]);
+		let (base_url, server) = serve(vec![400, 200, 400, 503]);
+		let backend = ListenBrainzBackend {
+			base_url: base_url + "/",
+			token: "secret".to_string(),
+		};
+
+		let status = flush_queue(&queue, &backend).unwrap();
+		assert_eq!(status.submitted_count, 1);
+		assert_eq!(status.rejected, ["Artist - bad"]);
+		assert_eq!(status.pending_count, 0);
+
+		queue.lock().unwrap().add(listen("c")).unwrap();
+		let status = flush_queue(&queue, &backend).unwrap();
+		assert!(status.error.is_some());
+		assert!(status.retry_at.is_some());
+		assert_eq!(status.pending_count, 1);
+
+		let requests = server.join().unwrap();
+		assert_eq!(requests.len(), 4);
+		for (request_line, authorization, _) in &requests {
+			assert_eq!(request_line, "POST /1/submit-listens HTTP/1.1");
+			assert_eq!(authorization, "Token secret");
+		}
+		let bodies: Vec<serde_json::Value> = requests
+			.iter()
+			.map(|(_, _, body)| serde_json::from_str(body).unwrap())
+			.collect();
+		assert_eq!(bodies[0]["listen_type"], "import");
+		assert_eq!(bodies[0]["payload"].as_array().unwrap().len(), 2);
+		assert_eq!(bodies[1]["listen_type"], "single");
+		assert_eq!(bodies[1]["payload"][0]["track_metadata"]["track_name"], "a");
+		assert_eq!(bodies[1]["payload"][0]["listened_at"], 1_609_459_200);
+		assert_eq!(
+			bodies[2]["payload"][0]["track_metadata"]["track_name"],
+			"bad"
+		);
+		assert_eq!(bodies[3]["payload"][0]["track_metadata"]["track_name"], "c");
+		fs::remove_file(&path).unwrap();
+	}
+}
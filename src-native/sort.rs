@@ -20,12 +20,14 @@ fn get_field_str<'a>(track: &'a Track, sort_key: &str) -> Option<&'a String> {
 		"sortArtist" => track.sortArtist.as_ref(),
 		"sortComposer" => track.sortComposer.as_ref(),
 		"genre" => track.genre.as_ref(),
+		"key" => track.key.as_ref(),
 		"comments" => track.comments.as_ref(),
 		"grouping" => track.grouping.as_ref(),
 		"albumName" => track.albumName.as_ref(),
 		"albumArtist" => track.albumArtist.as_ref(),
 		"sortAlbumName" => track.sortAlbumName.as_ref(),
 		"sortAlbumArtist" => track.sortAlbumArtist.as_ref(),
+		"equalizer" => track.equalizer.as_ref(),
 		_ => panic!("Field type not found for {}", sort_key),
 	}
 }
@@ -36,6 +38,8 @@ fn get_field_f64(track: &Track, sort_key: &str) -> Option<f64> {
 		"bitrate" => Some(track.bitrate),
 		"sampleRate" => Some(track.sampleRate),
 		"bpm" => track.bpm,
+		"startTime" => track.startTime,
+		"stopTime" => track.stopTime,
 		_ => panic!("Field type not found for {}", sort_key),
 	}
 }
@@ -73,6 +77,7 @@ fn get_field_i8(track: &Track, sort_key: &str) -> Option<i8> {
 fn get_field_u8(track: &Track, sort_key: &str) -> Option<u8> {
 	match sort_key {
 		"rating" => track.rating,
+		"albumRating" => track.albumRating,
 		_ => panic!("Field type not found for {}", sort_key),
 	}
 }
@@ -83,6 +88,7 @@ fn get_field_bool(track: &Track, sort_key: &str) -> Option<bool> {
 		"disliked" => track.disliked,
 		"disabled" => track.disabled,
 		"compilation" => track.compilation,
+		"albumLiked" => track.albumLiked,
 		_ => panic!("Field type not found for {}", sort_key),
 	}
 }
@@ -93,12 +99,29 @@ struct SortItem<'a> {
 }
 
 pub fn sort(options: TracksPageOptions, library: &Library) -> Result<Vec<ItemId>> {
+	let item_ids = get_tracklist_item_ids(library, &options.playlist_id)?;
+	sort_item_ids(
+		item_ids,
+		&options.sort_key,
+		options.sort_desc,
+		options.group_album_tracks,
+		library,
+	)
+}
+
+pub fn sort_item_ids(
+	item_ids: Vec<ItemId>,
+	sort_key: &str,
+	sort_desc: bool,
+	group_album_tracks: bool,
+	library: &Library,
+) -> Result<Vec<ItemId>> {
 	let now = Instant::now();
 
 	let id_map = TRACK_ID_MAP.read().unwrap();
 	let tracks = library.get_tracks();
 
-	let items: Result<Vec<SortItem>> = get_tracklist_item_ids(library, &options.playlist_id)?
+	let items: Result<Vec<SortItem>> = item_ids
 		.into_iter()
 		.enumerate()
 		.map(|(i, id)| {
@@ -114,10 +137,10 @@ pub fn sort(options: TracksPageOptions, library: &Library) -> Result<Vec<ItemId>
 	let mut items = items?;
 	let item_count = items.len();
 
-	if options.sort_key == "index" {
+	if sort_key == "index" {
 		// Note: Indexes descend from "first to last", unlike
 		// other numbers which ascend from "high to low"
-		if !options.sort_desc {
+		if !sort_desc {
 			items.reverse();
 		}
 		println!("Sort: {}ms", now.elapsed().as_millis());
@@ -125,17 +148,17 @@ pub fn sort(options: TracksPageOptions, library: &Library) -> Result<Vec<ItemId>
 		return Ok(item_ids);
 	}
 
-	let field = get_track_field_type(&options.sort_key)?;
-	let group_album_tracks = options.group_album_tracks
-		&& match options.sort_key.as_str() {
+	let field = get_track_field_type(sort_key)?;
+	let group_album_tracks = group_album_tracks
+		&& match sort_key {
 			"dateAdded" | "albumName" | "comments" | "genre" | "year" | "artist" => true,
 			_ => false,
 		};
 	items.sort_by(|a, b| {
-		return compare_track_field(a.track, b.track, &options.sort_key, &field);
+		return compare_track_field(a.track, b.track, sort_key, &field);
 	});
 
-	if options.sort_desc {
+	if sort_desc {
 		items.reverse();
 	}
 
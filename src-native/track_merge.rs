@@ -0,0 +1,439 @@
+use crate::data::Data;
+use crate::data_js::get_data;
+use crate::get_now_timestamp;
+use crate::library::Paths;
+use crate::library_types::{
+	ItemId, Library, MsSinceUnixEpoch, TRACK_ID_MAP, Track, TrackID, TrackList, TrackListID,
+	new_item_ids_from_track_ids,
+};
+use crate::playlists::delete_file;
+use anyhow::{Context, Result, anyhow};
+use napi::Env;
+use std::path::PathBuf;
+
+/// Adds timestamps to `target` and sorts them. Identical timestamps are only
+/// kept once, since they are the same play or skip recorded on both tracks.
+/// Returns how many were removed.
+fn union_timestamps(
+	target: &mut Option<Vec<MsSinceUnixEpoch>>,
+	other: &Option<Vec<MsSinceUnixEpoch>>,
+) -> u32 {
+	let Some(other) = other else {
+		return 0;
+	};
+	let timestamps = target.get_or_insert_with(Vec::new);
+	let total = timestamps.len() + other.len();
+	timestamps.extend(other);
+	timestamps.sort_unstable();
+	timestamps.dedup();
+	(total - timestamps.len()) as u32
+}
+
+/// Adds `other` to `target`, minus plays or skips that were on both tracks
+fn add_count(target: &mut Option<u32>, other: Option<u32>, removed: u32) {
+	if target.is_none() && other.is_none() {
+		return;
+	}
+	let count = target.unwrap_or(0) + other.unwrap_or(0);
+	*target = Some(count.saturating_sub(removed));
+}
+
+/// Adds the plays and skips of `other` to `track`. The metadata of `track` is
+/// kept.
+fn merge_track_stats(track: &mut Track, other: &Track) {
+	let removed_plays = union_timestamps(&mut track.plays, &other.plays);
+	add_count(&mut track.playCount, other.playCount, removed_plays);
+	let removed_skips = union_timestamps(&mut track.skips, &other.skips);
+	add_count(&mut track.skipCount, other.skipCount, removed_skips);
+	if let Some(other_imported) = &other.playsImported {
+		let imported = track.playsImported.get_or_insert_with(Vec::new);
+		imported.extend(other_imported.iter().cloned());
+	}
+	if let Some(other_imported) = &other.skipsImported {
+		let imported = track.skipsImported.get_or_insert_with(Vec::new);
+		imported.extend(other_imported.iter().cloned());
+	}
+	track.dateAdded = track.dateAdded.min(other.dateAdded);
+}
+
+/// Files of `others` that should be moved to the trash
+fn get_files_to_trash(
+	library: &Library,
+	paths: &Paths,
+	survivor_id: &TrackID,
+	others: &[TrackID],
+) -> Result<Vec<PathBuf>> {
+	let survivor_file = &library.get_track(survivor_id)?.file;
+	let mut files = Vec::new();
+	for id in others {
+		let track = library.get_track(id)?;
+		let path = paths.get_track_file_path(&track.file);
+		// Referenced files are never deleted
+		if track.is_referenced() || track.file == *survivor_file || !path.exists() {
+			continue;
+		}
+		if !files.contains(&path) {
+			files.push(path);
+		}
+	}
+	Ok(files)
+}
+
+struct PlaylistMerge {
+	playlist_id: TrackListID,
+	/// `None` is a new entry of the survivor
+	entries: Vec<Option<ItemId>>,
+	/// Entries that point to the survivor instead of one of the other tracks
+	moved_count: usize,
+	/// Entries that are removed since the playlist already has the survivor
+	removed_count: usize,
+}
+
+/// Entries of `others` are replaced with an entry of the survivor. If the
+/// playlist already has the survivor, or several of the tracks are in it,
+/// only the first entry is kept so that the merged track isn't in it twice.
+fn merge_playlists(
+	library: &Library,
+	survivor_id: &TrackID,
+	others: &[TrackID],
+) -> Vec<PlaylistMerge> {
+	let track_id_map = TRACK_ID_MAP.read().unwrap();
+	let mut merges = Vec::new();
+	for (playlist_id, tracklist) in &library.trackLists {
+		let TrackList::Playlist(playlist) = tracklist else {
+			continue;
+		};
+		let is_other = |item_id: &ItemId| others.contains(&track_id_map[*item_id as usize]);
+		if !playlist.tracks.iter().any(is_other) {
+			continue;
+		}
+		let mut has_survivor =
+			(playlist.tracks.iter()).any(|item_id| track_id_map[*item_id as usize] == *survivor_id);
+		let mut merge = PlaylistMerge {
+			playlist_id: playlist_id.clone(),
+			entries: Vec::new(),
+			moved_count: 0,
+			removed_count: 0,
+		};
+		for item_id in &playlist.tracks {
+			if !is_other(item_id) {
+				merge.entries.push(Some(*item_id));
+			} else if has_survivor {
+				merge.removed_count += 1;
+			} else {
+				merge.entries.push(None);
+				merge.moved_count += 1;
+				has_survivor = true;
+			}
+		}
+		merges.push(merge);
+	}
+	merges
+}
+
+/// What `merge_tracks` changed, so that it can be reverted
+struct MergedTracks {
+	/// The survivor before the merge
+	survivor: Track,
+	removed_tracks: Vec<(TrackID, Track)>,
+	/// Entries of the changed playlists before the merge
+	playlist_entries: Vec<(TrackListID, Vec<ItemId>)>,
+	/// Indexes and track IDs of the changed `playTime` entries
+	play_time_ids: Vec<(usize, TrackID)>,
+	v1_play_time_ids: Vec<(usize, TrackID)>,
+}
+
+/// Merges `others` into `survivor_id` and removes them from the library
+fn merge_tracks(
+	library: &mut Library,
+	survivor_id: &TrackID,
+	others: &[TrackID],
+) -> Result<MergedTracks> {
+	let survivor = library.get_track(survivor_id)?.clone();
+	let mut merged_survivor = survivor.clone();
+	for id in others {
+		merge_track_stats(&mut merged_survivor, library.get_track(id)?);
+	}
+	merged_survivor.dateModified = get_now_timestamp();
+	*library.get_track_mut(survivor_id)? = merged_survivor;
+
+	let mut playlist_entries = Vec::new();
+	for merge in merge_playlists(library, survivor_id, others) {
+		let TrackList::Playlist(playlist) = library.get_tracklist_mut(&merge.playlist_id)? else {
+			continue;
+		};
+		let entries = merge
+			.entries
+			.into_iter()
+			.map(|entry| match entry {
+				Some(item_id) => item_id,
+				None => new_item_ids_from_track_ids(std::slice::from_ref(survivor_id))[0],
+			})
+			.collect();
+		let old_entries = std::mem::replace(&mut playlist.tracks, entries);
+		playlist_entries.push((merge.playlist_id, old_entries));
+	}
+	let mut merged = MergedTracks {
+		survivor,
+		removed_tracks: Vec::new(),
+		playlist_entries,
+		play_time_ids: Vec::new(),
+		v1_play_time_ids: Vec::new(),
+	};
+	let play_times = [
+		(&mut library.playTime, &mut merged.play_time_ids),
+		(&mut library.v1PlayTime, &mut merged.v1_play_time_ids),
+	];
+	for (play_times, old_ids) in play_times {
+		for (i, (track_id, _, _)) in play_times.iter_mut().enumerate() {
+			if others.contains(track_id) {
+				old_ids.push((i, std::mem::replace(track_id, survivor_id.clone())));
+			}
+		}
+	}
+	for id in others {
+		let track = library.remove_track(id)?;
+		merged.removed_tracks.push((id.clone(), track));
+	}
+	Ok(merged)
+}
+
+/// Undoes `merge_tracks`. The removed tracks are added back at the end of the
+/// library.
+fn revert_merge(library: &mut Library, survivor_id: &TrackID, merged: MergedTracks) -> Result<()> {
+	for (id, track) in merged.removed_tracks {
+		library.insert_track(id, track);
+	}
+	*library.get_track_mut(survivor_id)? = merged.survivor;
+	for (playlist_id, entries) in merged.playlist_entries {
+		if let TrackList::Playlist(playlist) = library.get_tracklist_mut(&playlist_id)? {
+			playlist.tracks = entries;
+		}
+	}
+	let play_times = [
+		(&mut library.playTime, merged.play_time_ids),
+		(&mut library.v1PlayTime, merged.v1_play_time_ids),
+	];
+	for (play_times, old_ids) in play_times {
+		for (i, old_id) in old_ids {
+			play_times[i].0 = old_id;
+		}
+	}
+	Ok(())
+}
+
+/// What `finish` will do
+#[napi(object)]
+pub struct TrackMergePreview {
+	pub play_count: i64,
+	pub skip_count: i64,
+	/// Playlist entries that will point to the merged track
+	pub playlist_entry_count: i64,
+	/// Playlist entries that will be removed, since the playlist already has
+	/// the merged track
+	pub removed_playlist_entry_count: i64,
+	/// Files that will be moved to the trash
+	pub trashed_files: Vec<String>,
+}
+
+/// Merges duplicate tracks into one. Plays, skips and playlist entries of the
+/// other tracks are moved to the surviving track. Nothing is changed until
+/// `finish` is called, and the files of the other tracks are only moved to
+/// the trash by `trash_files`.
+#[napi]
+pub struct TrackMerge {
+	survivor_id: TrackID,
+	others: Vec<TrackID>,
+	/// Set by `finish`, until the merge is reverted or the files are trashed
+	merged: Option<MergedTracks>,
+	/// Files that should be moved to the trash once the library is saved
+	files: Vec<PathBuf>,
+}
+#[napi]
+impl TrackMerge {
+	/// `item_ids` are the tracks to merge into `survivor`, which may include
+	/// `survivor` itself
+	#[napi(factory)]
+	pub fn new(survivor: ItemId, item_ids: Vec<ItemId>, env: Env) -> napi::Result<Self> {
+		let data: &Data = get_data(&env);
+		let id_map = TRACK_ID_MAP.read().unwrap();
+		let survivor_id = id_map.get(survivor as usize).context("Item ID not found")?;
+		data.library.get_track(survivor_id)?;
+		let mut others: Vec<TrackID> = Vec::new();
+		for item_id in item_ids {
+			let track_id = id_map.get(item_id as usize).context("Item ID not found")?;
+			data.library.get_track(track_id)?;
+			if track_id != survivor_id && !others.contains(track_id) {
+				others.push(track_id.clone());
+			}
+		}
+		if others.is_empty() {
+			return Err(anyhow!("No other tracks to merge").into());
+		}
+		Ok(Self {
+			survivor_id: survivor_id.clone(),
+			others,
+			merged: None,
+			files: Vec::new(),
+		})
+	}
+	#[napi]
+	pub fn preview(&self, env: Env) -> napi::Result<TrackMergePreview> {
+		let data: &Data = get_data(&env);
+		let library = &data.library;
+		let mut merged = library.get_track(&self.survivor_id)?.clone();
+		for id in &self.others {
+			merge_track_stats(&mut merged, library.get_track(id)?);
+		}
+		let mut moved_count = 0;
+		let mut removed_count = 0;
+		for merge in merge_playlists(library, &self.survivor_id, &self.others) {
+			moved_count += merge.moved_count;
+			removed_count += merge.removed_count;
+		}
+		let files = get_files_to_trash(library, &data.paths, &self.survivor_id, &self.others)?;
+		Ok(TrackMergePreview {
+			play_count: merged.playCount.unwrap_or(0).into(),
+			skip_count: merged.skipCount.unwrap_or(0).into(),
+			playlist_entry_count: moved_count as i64,
+			removed_playlist_entry_count: removed_count as i64,
+			trashed_files: files
+				.iter()
+				.map(|path| path.to_string_lossy().into_owned())
+				.collect(),
+		})
+	}
+	/// Merges the tracks in the library, without saving it
+	#[napi]
+	pub fn finish(&mut self, env: Env) -> napi::Result<()> {
+		if self.merged.is_some() {
+			return Err(anyhow!("The tracks were already merged").into());
+		}
+		let data: &mut Data = get_data(&env);
+		let library = &mut data.library;
+		self.files = get_files_to_trash(library, &data.paths, &self.survivor_id, &self.others)?;
+		self.merged = Some(merge_tracks(library, &self.survivor_id, &self.others)?);
+		Ok(())
+	}
+	/// Restores the tracks as they were before `finish`, for example if the
+	/// library could not be saved
+	#[napi]
+	pub fn revert(&mut self, env: Env) -> napi::Result<()> {
+		let merged = self.merged.take().context("The tracks were not merged")?;
+		let data: &mut Data = get_data(&env);
+		revert_merge(&mut data.library, &self.survivor_id, merged)?;
+		Ok(())
+	}
+	/// Moves the files of the merged tracks to the trash. Call this once the
+	/// library is saved. Returns errors for files that could not be moved to
+	/// the trash.
+	#[napi]
+	pub fn trash_files(&mut self) -> napi::Result<Vec<String>> {
+		self.merged.take().context("The tracks were not merged")?;
+		let errors = (self.files.iter())
+			.filter_map(|path| delete_file(path).err())
+			.map(|e| format!("{e:#}"))
+			.collect();
+		Ok(errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn track(name: &str, plays: &[MsSinceUnixEpoch]) -> Track {
+		serde_json::from_value(serde_json::json!({
+			"size": 0,
+			"duration": 200.0,
+			"bitrate": 0.0,
+			"sampleRate": 0.0,
+			"file": format!("{name}.mp3"),
+			"dateModified": 0,
+			"dateAdded": 0,
+			"name": name,
+			"artist": "Artist",
+			"playCount": plays.len(),
+			"plays": plays,
+		}))
+		.unwrap()
+	}
+
+	fn add_playlist(library: &mut Library, track_ids: &[&str]) -> TrackListID {
+		let mut playlist = library.new_playlist("Playlist".to_string(), None);
+		let track_ids: Vec<TrackID> = track_ids.iter().map(|id| id.to_string()).collect();
+		playlist.tracks = new_item_ids_from_track_ids(&track_ids);
+		let id = playlist.id.clone();
+		library
+			.trackLists
+			.insert(id.clone(), TrackList::Playlist(playlist));
+		id
+	}
+
+	fn playlist_track_ids(library: &Library, id: &str) -> Vec<TrackID> {
+		match library.get_tracklist(id).unwrap() {
+			TrackList::Playlist(playlist) => playlist.get_track_ids(),
+			_ => panic!("Not a playlist"),
+		}
+	}
+
+	#[test]
+	fn merge_and_revert_test() {
+		let mut library = Library::new();
+		library.insert_track("merge_a".to_string(), track("a", &[1, 2]));
+		library.insert_track("merge_b".to_string(), track("b", &[2, 3]));
+		library.insert_track("merge_c".to_string(), track("c", &[]));
+		library.insert_track("merge_x".to_string(), track("x", &[]));
+		let with_survivor = add_playlist(&mut library, &["merge_a", "merge_b", "merge_x"]);
+		let without_survivor = add_playlist(&mut library, &["merge_b", "merge_x", "merge_c"]);
+		let unrelated = add_playlist(&mut library, &["merge_x"]);
+		library.playTime.push(("merge_b".to_string(), 3, 1000));
+		library.playTime.push(("merge_x".to_string(), 4, 1000));
+
+		let survivor_id = "merge_a".to_string();
+		let others = ["merge_b".to_string(), "merge_c".to_string()];
+		let merges = merge_playlists(&library, &survivor_id, &others);
+		assert_eq!(merges.len(), 2);
+		assert_eq!((merges[0].moved_count, merges[0].removed_count), (0, 1));
+		assert_eq!((merges[1].moved_count, merges[1].removed_count), (1, 1));
+
+		let merged = merge_tracks(&mut library, &survivor_id, &others).unwrap();
+		let survivor = library.get_track(&survivor_id).unwrap();
+		assert_eq!(survivor.plays, Some(vec![1, 2, 3]));
+		assert_eq!(survivor.playCount, Some(3));
+		assert!(library.get_track(&others[0]).is_err());
+		assert!(library.get_track(&others[1]).is_err());
+		assert_eq!(
+			playlist_track_ids(&library, &with_survivor),
+			["merge_a", "merge_x"]
+		);
+		assert_eq!(
+			playlist_track_ids(&library, &without_survivor),
+			["merge_a", "merge_x"]
+		);
+		assert_eq!(playlist_track_ids(&library, &unrelated), ["merge_x"]);
+		assert_eq!(library.playTime[0].0, "merge_a");
+		assert_eq!(library.playTime[1].0, "merge_x");
+
+		revert_merge(&mut library, &survivor_id, merged).unwrap();
+		assert_eq!(
+			library.get_track(&survivor_id).unwrap().plays,
+			Some(vec![1, 2])
+		);
+		assert_eq!(
+			library.get_track(&others[0]).unwrap().plays,
+			Some(vec![2, 3])
+		);
+		assert!(library.get_track(&others[1]).is_ok());
+		assert_eq!(library.get_tracks().len(), 4);
+		assert_eq!(
+			playlist_track_ids(&library, &with_survivor),
+			["merge_a", "merge_b", "merge_x"]
+		);
+		assert_eq!(
+			playlist_track_ids(&library, &without_survivor),
+			["merge_b", "merge_x", "merge_c"]
+		);
+		assert_eq!(library.playTime[0].0, "merge_b");
+	}
+}
@@ -0,0 +1,420 @@
+use super::decode::{AudioDecoder, Samples, Stft};
+use super::import::owns_track_file;
+use super::{AnalysisProgress, AnalysisStatus, Tag};
+use crate::data_js::get_data;
+use crate::get_now_timestamp;
+use crate::library_types::{ItemId, TRACK_ID_MAP, TrackID};
+use crate::musical_key::MusicalKey;
+use anyhow::{Context, Result, bail};
+use napi::Env;
+use rayon::prelude::*;
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Higher sample rates are downsampled by an integer factor before analysis
+const MAX_ANALYSIS_RATE: u32 = 48000;
+const ONSET_FRAME_SIZE: usize = 1024;
+const ONSET_HOP: usize = 512;
+const CHROMA_FRAME_SIZE: usize = 8192;
+const CHROMA_HOP: usize = 4096;
+/// Lower frequencies are too close together to tell semitones apart with the
+/// chroma frame size
+const CHROMA_MIN_FREQ: f64 = 80.0;
+const CHROMA_MAX_FREQ: f64 = 5000.0;
+const MIN_BPM: f64 = 70.0;
+const MAX_BPM: f64 = 180.0;
+/// Tempos far from this are penalized, to avoid detecting half or double the
+/// tempo. Typical for dance music
+const PREFERRED_BPM: f64 = 128.0;
+
+/// Key profiles by Krumhansl and Kessler, starting from the tonic
+const MAJOR_PROFILE: [f64; 12] = [
+	6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f64; 12] = [
+	6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Collects an onset envelope for tempo detection and a chromagram for key
+/// detection from mono audio
+struct BpmKeyDetector {
+	decimation: usize,
+	decimation_sum: f32,
+	decimation_count: usize,
+	mono: Vec<f32>,
+	onset_stft: Stft,
+	previous_spectrum: Vec<f32>,
+	/// Spectral flux of each onset frame
+	onset_envelope: Vec<f32>,
+	/// Onset envelope values per second
+	envelope_rate: f64,
+	chroma_stft: Stft,
+	/// Pitch class of each chroma frequency bin, where 0 is C
+	bin_pitch_classes: Vec<Option<usize>>,
+	chroma: [f64; 12],
+}
+impl BpmKeyDetector {
+	fn new(sample_rate: u32) -> Self {
+		let decimation = sample_rate.div_ceil(MAX_ANALYSIS_RATE).max(1) as usize;
+		let rate = f64::from(sample_rate) / decimation as f64;
+		let bin_pitch_classes = (0..=CHROMA_FRAME_SIZE / 2)
+			.map(|bin| {
+				let freq = bin as f64 * rate / CHROMA_FRAME_SIZE as f64;
+				if !(CHROMA_MIN_FREQ..=CHROMA_MAX_FREQ).contains(&freq) {
+					return None;
+				}
+				let midi_note = 69.0 + 12.0 * (freq / 440.0).log2();
+				Some((midi_note.round() as i64).rem_euclid(12) as usize)
+			})
+			.collect();
+		let mut planner = FftPlanner::new();
+		Self {
+			decimation,
+			decimation_sum: 0.0,
+			decimation_count: 0,
+			mono: Vec::new(),
+			onset_stft: Stft::new(&mut planner, ONSET_FRAME_SIZE, ONSET_HOP),
+			previous_spectrum: vec![0.0; ONSET_FRAME_SIZE / 2 + 1],
+			onset_envelope: Vec::new(),
+			envelope_rate: rate / ONSET_HOP as f64,
+			chroma_stft: Stft::new(&mut planner, CHROMA_FRAME_SIZE, CHROMA_HOP),
+			bin_pitch_classes,
+			chroma: [0.0; 12],
+		}
+	}
+	fn process(&mut self, samples: &Samples) {
+		self.mono.clear();
+		for frame in samples.data.chunks_exact(samples.channels) {
+			self.decimation_sum += frame.iter().sum::<f32>() / samples.channels as f32;
+			self.decimation_count += 1;
+			if self.decimation_count == self.decimation {
+				self.mono.push(self.decimation_sum / self.decimation as f32);
+				self.decimation_sum = 0.0;
+				self.decimation_count = 0;
+			}
+		}
+
+		let previous_spectrum = &mut self.previous_spectrum;
+		let onset_envelope = &mut self.onset_envelope;
+		self.onset_stft.push(&self.mono, |bins| {
+			let mut flux = 0.0;
+			for (bin, previous) in bins.iter().zip(previous_spectrum.iter_mut()) {
+				// Log compression, so quiet onsets count too
+				let magnitude = (1.0 + 1000.0 * bin.norm() / ONSET_FRAME_SIZE as f32).ln();
+				flux += (magnitude - *previous).max(0.0);
+				*previous = magnitude;
+			}
+			onset_envelope.push(flux);
+		});
+
+		let bin_pitch_classes = &self.bin_pitch_classes;
+		let chroma = &mut self.chroma;
+		self.chroma_stft.push(&self.mono, |bins| {
+			for (bin, pitch_class) in bins.iter().zip(bin_pitch_classes) {
+				if let Some(pitch_class) = pitch_class {
+					chroma[*pitch_class] += f64::from(bin.norm());
+				}
+			}
+		});
+	}
+}
+
+/// Autocorrelation for each lag from 0 to `values.len() - 1`, normalized by
+/// the number of overlapping values
+fn autocorrelation(values: &[f64]) -> Vec<f64> {
+	let size = (values.len() * 2).next_power_of_two();
+	let mut buffer: Vec<Complex<f64>> = values
+		.iter()
+		.map(|&value| Complex::new(value, 0.0))
+		.chain(std::iter::repeat(Complex::default()))
+		.take(size)
+		.collect();
+	let mut planner = FftPlanner::new();
+	planner.plan_fft_forward(size).process(&mut buffer);
+	for x in &mut buffer {
+		*x = Complex::new(x.norm_sqr(), 0.0);
+	}
+	planner.plan_fft_inverse(size).process(&mut buffer);
+	buffer[..values.len()]
+		.iter()
+		.enumerate()
+		.map(|(lag, x)| x.re / (values.len() - lag) as f64)
+		.collect()
+}
+
+/// Finds the beat period by autocorrelation of the onset envelope
+fn detect_bpm(envelope: &[f32], envelope_rate: f64) -> Option<f64> {
+	let len = envelope.len();
+	let min_lag = ((60.0 * envelope_rate / MAX_BPM).floor() as usize).max(1);
+	let max_lag = (60.0 * envelope_rate / MIN_BPM).ceil() as usize;
+	if len < max_lag * 4 {
+		return None;
+	}
+
+	// Subtract the local mean, to remove changes in loudness and keep onsets
+	let radius = (envelope_rate / 2.0) as usize;
+	let mut prefix_sums = Vec::with_capacity(len + 1);
+	prefix_sums.push(0.0);
+	for &value in envelope {
+		prefix_sums.push(prefix_sums.last().unwrap() + f64::from(value));
+	}
+	let onsets: Vec<f64> = (0..len)
+		.map(|i| {
+			let start = i.saturating_sub(radius);
+			let end = (i + radius + 1).min(len);
+			let mean = (prefix_sums[end] - prefix_sums[start]) / (end - start) as f64;
+			(f64::from(envelope[i]) - mean).max(0.0)
+		})
+		.collect();
+
+	let ac = autocorrelation(&onsets);
+	if ac[0] <= 0.0 {
+		return None;
+	}
+	let score = |lag: usize| {
+		let bpm = 60.0 * envelope_rate / lag as f64;
+		let octaves = (bpm / PREFERRED_BPM).log2();
+		let weight = (-0.5 * octaves * octaves).exp();
+		weight * (ac[lag] + 0.5 * ac[2 * lag])
+	};
+	let lag = (min_lag..=max_lag).max_by(|a, b| score(*a).total_cmp(&score(*b)))?;
+
+	// The peak of a later beat has a larger lag, so its position is more precise
+	let beats = [16, 8, 4, 2, 1]
+		.into_iter()
+		.find(|beats| beats * (lag + 1) < len / 2)?;
+	let center = beats * lag;
+	let search_radius = (beats / 2).max(1);
+	let peak = (center - search_radius..=center + search_radius)
+		.max_by(|a, b| ac[*a].total_cmp(&ac[*b]))?;
+	let (a, b, c) = (ac[peak - 1], ac[peak], ac[peak + 1]);
+	let denominator = a - 2.0 * b + c;
+	let offset = match denominator < 0.0 {
+		true => 0.5 * (a - c) / denominator,
+		false => 0.0,
+	};
+	let precise_lag = (peak as f64 + offset) / beats as f64;
+	let bpm = 60.0 * envelope_rate / precise_lag;
+	Some((bpm * 10.0).round() / 10.0)
+}
+
+fn correlation(a: &[f64; 12], b: &[f64; 12]) -> f64 {
+	let mean_a = a.iter().sum::<f64>() / 12.0;
+	let mean_b = b.iter().sum::<f64>() / 12.0;
+	let mut covariance = 0.0;
+	let mut variance_a = 0.0;
+	let mut variance_b = 0.0;
+	for (a, b) in a.iter().zip(b) {
+		covariance += (a - mean_a) * (b - mean_b);
+		variance_a += (a - mean_a).powi(2);
+		variance_b += (b - mean_b).powi(2);
+	}
+	if variance_a == 0.0 {
+		return 0.0;
+	}
+	covariance / (variance_a * variance_b).sqrt()
+}
+
+/// Picks the key whose profile correlates best with the chromagram
+fn detect_key(chroma: &[f64; 12]) -> Option<MusicalKey> {
+	if chroma.iter().sum::<f64>() <= 0.0 {
+		return None;
+	}
+	let mut best: Option<(f64, MusicalKey)> = None;
+	for tonic in 0..12 {
+		let rotated: [f64; 12] = std::array::from_fn(|i| chroma[(tonic + i) % 12]);
+		for (minor, profile) in [(false, &MAJOR_PROFILE), (true, &MINOR_PROFILE)] {
+			let score = correlation(&rotated, profile);
+			if best.is_none_or(|(best_score, _)| score > best_score) {
+				let key = MusicalKey {
+					tonic: tonic as u8,
+					minor,
+				};
+				best = Some((score, key));
+			}
+		}
+	}
+	best.map(|(_, key)| key)
+}
+
+pub struct TrackBpmKey {
+	pub bpm: Option<f64>,
+	pub key: Option<MusicalKey>,
+}
+
+pub fn analyze_file(path: &Path, cancelled: &AtomicBool) -> Result<TrackBpmKey> {
+	let mut decoder = AudioDecoder::open(path)?;
+	let mut detector: Option<BpmKeyDetector> = None;
+	while let Some(samples) = decoder.next_samples()? {
+		if cancelled.load(Ordering::Relaxed) {
+			bail!("Cancelled");
+		}
+		let detector = detector.get_or_insert_with(|| BpmKeyDetector::new(samples.sample_rate));
+		detector.process(&samples);
+	}
+	let detector = detector.context("No audio found")?;
+	let result = TrackBpmKey {
+		bpm: detect_bpm(&detector.onset_envelope, detector.envelope_rate),
+		key: detect_key(&detector.chroma),
+	};
+	if result.bpm.is_none() && result.key.is_none() {
+		bail!("No tempo or key found");
+	}
+	Ok(result)
+}
+
+#[derive(Clone)]
+struct AnalysisItem {
+	track_id: TrackID,
+	path: PathBuf,
+	/// Tags are only written to files that belong to the library
+	owns_file: bool,
+}
+
+/// Detects the tempo and musical key of tracks in the background. Values that
+/// can't be detected are left unchanged.
+#[napi]
+pub struct BpmKeyAnalysis {
+	items: Vec<AnalysisItem>,
+	/// Write BPM and initial key tags
+	write_tags: bool,
+	cancelled: Arc<AtomicBool>,
+	done_count: Arc<AtomicU32>,
+	results: Arc<Mutex<HashMap<TrackID, TrackBpmKey>>>,
+}
+#[napi]
+impl BpmKeyAnalysis {
+	#[napi(factory)]
+	pub fn new(item_ids: Vec<ItemId>, write_tags: Option<bool>, env: Env) -> napi::Result<Self> {
+		let data = get_data(&env);
+		let id_map = TRACK_ID_MAP.read().unwrap();
+		let mut items = Vec::new();
+		for item_id in item_ids {
+			let track_id = id_map.get(item_id as usize).context("Item ID not found")?;
+			let track = data.library.get_track(track_id)?;
+			let path = data.paths.get_track_file_path(&track.file);
+			items.push(AnalysisItem {
+				track_id: track_id.clone(),
+				owns_file: owns_track_file(track, &path),
+				path,
+			});
+		}
+		Ok(Self {
+			items,
+			write_tags: write_tags.unwrap_or(false),
+			cancelled: Arc::new(AtomicBool::new(false)),
+			done_count: Arc::new(AtomicU32::new(0)),
+			results: Arc::new(Mutex::new(HashMap::new())),
+		})
+	}
+	#[napi]
+	pub async fn start(&self) -> napi::Result<AnalysisStatus> {
+		let items = self.items.clone();
+		let write_tags = self.write_tags;
+		let cancelled = self.cancelled.clone();
+		let done_count = self.done_count.clone();
+		let results = self.results.clone();
+		let status = tokio::task::spawn_blocking(move || {
+			run_analysis(&items, write_tags, &cancelled, &done_count, &results)
+		})
+		.await
+		.context("BPM and key analysis thread failed")?;
+		Ok(status)
+	}
+	#[napi]
+	pub fn progress(&self) -> AnalysisProgress {
+		AnalysisProgress {
+			done_count: self.done_count.load(Ordering::Relaxed).into(),
+			total_count: self.items.len() as i64,
+		}
+	}
+	/// Stops after the tracks that are being analyzed. Tracks that were
+	/// already analyzed are kept.
+	#[napi]
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+	/// Saves the results to the tracks
+	#[napi]
+	pub fn finish(&self, env: Env) -> napi::Result<()> {
+		let data = get_data(&env);
+		let results = std::mem::take(&mut *self.results.lock().unwrap());
+		let now = get_now_timestamp();
+		for (track_id, result) in results {
+			let track = match data.library.get_track_mut(&track_id) {
+				Ok(track) => track,
+				Err(_) => continue,
+			};
+			if let Some(bpm) = result.bpm {
+				track.bpm = Some(bpm);
+			}
+			if let Some(key) = result.key {
+				track.key = Some(key.name());
+			}
+			track.dateModified = now;
+		}
+		Ok(())
+	}
+}
+
+fn run_analysis(
+	items: &[AnalysisItem],
+	write_tags: bool,
+	cancelled: &AtomicBool,
+	done_count: &AtomicU32,
+	results: &Mutex<HashMap<TrackID, TrackBpmKey>>,
+) -> AnalysisStatus {
+	let analyzed: Vec<Option<Result<TrackBpmKey>>> = items
+		.par_iter()
+		.map(|item| {
+			if cancelled.load(Ordering::Relaxed) {
+				return None;
+			}
+			let result = analyze_file(&item.path, cancelled);
+			done_count.fetch_add(1, Ordering::Relaxed);
+			Some(result)
+		})
+		.collect();
+	let is_cancelled = cancelled.load(Ordering::Relaxed);
+
+	let mut errors = Vec::new();
+	let mut untagged_files = Vec::new();
+	let mut results = results.lock().unwrap();
+	for (item, result) in items.iter().zip(analyzed) {
+		let track_bpm_key = match result {
+			Some(Ok(track_bpm_key)) => track_bpm_key,
+			Some(Err(e)) if !is_cancelled => {
+				errors.push(format!("{}: {e:#}", item.path.to_string_lossy()));
+				continue;
+			}
+			_ => continue,
+		};
+		if write_tags && !item.owns_file {
+			untagged_files.push(item.path.to_string_lossy().into_owned());
+		} else if write_tags && let Err(e) = write_bpm_key_tags(&item.path, &track_bpm_key) {
+			errors.push(format!("{}: {e:#}", item.path.to_string_lossy()));
+		}
+		results.insert(item.track_id.clone(), track_bpm_key);
+	}
+	AnalysisStatus {
+		analyzed_count: results.len() as i64,
+		errors,
+		untagged_files,
+		cancelled: is_cancelled,
+	}
+}
+
+fn write_bpm_key_tags(path: &Path, track_bpm_key: &TrackBpmKey) -> Result<()> {
+	let mut tag = Tag::read_from_path(&path.to_path_buf())?;
+	if let Some(bpm) = track_bpm_key.bpm {
+		tag.set_bpm(bpm.round() as u16);
+	}
+	if let Some(key) = track_bpm_key.key {
+		tag.set_initial_key(&key.name());
+	}
+	tag.write_to_path(path)
+}
@@ -1,4 +1,6 @@
 use super::Tag;
+use super::fingerprint::FINGERPRINT_CACHE_TABLE;
+use super::waveform::WAVEFORM_CACHE_TABLE;
 use anyhow::{Context, Result, anyhow, bail};
 use fast_image_resize::images::Image;
 use fast_image_resize::{IntoImageView, Resizer};
@@ -18,9 +20,9 @@ type CacheEntry = (i64, Vec<u8>);
 
 const IMG_CACHE_TABLE: TableDefinition<&str, CacheEntry> = TableDefinition::new("img_cache");
 
-static CACHE_DB: LazyLock<RwLock<Option<Database>>> = LazyLock::new(|| RwLock::new(None));
+pub static CACHE_DB: LazyLock<RwLock<Option<Database>>> = LazyLock::new(|| RwLock::new(None));
 
-fn init_cache_db(path: String) -> Result<()> {
+pub fn init_cache_db(path: String) -> Result<()> {
 	let now = Instant::now();
 
 	// Check quickly with a read-lock first
@@ -35,15 +37,21 @@ fn init_cache_db(path: String) -> Result<()> {
 	let cache_db_global = &*CACHE_DB;
 	let mut cache_db_lock = cache_db_global.write().unwrap();
 	if cache_db_lock.is_none() {
-		let db = Database::create(&path).context("Could not load image cache")?;
+		let db = Database::create(&path).context("Could not load cache")?;
 		let init_txn = db
 			.begin_write()
 			.context("Could not begin write transaction")?;
 		{
-			// Create table
+			// Create tables
 			init_txn
 				.open_table(IMG_CACHE_TABLE)
 				.context("Could not open table")?;
+			init_txn
+				.open_table(WAVEFORM_CACHE_TABLE)
+				.context("Could not open table")?;
+			init_txn
+				.open_table(FINGERPRINT_CACHE_TABLE)
+				.context("Could not open table")?;
 		}
 		init_txn.commit().context("Could not commit cache")?;
 		*cache_db_lock = Some(db);
@@ -71,7 +79,7 @@ pub async fn close_cache_db() -> napi::Result<()> {
 }
 
 /// Returns `None` if the file does not have an image
-fn get_modified_timestamp_ms(path: &str) -> Result<Option<u128>> {
+pub fn get_modified_timestamp_ms(path: &str) -> Result<Option<u128>> {
 	let file_metadata = match fs::metadata(path) {
 		Ok(file_metadata) => file_metadata,
 		Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
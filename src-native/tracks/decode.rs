@@ -0,0 +1,184 @@
+use crate::tracks::import::FileType;
+use anyhow::{Context, Result, bail};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use symphonia::core::audio::{SampleBuffer, SignalSpec};
+use symphonia::core::codecs::{CODEC_TYPE_NULL, Decoder, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Interleaved samples of one packet
+pub struct Samples<'a> {
+	pub data: &'a [f32],
+	pub sample_rate: u32,
+	pub channels: usize,
+}
+
+/// Decodes the first audio track of a file, one packet at a time
+pub struct AudioDecoder {
+	format: Box<dyn FormatReader>,
+	decoder: Box<dyn Decoder>,
+	track_id: u32,
+	buffer: Option<(SampleBuffer<f32>, u64, SignalSpec)>,
+}
+impl AudioDecoder {
+	/// Symphonia has no Opus decoder, so Opus files fail with a clear error
+	/// instead of a probe or codec error
+	pub fn open(path: &Path) -> Result<Self> {
+		if let Ok(FileType::Opus) = FileType::from_path(path) {
+			bail!("Opus is not supported");
+		}
+		let file = File::open(path)
+			.with_context(|| format!("Unable to open {}", path.to_string_lossy()))?;
+		let stream = MediaSourceStream::new(Box::new(file), Default::default());
+		let mut hint = Hint::new();
+		if let Some(ext) = path.extension() {
+			hint.with_extension(&ext.to_string_lossy());
+		}
+		let probed = symphonia::default::get_probe()
+			.format(
+				&hint,
+				stream,
+				&FormatOptions::default(),
+				&MetadataOptions::default(),
+			)
+			.context("Unsupported audio format")?;
+		let format = probed.format;
+		let track = format
+			.tracks()
+			.iter()
+			.find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+			.context("No audio track found")?;
+		let decoder = symphonia::default::get_codecs()
+			.make(&track.codec_params, &DecoderOptions::default())
+			.context("Unsupported codec")?;
+		let track_id = track.id;
+		Ok(Self {
+			track_id,
+			format,
+			decoder,
+			buffer: None,
+		})
+	}
+	/// Samples of the next packet, or `None` at the end of the file. Packets
+	/// that fail to decode are skipped.
+	pub fn next_samples(&mut self) -> Result<Option<Samples<'_>>> {
+		loop {
+			let packet = match self.format.next_packet() {
+				Ok(packet) => packet,
+				Err(SymphoniaError::IoError(e))
+					if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+				{
+					return Ok(None);
+				}
+				Err(SymphoniaError::ResetRequired) => return Ok(None),
+				Err(e) => return Err(e).context("Unable to read audio"),
+			};
+			if packet.track_id() != self.track_id {
+				continue;
+			}
+			let decoded = match self.decoder.decode(&packet) {
+				Ok(decoded) => decoded,
+				Err(SymphoniaError::DecodeError(_)) => continue,
+				Err(e) => return Err(e).context("Unable to decode audio"),
+			};
+			let spec = *decoded.spec();
+			let frames = decoded.capacity() as u64;
+			let buffer = match self.buffer.take() {
+				Some((buffer, capacity, buffer_spec))
+					if capacity >= frames && buffer_spec == spec =>
+				{
+					(buffer, capacity, buffer_spec)
+				}
+				_ => (SampleBuffer::new(frames, spec), frames, spec),
+			};
+			let (buffer, _, _) = self.buffer.insert(buffer);
+			buffer.copy_interleaved_ref(decoded);
+			return Ok(Some(Samples {
+				data: buffer.samples(),
+				sample_rate: spec.rate,
+				channels: spec.channels.count(),
+			}));
+		}
+	}
+}
+
+fn hann_window(size: usize) -> Vec<f32> {
+	(0..size)
+		.map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / size as f64).cos()) as f32)
+		.collect()
+}
+
+/// Short-time Fourier transform of a stream of samples
+pub struct Stft {
+	hop: usize,
+	window: Vec<f32>,
+	fft: Arc<dyn Fft<f32>>,
+	input: Vec<f32>,
+	buffer: Vec<Complex<f32>>,
+	scratch: Vec<Complex<f32>>,
+}
+impl Stft {
+	pub fn new(planner: &mut FftPlanner<f32>, size: usize, hop: usize) -> Self {
+		let fft = planner.plan_fft_forward(size);
+		Self {
+			hop,
+			window: hann_window(size),
+			input: Vec::with_capacity(size * 2),
+			buffer: vec![Complex::default(); size],
+			scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+			fft,
+		}
+	}
+	/// Calls `f` with the positive frequency bins of each completed frame
+	pub fn push(&mut self, samples: &[f32], mut f: impl FnMut(&[Complex<f32>])) {
+		self.input.extend_from_slice(samples);
+		let size = self.window.len();
+		let mut start = 0;
+		while self.input.len() - start >= size {
+			let frame = &self.input[start..start + size];
+			for ((out, &x), &w) in self.buffer.iter_mut().zip(frame).zip(&self.window) {
+				*out = Complex::new(x * w, 0.0);
+			}
+			self.fft
+				.process_with_scratch(&mut self.buffer, &mut self.scratch);
+			f(&self.buffer[..size / 2 + 1]);
+			start += self.hop;
+		}
+		self.input.drain(..start);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn open_error(name: &str, bytes: &[u8]) -> String {
+		let path = std::env::temp_dir().join(format!("ferrum_decode_test_{name}"));
+		std::fs::write(&path, bytes).unwrap();
+		let result = AudioDecoder::open(&path);
+		std::fs::remove_file(&path).unwrap();
+		match result {
+			Ok(_) => panic!("{name} was opened"),
+			Err(e) => e.to_string(),
+		}
+	}
+
+	#[test]
+	fn opus_test() {
+		let mut ogg_opus = b"OggS".to_vec();
+		ogg_opus.extend([0; 22]);
+		ogg_opus.extend([1, 19]);
+		ogg_opus.extend(b"OpusHead");
+		ogg_opus.extend([1, 2, 0, 0, 0x80, 0xBB, 0, 0, 0, 0, 0]);
+		assert_eq!(open_error("opus.ogg", &ogg_opus), "Opus is not supported");
+		assert_eq!(open_error("opus.opus", &ogg_opus), "Opus is not supported");
+	}
+}
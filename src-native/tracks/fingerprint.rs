@@ -0,0 +1,617 @@
+use super::AnalysisProgress;
+use super::cover::{CACHE_DB, get_modified_timestamp_ms, init_cache_db};
+use super::decode::{AudioDecoder, Samples, Stft};
+use crate::data_js::get_data;
+use crate::library_types::{ItemId, TRACK_ID_MAP};
+use anyhow::{Context, Result, bail};
+use napi::Env;
+use rayon::prelude::*;
+use redb::{Database, TableDefinition};
+use rustfft::FftPlanner;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+// (modified_timestamp_ms, subfingerprints as u32 values)
+type FingerprintCacheEntry = (i64, Vec<u8>);
+
+pub const FINGERPRINT_CACHE_TABLE: TableDefinition<&str, FingerprintCacheEntry> =
+	TableDefinition::new("fingerprint_cache");
+
+/// Frames are 4096 samples with a hop of 1365 samples at 11025 Hz, like
+/// Chromaprint. Other sample rates use frames of the same duration.
+const FRAME_SECONDS: f64 = 4096.0 / 11025.0;
+const HOP_SECONDS: f64 = 1365.0 / 11025.0;
+/// Only the start of the track is fingerprinted
+const MAX_SECONDS: f64 = 120.0;
+/// Each subfingerprint bit compares two neighbouring bands
+const BANDS: usize = 33;
+const MIN_FREQ: f64 = 300.0;
+const MAX_FREQ: f64 = 3000.0;
+/// Leading samples quieter than this are skipped, so different amounts of
+/// silence at the start don't misalign fingerprints
+const SILENCE_THRESHOLD: f32 = 0.001;
+/// Fingerprints are compared with offsets up to this much in each direction
+const MAX_OFFSET_SECONDS: f64 = 5.0;
+const MIN_OVERLAP_SECONDS: f64 = 10.0;
+/// Tracks with a bigger difference in duration are never duplicates
+const MAX_DURATION_DIFFERENCE: f64 = 10.0;
+const DEFAULT_MIN_SIMILARITY: f64 = 0.75;
+
+/// Computes a fingerprint from the sign of energy differences between
+/// neighbouring bands and consecutive frames, like Haitsma and Kalker's
+/// audio fingerprinting. One subfingerprint per frame, one bit per band pair.
+struct Fingerprinter {
+	stft: Stft,
+	/// Frequency bins of each band
+	bands: Vec<Range<usize>>,
+	previous_differences: Option<[f64; BANDS - 1]>,
+	fingerprint: Vec<u32>,
+	max_frames: usize,
+	mono: Vec<f32>,
+	started: bool,
+}
+impl Fingerprinter {
+	fn new(sample_rate: u32) -> Self {
+		let rate = f64::from(sample_rate);
+		let frame_size = (rate * FRAME_SECONDS).round() as usize;
+		let hop = (rate * HOP_SECONDS).round() as usize;
+		let bin =
+			|freq: f64| ((freq * frame_size as f64 / rate).round() as usize).min(frame_size / 2);
+		let bands = (0..BANDS)
+			.map(|i| {
+				let edge =
+					|i: usize| MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(i as f64 / BANDS as f64);
+				bin(edge(i))..bin(edge(i + 1))
+			})
+			.collect();
+		let mut planner = FftPlanner::new();
+		Self {
+			stft: Stft::new(&mut planner, frame_size, hop),
+			bands,
+			previous_differences: None,
+			fingerprint: Vec::new(),
+			max_frames: (MAX_SECONDS / HOP_SECONDS) as usize,
+			mono: Vec::new(),
+			started: false,
+		}
+	}
+	fn is_done(&self) -> bool {
+		self.fingerprint.len() >= self.max_frames
+	}
+	fn process(&mut self, samples: &Samples) {
+		self.mono.clear();
+		for frame in samples.data.chunks_exact(samples.channels) {
+			let sample = frame.iter().sum::<f32>() / samples.channels as f32;
+			if !self.started && sample.abs() < SILENCE_THRESHOLD {
+				continue;
+			}
+			self.started = true;
+			self.mono.push(sample);
+		}
+
+		let bands = &self.bands;
+		let previous_differences = &mut self.previous_differences;
+		let fingerprint = &mut self.fingerprint;
+		self.stft.push(&self.mono, |bins| {
+			let energies: Vec<f64> = bands
+				.iter()
+				.map(|band| {
+					bins[band.clone()]
+						.iter()
+						.map(|x| f64::from(x.norm_sqr()))
+						.sum()
+				})
+				.collect();
+			let differences: [f64; BANDS - 1] =
+				std::array::from_fn(|b| energies[b] - energies[b + 1]);
+			if let Some(previous) = previous_differences {
+				let mut subfingerprint = 0;
+				for b in 0..BANDS - 1 {
+					if differences[b] > previous[b] {
+						subfingerprint |= 1 << b;
+					}
+				}
+				fingerprint.push(subfingerprint);
+			}
+			*previous_differences = Some(differences);
+		});
+	}
+}
+
+pub fn fingerprint_file(path: &Path, cancelled: &AtomicBool) -> Result<Vec<u32>> {
+	let mut decoder = AudioDecoder::open(path)?;
+	let mut fingerprinter: Option<Fingerprinter> = None;
+	while let Some(samples) = decoder.next_samples()? {
+		if cancelled.load(Ordering::Relaxed) {
+			bail!("Cancelled");
+		}
+		let fingerprinter =
+			fingerprinter.get_or_insert_with(|| Fingerprinter::new(samples.sample_rate));
+		fingerprinter.process(&samples);
+		if fingerprinter.is_done() {
+			break;
+		}
+	}
+	let mut fingerprint = fingerprinter.context("No audio found")?.fingerprint;
+	if fingerprint.is_empty() {
+		bail!("No audio found");
+	}
+	fingerprint.truncate((MAX_SECONDS / HOP_SECONDS) as usize);
+	Ok(fingerprint)
+}
+
+/// Bit error rate of `a` compared to `b` shifted by `offset` frames, using
+/// every `step`th frame. `None` if they overlap too little.
+fn bit_error_rate(a: &[u32], b: &[u32], offset: isize, step: usize) -> Option<f64> {
+	let start = offset.min(0).unsigned_abs();
+	let end = a.len().min(b.len().saturating_add_signed(-offset));
+	let min_overlap = (MIN_OVERLAP_SECONDS / HOP_SECONDS) as usize;
+	if end < start + min_overlap {
+		return None;
+	}
+	let mut errors = 0;
+	let mut frames = 0;
+	for i in (start..end).step_by(step) {
+		let j = i.checked_add_signed(offset)?;
+		errors += (a[i] ^ b[j]).count_ones();
+		frames += 1;
+	}
+	Some(f64::from(errors) / (frames * (BANDS - 1)) as f64)
+}
+
+/// Similarity between 0 and 1 at the best alignment, where unrelated audio is
+/// around 0.5
+fn similarity(a: &[u32], b: &[u32]) -> Option<f64> {
+	let max_offset = (MAX_OFFSET_SECONDS / HOP_SECONDS) as isize;
+	// Find the alignment with a subset of frames first
+	let (offset, _) = (-max_offset..=max_offset)
+		.filter_map(|offset| Some((offset, bit_error_rate(a, b, offset, 4)?)))
+		.min_by(|(_, x), (_, y)| x.total_cmp(y))?;
+	Some(1.0 - bit_error_rate(a, b, offset, 1)?)
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+	while parents[i] != i {
+		parents[i] = parents[parents[i]];
+		i = parents[i];
+	}
+	i
+}
+
+struct FingerprintedTrack {
+	item_id: ItemId,
+	duration: f64,
+	fingerprint: Vec<u32>,
+}
+
+/// Each table of sub-hashes uses 24 bits of the subfingerprints, rotated
+/// differently
+const SUB_HASH_TABLES: u32 = 3;
+const SUB_HASH_BITS: u32 = 24;
+/// Unrelated tracks share a sub-hash by chance fairly often, but rarely two
+const MIN_SHARED_SUB_HASHES: usize = 2;
+/// Sub-hashes that are in more tracks than this, like the ones of silence,
+/// are too common to tell tracks apart
+const MAX_SUB_HASH_TRACKS: usize = 50;
+
+/// Keys of the sub-hash index, which are unique per table
+fn sub_hashes(fingerprint: &[u32]) -> impl Iterator<Item = u32> + '_ {
+	let mask = (1 << SUB_HASH_BITS) - 1;
+	(0..SUB_HASH_TABLES).flat_map(move |table| {
+		let rotation = table * 32 / SUB_HASH_TABLES;
+		fingerprint
+			.iter()
+			.map(move |sub| (sub.rotate_right(rotation) & mask) | (table << SUB_HASH_BITS))
+	})
+}
+
+/// Pairs of tracks with a similar duration that have sub-hashes in common.
+/// Duplicates share sub-hashes in many of their aligned frames, while
+/// unrelated tracks rarely do, so this avoids comparing every pair.
+fn candidate_pairs(tracks: &[FingerprintedTrack]) -> Vec<(usize, usize)> {
+	let mut index: Vec<(u32, u32)> = tracks
+		.par_iter()
+		.enumerate()
+		.flat_map_iter(|(i, track)| sub_hashes(&track.fingerprint).map(move |key| (key, i as u32)))
+		.collect();
+	index.par_sort_unstable();
+	index.dedup();
+	let mut pairs = Vec::new();
+	for group in index.chunk_by(|a, b| a.0 == b.0) {
+		if group.len() > MAX_SUB_HASH_TRACKS {
+			continue;
+		}
+		for (i, &(_, a)) in group.iter().enumerate() {
+			for &(_, b) in &group[i + 1..] {
+				let (a, b) = (a as usize, b as usize);
+				let duration_difference = (tracks[a].duration - tracks[b].duration).abs();
+				if duration_difference <= MAX_DURATION_DIFFERENCE {
+					pairs.push((a, b));
+				}
+			}
+		}
+	}
+	pairs.par_sort_unstable();
+	pairs
+		.chunk_by(|a, b| a == b)
+		.filter(|shared| shared.len() >= MIN_SHARED_SUB_HASHES)
+		.map(|shared| shared[0])
+		.collect()
+}
+
+/// Groups tracks that are similar to each other, directly or through other
+/// tracks in the group. Stops comparing when cancelled.
+fn find_duplicates(
+	tracks: &[FingerprintedTrack],
+	min_similarity: f64,
+	cancelled: &AtomicBool,
+	progress: &ComparisonProgress,
+) -> Vec<AcousticDuplicateCluster> {
+	let candidates = candidate_pairs(tracks);
+	(progress.total_count).store(candidates.len() as u64, Ordering::Relaxed);
+	let matches: Vec<(usize, usize, f64)> = candidates
+		.into_par_iter()
+		.filter_map(|(a, b)| {
+			if cancelled.load(Ordering::Relaxed) {
+				return None;
+			}
+			let similarity = similarity(&tracks[a].fingerprint, &tracks[b].fingerprint);
+			progress.done_count.fetch_add(1, Ordering::Relaxed);
+			let similarity = similarity.filter(|&similarity| similarity >= min_similarity)?;
+			Some((a, b, similarity))
+		})
+		.collect();
+
+	let mut parents: Vec<usize> = (0..tracks.len()).collect();
+	for &(a, b, _) in &matches {
+		let (root_a, root_b) = (find_root(&mut parents, a), find_root(&mut parents, b));
+		parents[root_b] = root_a;
+	}
+	let mut cluster_similarities = vec![1.0_f64; tracks.len()];
+	for &(a, _, similarity) in &matches {
+		let root = find_root(&mut parents, a);
+		cluster_similarities[root] = cluster_similarities[root].min(similarity);
+	}
+	let mut clusters: Vec<AcousticDuplicateCluster> = Vec::new();
+	let mut cluster_indexes: HashMap<usize, usize> = HashMap::new();
+	for (i, track) in tracks.iter().enumerate() {
+		let root = find_root(&mut parents, i);
+		let index = *cluster_indexes.entry(root).or_insert_with(|| {
+			clusters.push(AcousticDuplicateCluster {
+				item_ids: Vec::new(),
+				similarity: cluster_similarities[root],
+			});
+			clusters.len() - 1
+		});
+		clusters[index].item_ids.push(track.item_id);
+	}
+	clusters.retain(|cluster| cluster.item_ids.len() > 1);
+	clusters
+}
+
+fn get_cached_fingerprint(
+	cache_db: &Database,
+	path: &str,
+	date_modified_ms: i64,
+) -> Result<Option<Vec<u32>>> {
+	let read_txn = cache_db
+		.begin_read()
+		.context("Could not begin read transaction")?;
+	let table = read_txn
+		.open_table(FINGERPRINT_CACHE_TABLE)
+		.context("Could not open table")?;
+	let cache_entry = match table.get(path).context("Could not get record")? {
+		Some(cache_entry) => cache_entry.value(),
+		None => return Ok(None),
+	};
+	if cache_entry.0 != date_modified_ms {
+		return Ok(None);
+	}
+	let fingerprint = cache_entry
+		.1
+		.chunks_exact(4)
+		.map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+		.collect();
+	Ok(Some(fingerprint))
+}
+
+/// Entries of path, modified timestamp and fingerprint
+fn write_fingerprints_to_cache(
+	cache_db: &Database,
+	entries: &[(String, i64, Vec<u32>)],
+) -> Result<()> {
+	let write_txn = cache_db
+		.begin_write()
+		.context("Could not begin write transaction")?;
+	{
+		let mut table = write_txn
+			.open_table(FINGERPRINT_CACHE_TABLE)
+			.context("Could not open table")?;
+		for (path, date_modified_ms, fingerprint) in entries {
+			let bytes: Vec<u8> = fingerprint.iter().flat_map(|x| x.to_le_bytes()).collect();
+			table
+				.insert(path.as_str(), (*date_modified_ms, bytes))
+				.context("Could not insert record")?;
+		}
+	}
+	write_txn.commit().context("Could not commit transaction")?;
+	Ok(())
+}
+
+/// The fingerprint, and the modified timestamp if it should be cached
+type FingerprintResult = Result<(Vec<u32>, Option<i64>)>;
+
+fn get_fingerprint(path: &Path, cancelled: &AtomicBool) -> FingerprintResult {
+	let path_str = path.to_string_lossy();
+	let date_modified_ms: Option<i64> =
+		get_modified_timestamp_ms(&path_str)?.map(|n| n.try_into().unwrap());
+	if let Some(date_modified_ms) = date_modified_ms {
+		let cache_db_lock = CACHE_DB.read().unwrap();
+		let cache_db = cache_db_lock.as_ref().context("Cache.redb was closed")?;
+		if let Some(fingerprint) = get_cached_fingerprint(cache_db, &path_str, date_modified_ms)? {
+			return Ok((fingerprint, None));
+		}
+	}
+	Ok((fingerprint_file(path, cancelled)?, date_modified_ms))
+}
+
+#[napi(object)]
+pub struct AcousticDuplicateCluster {
+	pub item_ids: Vec<ItemId>,
+	/// Lowest similarity between 0 and 1 of the matches that formed the
+	/// cluster
+	pub similarity: f64,
+}
+
+#[napi(object)]
+pub struct AcousticDuplicatesStatus {
+	pub clusters: Vec<AcousticDuplicateCluster>,
+	pub errors: Vec<String>,
+	pub cancelled: bool,
+}
+
+#[derive(Default)]
+struct ComparisonProgress {
+	done_count: AtomicU64,
+	total_count: AtomicU64,
+}
+
+#[derive(Clone)]
+struct SearchItem {
+	item_id: ItemId,
+	path: PathBuf,
+	duration: f64,
+}
+
+/// Finds tracks that are the same recording, by comparing fingerprints of
+/// their audio. Fingerprints are cached in Cache.redb.
+#[napi]
+pub struct AcousticDuplicateSearch {
+	items: Vec<SearchItem>,
+	cache_db_path: String,
+	min_similarity: f64,
+	cancelled: Arc<AtomicBool>,
+	done_count: Arc<AtomicU32>,
+	comparison_progress: Arc<ComparisonProgress>,
+}
+#[napi]
+impl AcousticDuplicateSearch {
+	/// `min_similarity` is between 0 and 1, and defaults to 0.75
+	#[napi(factory)]
+	pub fn new(item_ids: Vec<ItemId>, min_similarity: Option<f64>, env: Env) -> napi::Result<Self> {
+		let data = get_data(&env);
+		let id_map = TRACK_ID_MAP.read().unwrap();
+		let mut items = Vec::new();
+		for item_id in item_ids {
+			let track_id = id_map.get(item_id as usize).context("Item ID not found")?;
+			let track = data.library.get_track(track_id)?;
+			items.push(SearchItem {
+				item_id,
+				path: data.paths.get_track_file_path(&track.file),
+				duration: track.duration,
+			});
+		}
+		Ok(Self {
+			items,
+			cache_db_path: data.paths.cache_db.clone(),
+			min_similarity: min_similarity.unwrap_or(DEFAULT_MIN_SIMILARITY),
+			cancelled: Arc::new(AtomicBool::new(false)),
+			done_count: Arc::new(AtomicU32::new(0)),
+			comparison_progress: Arc::new(ComparisonProgress::default()),
+		})
+	}
+	#[napi]
+	pub async fn start(&self) -> napi::Result<AcousticDuplicatesStatus> {
+		let items = self.items.clone();
+		let cache_db_path = self.cache_db_path.clone();
+		let min_similarity = self.min_similarity;
+		let cancelled = self.cancelled.clone();
+		let done_count = self.done_count.clone();
+		let comparison_progress = self.comparison_progress.clone();
+		let status = tokio::task::spawn_blocking(move || {
+			run_search(
+				&items,
+				cache_db_path,
+				min_similarity,
+				&cancelled,
+				&done_count,
+				&comparison_progress,
+			)
+		})
+		.await
+		.context("Duplicate search thread failed")??;
+		Ok(status)
+	}
+	/// Tracks that were fingerprinted or failed
+	#[napi]
+	pub fn progress(&self) -> AnalysisProgress {
+		AnalysisProgress {
+			done_count: self.done_count.load(Ordering::Relaxed).into(),
+			total_count: self.items.len() as i64,
+		}
+	}
+	/// Pairs of tracks that were compared, out of the pairs that share parts
+	/// of their fingerprints. The total is 0 until fingerprinting is done.
+	#[napi]
+	pub fn comparison_progress(&self) -> AnalysisProgress {
+		let progress = &self.comparison_progress;
+		AnalysisProgress {
+			done_count: progress.done_count.load(Ordering::Relaxed) as i64,
+			total_count: progress.total_count.load(Ordering::Relaxed) as i64,
+		}
+	}
+	/// Stops fingerprinting and comparing. Duplicates among the pairs that
+	/// were already compared are still returned.
+	#[napi]
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+}
+
+fn run_search(
+	items: &[SearchItem],
+	cache_db_path: String,
+	min_similarity: f64,
+	cancelled: &AtomicBool,
+	done_count: &AtomicU32,
+	comparison_progress: &ComparisonProgress,
+) -> Result<AcousticDuplicatesStatus> {
+	init_cache_db(cache_db_path)?;
+	let fingerprinted: Vec<Option<FingerprintResult>> = items
+		.par_iter()
+		.map(|item| {
+			if cancelled.load(Ordering::Relaxed) {
+				return None;
+			}
+			let result = get_fingerprint(&item.path, cancelled);
+			done_count.fetch_add(1, Ordering::Relaxed);
+			Some(result)
+		})
+		.collect();
+	let is_cancelled = cancelled.load(Ordering::Relaxed);
+
+	let mut errors = Vec::new();
+	let mut tracks = Vec::new();
+	let mut new_entries = Vec::new();
+	for (item, result) in items.iter().zip(fingerprinted) {
+		let (fingerprint, date_modified_ms) = match result {
+			Some(Ok(result)) => result,
+			Some(Err(e)) if !is_cancelled => {
+				errors.push(format!("{}: {e:#}", item.path.to_string_lossy()));
+				continue;
+			}
+			_ => continue,
+		};
+		if let Some(date_modified_ms) = date_modified_ms {
+			new_entries.push((
+				item.path.to_string_lossy().into_owned(),
+				date_modified_ms,
+				fingerprint.clone(),
+			));
+		}
+		tracks.push(FingerprintedTrack {
+			item_id: item.item_id,
+			duration: item.duration,
+			fingerprint,
+		});
+	}
+
+	if !new_entries.is_empty() {
+		let cache_db_lock = CACHE_DB.read().unwrap();
+		let cache_db = cache_db_lock.as_ref().context("Cache.redb was closed")?;
+		write_fingerprints_to_cache(cache_db, &new_entries)?;
+	}
+
+	let clusters = find_duplicates(&tracks, min_similarity, cancelled, comparison_progress);
+	Ok(AcousticDuplicatesStatus {
+		clusters,
+		errors,
+		cancelled: cancelled.load(Ordering::Relaxed),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Xorshift, so tests don't depend on a random number crate
+	fn random_values(seed: u32) -> impl Iterator<Item = u32> {
+		let mut state = seed.wrapping_mul(0x9E37_79B9) | 1;
+		std::iter::repeat_with(move || {
+			state ^= state << 13;
+			state ^= state >> 17;
+			state ^= state << 5;
+			state
+		})
+	}
+
+	fn track(item_id: ItemId, duration: f64, fingerprint: Vec<u32>) -> FingerprintedTrack {
+		FingerprintedTrack {
+			item_id,
+			duration,
+			fingerprint,
+		}
+	}
+
+	/// A copy that starts a few frames later, with about 1 in 8 bits flipped
+	fn noisy_copy(fingerprint: &[u32], seed: u32) -> Vec<u32> {
+		let mut noise = random_values(seed);
+		fingerprint[8..]
+			.iter()
+			.map(|sub| {
+				let flips = noise.next().unwrap() & noise.next().unwrap() & noise.next().unwrap();
+				sub ^ flips
+			})
+			.collect()
+	}
+
+	#[test]
+	fn candidate_pairs_test() {
+		let frames = (MAX_SECONDS / HOP_SECONDS) as usize;
+		let original: Vec<u32> = random_values(1).take(frames).collect();
+		let mut tracks = vec![
+			track(0, 200.0, original.clone()),
+			track(1, 201.0, noisy_copy(&original, 2)),
+			// Too different in duration
+			track(2, 220.0, original.clone()),
+		];
+		for i in 3..30 {
+			tracks.push(track(i, 200.0, random_values(i).take(frames).collect()));
+		}
+		let pairs = candidate_pairs(&tracks);
+		assert!(pairs.contains(&(0, 1)));
+		assert!(!pairs.iter().any(|&(a, b)| a == 2 || b == 2));
+		// A few unrelated pairs can share sub-hashes by chance
+		let pair_count = tracks.len() * (tracks.len() - 1) / 2;
+		assert!(pairs.len() < pair_count / 20, "{pairs:?}");
+	}
+
+	#[test]
+	fn find_duplicates_test() {
+		let frames = (MAX_SECONDS / HOP_SECONDS) as usize;
+		let original: Vec<u32> = random_values(1).take(frames).collect();
+		let tracks = [
+			track(10, 200.0, original.clone()),
+			track(11, 180.0, random_values(3).take(frames).collect()),
+			track(12, 203.0, noisy_copy(&original, 2)),
+			track(13, 205.0, noisy_copy(&original, 4)),
+		];
+		let progress = ComparisonProgress::default();
+		let clusters = find_duplicates(&tracks, 0.75, &AtomicBool::new(false), &progress);
+		assert_eq!(clusters.len(), 1);
+		assert_eq!(clusters[0].item_ids, [10, 12, 13]);
+		assert!((0.75..0.9).contains(&clusters[0].similarity));
+		let compared = progress.done_count.load(Ordering::Relaxed);
+		assert_eq!(compared, progress.total_count.load(Ordering::Relaxed));
+		assert_eq!(compared, 3);
+
+		let progress = ComparisonProgress::default();
+		let clusters = find_duplicates(&tracks, 0.75, &AtomicBool::new(true), &progress);
+		assert!(clusters.is_empty());
+		assert_eq!(progress.done_count.load(Ordering::Relaxed), 0);
+		assert_eq!(progress.total_count.load(Ordering::Relaxed), 3);
+	}
+}
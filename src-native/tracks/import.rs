@@ -1,26 +1,90 @@
-use crate::data::Data;
-use crate::library_types::Track;
+use super::AnalysisProgress;
+use crate::data::{Data, path_to_string};
+use crate::data_js::get_data;
+use crate::library::Paths;
+use crate::library_types::{Library, MsSinceUnixEpoch, Track, TrackID};
+use crate::playlists::delete_file;
 use crate::sys_time_to_timestamp;
 use crate::tracks::generate_filename;
-use anyhow::{Context, Result, bail};
-use lofty::file::{AudioFile, TaggedFileExt};
+use crate::tracks::tag::Tag;
+use alphanumeric_sort::compare_path;
+use anyhow::{Context, Result, anyhow, bail};
+use lofty::config::{ParseOptions, ParsingMode};
+use lofty::file::{AudioFile, TaggedFile, TaggedFileExt};
+use lofty::properties::FileProperties;
 use lofty::tag::{Accessor, ItemKey, TagExt};
+use napi::Env;
+use rayon::prelude::*;
+use std::cell::OnceCell;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 
-#[derive(PartialEq)]
+/// Tags are written using lofty's primary tag type for each format. For WAV
+/// and AIFF that is an ID3v2 tag stored in an `ID3 ` chunk. Existing RIFF INFO
+/// and AIFF text chunks are read as a fallback, but never written.
+#[derive(PartialEq, Debug)]
 pub enum FileType {
 	Mp3,
 	M4a,
 	Opus,
+	Flac,
+	Ogg,
+	Wav,
+	Aiff,
 }
 impl FileType {
+	/// Like `from_extension`, but the codec of `.ogg` files is read from the
+	/// file, since they can contain Opus as well as Vorbis
 	pub fn from_path(path: &Path) -> Result<Self> {
-		let ext = path.extension().unwrap_or_default().to_string_lossy();
+		match Self::from_extension(path)? {
+			FileType::Ogg => {
+				let mut header = Vec::new();
+				let file = fs::File::open(path).context("File does not exist")?;
+				file.take(512)
+					.read_to_end(&mut header)
+					.context("Unable to read file")?;
+				Self::from_ogg_header(&header)
+			}
+			file_type => Ok(file_type),
+		}
+	}
+	/// The file type for the codec in the first page of an Ogg stream
+	fn from_ogg_header(header: &[u8]) -> Result<Self> {
+		if !header.starts_with(b"OggS") || header.len() < 27 {
+			bail!("Invalid Ogg file");
+		}
+		// The page header is followed by a table of segment sizes and then
+		// the first packet, which identifies the codec
+		let packet_start = 27 + usize::from(header[26]);
+		let packet = header.get(packet_start..).unwrap_or_default();
+		if packet.starts_with(b"OpusHead") {
+			Ok(FileType::Opus)
+		} else if packet.starts_with(b"\x01vorbis") {
+			Ok(FileType::Ogg)
+		} else {
+			bail!("Unsupported Ogg codec")
+		}
+	}
+	/// Files with an `.ogg` extension are assumed to be Vorbis
+	pub fn from_extension(path: &Path) -> Result<Self> {
+		let ext = path
+			.extension()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.to_lowercase();
 		match ext.as_ref() {
 			"mp3" => Ok(FileType::Mp3),
 			"m4a" => Ok(FileType::M4a),
 			"opus" => Ok(FileType::Opus),
+			"flac" => Ok(FileType::Flac),
+			"ogg" => Ok(FileType::Ogg),
+			"wav" | "wave" => Ok(FileType::Wav),
+			"aiff" | "aif" => Ok(FileType::Aiff),
 			_ => bail!("Unsupported file extension {}", ext),
 		}
 	}
@@ -29,6 +93,10 @@ impl FileType {
 			lofty::file::FileType::Mpeg => Ok(FileType::Mp3),
 			lofty::file::FileType::Mp4 => Ok(FileType::M4a),
 			lofty::file::FileType::Opus => Ok(FileType::Opus),
+			lofty::file::FileType::Flac => Ok(FileType::Flac),
+			lofty::file::FileType::Vorbis => Ok(FileType::Ogg),
+			lofty::file::FileType::Wav => Ok(FileType::Wav),
+			lofty::file::FileType::Aiff => Ok(FileType::Aiff),
 			_ => bail!("Unsupported file type {:?}", lofty_type),
 		}
 	}
@@ -37,6 +105,10 @@ impl FileType {
 			FileType::Mp3 => "mp3",
 			FileType::M4a => "m4a",
 			FileType::Opus => "opus",
+			FileType::Flac => "flac",
+			FileType::Ogg => "ogg",
+			FileType::Wav => "wav",
+			FileType::Aiff => "aiff",
 		}
 	}
 }
@@ -46,6 +118,24 @@ impl std::fmt::Display for FileType {
 	}
 }
 
+/// Whether the file of a track belongs to the library, so analyses can write
+/// tags to it. Referenced files are outside of `tracks_dir`, and hardlinked
+/// files are shared with another location. Hardlinks are only detected on
+/// Unix.
+pub fn owns_track_file(track: &Track, path: &Path) -> bool {
+	if track.is_referenced() {
+		return false;
+	}
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		if fs::metadata(path).is_ok_and(|md| md.nlink() > 1) {
+			return false;
+		}
+	}
+	true
+}
+
 pub fn read_file_metadata(path: &Path) -> Result<fs::Metadata> {
 	match std::fs::metadata(path) {
 		Ok(file_md) => Ok(file_md),
@@ -56,25 +146,135 @@ pub fn read_file_metadata(path: &Path) -> Result<fs::Metadata> {
 	}
 }
 
-pub fn import(data: &Data, track_path: &Path, now: i64) -> Result<Track> {
+/// Used when a file has no tag of its primary tag type, for example WAV files
+/// with only a RIFF INFO chunk. The tag is converted to the primary tag type so
+/// that saving it writes the primary tag.
+pub fn fallback_tag(tagged_file: &lofty::file::TaggedFile) -> lofty::tag::Tag {
+	let primary_tag_type = tagged_file.primary_tag_type();
+	match tagged_file.first_tag() {
+		Some(tag) => {
+			let mut tag = tag.clone();
+			tag.re_map(primary_tag_type);
+			tag
+		}
+		None => lofty::tag::Tag::new(primary_tag_type),
+	}
+}
+
+/// A parsed file that hasn't been copied to `tracks_dir` yet. Preparing is
+/// safe to do in parallel, but copying is not because of `generate_filename`.
+pub struct PreparedImport {
+	path: PathBuf,
+	file_type: FileType,
+	/// Tag that needs to be written to the copied file
+	changed_tag: Option<lofty::tag::Tag>,
+	/// `file` is empty until the file has been copied
+	track: Track,
+	/// Problems with the file that were worked around
+	pub warnings: Vec<String>,
+}
+
+fn read_tagged_file(path: &Path, parsing_mode: ParsingMode) -> Result<TaggedFile> {
+	let parse_options = ParseOptions::new()
+		.read_properties(true)
+		.parsing_mode(parsing_mode);
+	let probe = lofty::probe::Probe::open(path)
+		.context("File does not exist")?
+		.options(parse_options);
+	probe.read().context("Unable to read file")
+}
+
+/// Files are parsed strictly first. If that fails, they are parsed leniently
+/// and a warning is added.
+fn read_tagged_file_leniently(path: &Path, warnings: &mut Vec<String>) -> Result<TaggedFile> {
+	match read_tagged_file(path, ParsingMode::Strict) {
+		Ok(tagged_file) => Ok(tagged_file),
+		Err(e) => match read_tagged_file(path, ParsingMode::Relaxed) {
+			Ok(tagged_file) => {
+				warnings.push(format!("File was read leniently: {e:#}"));
+				Ok(tagged_file)
+			}
+			Err(_) => Err(e),
+		},
+	}
+}
+
+pub struct AudioProperties {
+	pub duration: f64,
+	pub bitrate: f64,
+	pub sample_rate: f64,
+}
+
+/// Missing bitrates are estimated from the file size, and other missing
+/// properties are 0
+fn get_audio_properties(
+	properties: &FileProperties,
+	size: i64,
+	warnings: &mut Vec<String>,
+) -> AudioProperties {
+	let duration = properties.duration().as_secs_f64();
+	if duration == 0.0 {
+		warnings.push("Unknown duration".to_string());
+	}
+	let bitrate = match properties.audio_bitrate() {
+		Some(kbps) if kbps > 0 => (kbps * 1000).into(), // kbps to bps
+		// VBR files without a header, for example
+		_ if duration > 0.0 => {
+			warnings.push("Missing bitrate, estimated from file size".to_string());
+			size as f64 * 8.0 / duration
+		}
+		_ => {
+			warnings.push("Unknown bitrate".to_string());
+			0.0
+		}
+	};
+	let sample_rate = match properties.sample_rate() {
+		Some(sample_rate) if sample_rate > 0 => sample_rate.into(),
+		_ => {
+			warnings.push("Unknown sample rate".to_string());
+			0.0
+		}
+	};
+	AudioProperties {
+		duration,
+		bitrate,
+		sample_rate,
+	}
+}
+
+/// Reads the audio properties of a file the same way `prepare` does, for
+/// imports that take the metadata from elsewhere
+pub fn read_audio_properties(
+	path: &Path,
+	size: i64,
+	warnings: &mut Vec<String>,
+) -> Result<AudioProperties> {
+	let tagged_file = read_tagged_file_leniently(path, warnings)?;
+	Ok(get_audio_properties(
+		tagged_file.properties(),
+		size,
+		warnings,
+	))
+}
+
+/// Files are parsed strictly first. If that fails, they are parsed leniently
+/// and a warning is added. Missing bitrates are estimated from the file size,
+/// and other missing properties are stored as 0.
+pub fn prepare(track_path: &Path, now: i64) -> Result<PreparedImport> {
 	let file_md = read_file_metadata(track_path)?;
+	let file_type = FileType::from_path(track_path)?;
 
-	let mut date_modified = match file_md.modified() {
+	let date_modified = match file_md.modified() {
 		Ok(sys_time) => sys_time_to_timestamp(&sys_time),
 		Err(_) => now,
 	};
 
-	let parse_options = lofty::config::ParseOptions::new()
-		.read_properties(true)
-		.parsing_mode(lofty::config::ParsingMode::Strict);
-	let probe = lofty::probe::Probe::open(track_path)
-		.context("File does not exist")?
-		.options(parse_options);
-	let mut tagged_file = probe.read().context("Unable to read file")?;
+	let mut warnings = Vec::new();
+	let mut tagged_file = read_tagged_file_leniently(track_path, &mut warnings)?;
 	let properties = tagged_file.properties().clone();
 
 	let mut tag_changed = false;
-	let mut default_tag = lofty::tag::Tag::new(tagged_file.primary_tag_type());
+	let mut default_tag = fallback_tag(&tagged_file);
 	let tag = match tagged_file.primary_tag_mut() {
 		Some(tag) => tag,
 		None => &mut default_tag,
@@ -94,41 +294,15 @@ pub fn import(data: &Data, track_path: &Path, now: i64) -> Result<Track> {
 	};
 	let artist = tag.artist().map(|s| s.into_owned()).unwrap_or_default();
 
-	let extension = match FileType::from_path(track_path)? {
-		FileType::Opus => "opus",
-		FileType::M4a => "m4a",
-		FileType::Mp3 => "mp3",
-	};
-	let filename = generate_filename(&data.paths, &artist, &title, extension);
-	let dest_path = data.paths.get_track_file_path(&filename);
-
-	fs::copy(track_path, &dest_path).context("Error copying file")?;
-	println!(
-		"{} -> {}",
-		track_path.to_string_lossy(),
-		dest_path.to_string_lossy()
-	);
-
-	if tag_changed {
-		println!("Writing tag to imported file");
-		match tag.save_to_path(&dest_path, lofty::config::WriteOptions::default()) {
-			Ok(_) => (),
-			Err(e) => bail!("Unable to tag file {}: {e}", dest_path.to_string_lossy()),
-		};
-		// manually set date_modified because the date_modified doens't seem to
-		// immediately update after tag.write_to_path().
-		date_modified = now;
-	}
+	let size: i64 = file_md.len().try_into().unwrap();
+	let audio = get_audio_properties(&properties, size, &mut warnings);
 
 	let track = Track {
-		size: file_md.len().try_into().unwrap(),
-		duration: properties.duration().as_secs_f64(),
-		bitrate: (properties.audio_bitrate().expect("Missing bitrate") * 1000).into(), // kbps to bps
-		sampleRate: properties
-			.sample_rate()
-			.expect("Missing sample rate")
-			.into(),
-		file: filename,
+		size,
+		duration: audio.duration,
+		bitrate: audio.bitrate,
+		sampleRate: audio.sample_rate,
+		file: String::new(),
 		dateModified: date_modified,
 		dateAdded: now,
 		name: title,
@@ -148,11 +322,19 @@ pub fn import(data: &Data, track_path: &Path, now: i64) -> Result<Track> {
 		genre: tag.genre().map(|s| s.into_owned()),
 		rating: None,
 		year: tag.year().map(|y| y.into()),
-		bpm: match tag.get_string(&ItemKey::Bpm) {
+		bpm: match tag
+			.get_string(&ItemKey::Bpm)
+			.or_else(|| tag.get_string(&ItemKey::IntegerBpm))
+		{
 			Some(n) => n.parse().ok(),
 			None => None,
 		},
-		comments: tag.comment().map(|s| s.into_owned()),
+		key: tag.get_string(&ItemKey::InitialKey).map(|s| s.to_string()),
+		comments: tag.comment().map(|s| s.into_owned()).or_else(|| {
+			// Vorbis comments are often stored as DESCRIPTION instead of COMMENT
+			tag.get_string(&ItemKey::Unknown("DESCRIPTION".to_string()))
+				.map(|s| s.to_string())
+		}),
 		grouping: tag
 			.get_string(&ItemKey::ContentGroup)
 			.map(|s| s.to_string())
@@ -184,6 +366,1043 @@ pub fn import(data: &Data, track_path: &Path, now: i64) -> Result<Track> {
 		skips: None,
 		skipsImported: None,
 		volume: None,
+		startTime: None,
+		stopTime: None,
+		albumRating: None,
+		albumLiked: None,
+		equalizer: None,
+		loudness: None,
+		peak: None,
+		albumLoudness: None,
+		albumPeak: None,
 	};
+	Ok(PreparedImport {
+		path: track_path.to_path_buf(),
+		file_type,
+		changed_tag: match tag_changed {
+			true => Some(tag.clone()),
+			false => None,
+		},
+		track,
+		warnings,
+	})
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum ImportMode {
+	/// Copy the file into `tracks_dir`
+	Copy,
+	/// Move the file into `tracks_dir`
+	Move,
+	/// Hardlink the file into `tracks_dir`. Tag edits will also change the
+	/// original file
+	Hardlink,
+	/// Keep the file at its original location. `Track.file` is then an
+	/// absolute path
+	Reference,
+}
+impl ImportMode {
+	/// Defaults to `Copy`
+	pub fn from_option(mode: Option<String>) -> Result<Self> {
+		match mode.as_deref() {
+			None | Some("copy") => Ok(ImportMode::Copy),
+			Some("move") => Ok(ImportMode::Move),
+			Some("hardlink") => Ok(ImportMode::Hardlink),
+			Some("reference") => Ok(ImportMode::Reference),
+			Some(mode) => bail!("Unknown import mode {mode}"),
+		}
+	}
+	/// Returns the `Track.file` for a file imported with this mode
+	pub fn track_file(
+		&self,
+		paths: &Paths,
+		src_path: &Path,
+		artist: &str,
+		title: &str,
+		file_type: &FileType,
+	) -> Result<String> {
+		match self {
+			ImportMode::Reference => {
+				let path = std::path::absolute(src_path).context("Invalid file path")?;
+				Ok(path_to_string(path))
+			}
+			_ => Ok(generate_filename(
+				paths,
+				artist,
+				title,
+				file_type.file_extension(),
+			)),
+		}
+	}
+	pub fn transfer_file(&self, src_path: &Path, dest_path: &Path) -> Result<()> {
+		match self {
+			ImportMode::Copy => {
+				fs::copy(src_path, dest_path).context("Error copying file")?;
+			}
+			ImportMode::Move => {
+				if fs::rename(src_path, dest_path).is_err() {
+					// Renaming doesn't work across file systems
+					fs::copy(src_path, dest_path).context("Error copying file")?;
+					fs::remove_file(src_path).context("Error removing original file")?;
+				}
+			}
+			ImportMode::Hardlink => {
+				fs::hard_link(src_path, dest_path).context("Error hardlinking file")?;
+			}
+			ImportMode::Reference => return Ok(()),
+		}
+		println!(
+			"{} -> {}",
+			src_path.to_string_lossy(),
+			dest_path.to_string_lossy()
+		);
+		Ok(())
+	}
+}
+
+impl PreparedImport {
+	pub fn add_to_library(self, paths: &Paths, mode: ImportMode, now: i64) -> Result<Track> {
+		let mut track = self.track;
+		let file = mode.track_file(
+			paths,
+			&self.path,
+			&track.artist,
+			&track.name,
+			&self.file_type,
+		)?;
+		let dest_path = paths.get_track_file_path(&file);
+		mode.transfer_file(&self.path, &dest_path)?;
+
+		// Files outside of tracks_dir, or shared with it, are not modified
+		let owns_file = matches!(mode, ImportMode::Copy | ImportMode::Move);
+		if let (Some(tag), true) = (self.changed_tag, owns_file) {
+			println!("Writing tag to imported file");
+			match tag.save_to_path(&dest_path, lofty::config::WriteOptions::default()) {
+				Ok(_) => (),
+				Err(e) => bail!("Unable to tag file {}: {e}", dest_path.to_string_lossy()),
+			};
+			// manually set date_modified because the date_modified doens't seem to
+			// immediately update after tag.write_to_path().
+			track.dateModified = now;
+		}
+
+		track.file = file;
+		Ok(track)
+	}
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum DuplicateAction {
+	/// Import likely duplicates as new tracks
+	Import,
+	/// Keep the existing track and don't import the file
+	Skip,
+	/// Point the existing track to the imported file, keeping its ID, tags,
+	/// plays, skips and playlist memberships
+	Replace,
+}
+impl DuplicateAction {
+	/// Defaults to `Import`
+	pub fn from_option(action: Option<String>) -> Result<Self> {
+		match action.as_deref() {
+			None | Some("import") => Ok(DuplicateAction::Import),
+			Some("skip") => Ok(DuplicateAction::Skip),
+			Some("replace") => Ok(DuplicateAction::Replace),
+			Some(action) => bail!("Unknown duplicate action {action}"),
+		}
+	}
+}
+
+#[napi(object)]
+pub struct ImportOptions {
+	/// "copy" (default), "move", "hardlink" or "reference"
+	pub mode: Option<String>,
+	/// What to do with likely duplicates: "import" (default), "skip" or "replace"
+	pub duplicates: Option<String>,
+	/// Only treat tracks as duplicates if their files are identical
+	pub compare_hash: Option<bool>,
+}
+
+pub struct ImportSettings {
+	pub mode: ImportMode,
+	pub duplicates: DuplicateAction,
+	pub compare_hash: bool,
+}
+impl ImportSettings {
+	pub fn from_options(options: Option<ImportOptions>) -> Result<Self> {
+		let options = options.unwrap_or(ImportOptions {
+			mode: None,
+			duplicates: None,
+			compare_hash: None,
+		});
+		Ok(ImportSettings {
+			mode: ImportMode::from_option(options.mode)?,
+			duplicates: DuplicateAction::from_option(options.duplicates)?,
+			compare_hash: options.compare_hash.unwrap_or(false),
+		})
+	}
+}
+
+fn hash_file(path: &Path) -> Result<u64> {
+	let mut file = fs::File::open(path)
+		.with_context(|| format!("Unable to open file {}", path.to_string_lossy()))?;
+	let mut hasher = DefaultHasher::new();
+	let mut buf = vec![0; 64 * 1024];
+	loop {
+		let len = file.read(&mut buf).context("Unable to read file")?;
+		if len == 0 {
+			break;
+		}
+		hasher.write(&buf[..len]);
+	}
+	Ok(hasher.finish())
+}
+
+struct DuplicateCandidate {
+	/// `None` for files imported in the same batch that aren't in the library
+	/// yet
+	id: Option<TrackID>,
+	duration: f64,
+	size: i64,
+	path: PathBuf,
+	/// Hash of the file, once it has been compared
+	hash: OnceCell<Option<u64>>,
+}
+
+/// Tracks by lowercase title and artist, for finding likely duplicates. Built
+/// once per import, and updated as files are imported.
+pub struct DuplicateIndex {
+	tracks: HashMap<(String, String), Vec<DuplicateCandidate>>,
+}
+impl DuplicateIndex {
+	/// Empty if duplicates are imported anyway
+	pub fn new(library: &Library, paths: &Paths, duplicates: DuplicateAction) -> Self {
+		let mut index = DuplicateIndex {
+			tracks: HashMap::new(),
+		};
+		if duplicates == DuplicateAction::Import {
+			return index;
+		}
+		for (id, track) in library.get_tracks() {
+			index.insert(Some(id.clone()), track, paths);
+		}
+		index
+	}
+	fn insert(&mut self, id: Option<TrackID>, track: &Track, paths: &Paths) {
+		self.insert_path(id, track, paths.get_track_file_path(&track.file));
+	}
+	fn insert_path(&mut self, id: Option<TrackID>, track: &Track, path: PathBuf) {
+		let key = (track.name.to_lowercase(), track.artist.to_lowercase());
+		self.tracks
+			.entry(key)
+			.or_default()
+			.push(DuplicateCandidate {
+				id,
+				duration: track.duration,
+				size: track.size,
+				path,
+				hash: OnceCell::new(),
+			});
+	}
+	/// Updates the candidate of a track whose file was replaced
+	fn update(&mut self, id: &TrackID, track: &Track, paths: &Paths) {
+		for candidates in self.tracks.values_mut() {
+			candidates.retain(|candidate| candidate.id.as_ref() != Some(id));
+		}
+		self.insert(Some(id.clone()), track, paths);
+	}
+}
+
+impl PreparedImport {
+	/// Finds an existing track with the same artist, title, duration (within a
+	/// second) and size. With `compare_hash`, the files also need to be
+	/// identical.
+	fn find_duplicate<'a>(
+		&self,
+		index: &'a DuplicateIndex,
+		compare_hash: bool,
+	) -> Result<Option<&'a DuplicateCandidate>> {
+		let key = (
+			self.track.name.to_lowercase(),
+			self.track.artist.to_lowercase(),
+		);
+		let Some(candidates) = index.tracks.get(&key) else {
+			return Ok(None);
+		};
+		let mut hash = None;
+		for candidate in candidates {
+			if (candidate.duration - self.track.duration).abs() >= 1.0 {
+				continue;
+			}
+			// `Track.size` can be outdated after tag edits
+			let existing_size = match fs::metadata(&candidate.path) {
+				Ok(md) => md.len() as i64,
+				Err(_) => candidate.size,
+			};
+			// A file that gets a tag written on import has a different size in
+			// the library than the original file
+			if self.changed_tag.is_none() && existing_size != self.track.size {
+				continue;
+			}
+			if compare_hash {
+				if hash.is_none() {
+					hash = Some(hash_file(&self.path)?);
+				}
+				let candidate_hash = candidate
+					.hash
+					.get_or_init(|| hash_file(&candidate.path).ok());
+				if *candidate_hash != hash {
+					continue;
+				}
+			}
+			return Ok(Some(candidate));
+		}
+		Ok(None)
+	}
+}
+
+/// Points an existing track to the file of `new_track`, and moves the old
+/// file to the trash unless it's referenced or missing. If that fails, the
+/// error is added to `warnings`. Loudness measured from the old file is
+/// cleared, including the album loudness of the other tracks of its album.
+/// Returns the updated track.
+fn replace_track_file(
+	library: &mut Library,
+	paths: &Paths,
+	id: &TrackID,
+	new_track: Track,
+	warnings: &mut Vec<String>,
+) -> Result<Track> {
+	let track = library.get_track_mut(id)?;
+	let old_file = std::mem::replace(&mut track.file, new_track.file);
+	track.size = new_track.size;
+	track.duration = new_track.duration;
+	track.bitrate = new_track.bitrate;
+	track.sampleRate = new_track.sampleRate;
+	track.dateModified = new_track.dateModified;
+	// Measured from the old audio
+	track.loudness = None;
+	track.peak = None;
+	track.albumLoudness = None;
+	track.albumPeak = None;
+	let track = track.clone();
+	// Album loudness is measured over all tracks of the album
+	let album_ids: Vec<TrackID> = (library.get_tracks().iter())
+		.filter(|(_, other)| other.is_same_album(&track))
+		.map(|(album_id, _)| album_id.clone())
+		.collect();
+	for album_id in album_ids {
+		let album_track = library.get_track_mut(&album_id)?;
+		album_track.albumLoudness = None;
+		album_track.albumPeak = None;
+	}
+
+	let old_path = paths.get_track_file_path(&old_file);
+	if !Path::new(&old_file).is_absolute()
+		&& old_path.exists()
+		&& let Err(e) = delete_file(&old_path)
+	{
+		warnings.push(format!("The old file was not moved to the trash: {e:#}"));
+	}
 	Ok(track)
 }
+
+/// Sets the fields of `track` that are read from tags
+fn set_tag_fields(track: &mut Track, from: Track) {
+	track.name = from.name;
+	track.artist = from.artist;
+	track.composer = from.composer;
+	track.sortName = from.sortName;
+	track.sortArtist = from.sortArtist;
+	track.sortComposer = from.sortComposer;
+	track.genre = from.genre;
+	track.year = from.year;
+	track.bpm = from.bpm;
+	track.key = from.key;
+	track.comments = from.comments;
+	track.grouping = from.grouping;
+	track.albumName = from.albumName;
+	track.albumArtist = from.albumArtist;
+	track.sortAlbumName = from.sortAlbumName;
+	track.sortAlbumArtist = from.sortAlbumArtist;
+	track.trackNum = from.trackNum;
+	track.trackCount = from.trackCount;
+	track.discNum = from.discNum;
+	track.discCount = from.discCount;
+}
+
+/// Copies a different file into `tracks_dir` and points an existing track to
+/// it, keeping its ID, plays, skips and playlist memberships. With
+/// `keep_tags`, the tags and artwork of the old file are written to the new
+/// file, or the track's metadata if the old file can't be read. Otherwise the
+/// track's metadata is read from the new file.
+pub fn relink(
+	data: &mut Data,
+	id: &TrackID,
+	path: &Path,
+	keep_tags: bool,
+	now: i64,
+) -> Result<ImportedTrack> {
+	let old_track = data.library.get_track(id)?;
+	let old_path = data.paths.get_track_file_path(&old_track.file);
+	let same_file = match (fs::canonicalize(path), fs::canonicalize(&old_path)) {
+		(Ok(a), Ok(b)) => a == b,
+		_ => false,
+	};
+	if same_file {
+		bail!("The track already uses this file");
+	}
+	let mut prepared = prepare(path, now)?;
+	let mut warnings = std::mem::take(&mut prepared.warnings);
+	let old_tag = match keep_tags {
+		true => match Tag::read_from_path(&old_path) {
+			Ok(tag) => Some(tag),
+			Err(e) => {
+				warnings.push(format!(
+					"The old tags could not be read, so the tags were written from the track's info: {e:#}"
+				));
+				Some(Tag::from_track(old_track))
+			}
+		},
+		false => None,
+	};
+	if old_tag.is_some() {
+		// Name the file after the tags it will get
+		prepared.track.name = old_track.name.clone();
+		prepared.track.artist = old_track.artist.clone();
+		prepared.changed_tag = None;
+	}
+	let mut new_track = prepared.add_to_library(&data.paths, ImportMode::Copy, now)?;
+
+	if let Some(mut tag) = old_tag {
+		let dest_path = data.paths.get_track_file_path(&new_track.file);
+		if let Err(e) = tag.write_converted_to_path(&dest_path) {
+			let _ = fs::remove_file(&dest_path);
+			return Err(e.context("Unable to write old tags"));
+		}
+		new_track.size = read_file_metadata(&dest_path)?.len() as i64;
+		new_track.dateModified = now;
+	} else {
+		let track = data.library.get_track_mut(id)?;
+		set_tag_fields(track, new_track.clone());
+	}
+
+	let track = replace_track_file(&mut data.library, &data.paths, id, new_track, &mut warnings)?;
+	Ok(ImportedTrack {
+		id: id.clone(),
+		track,
+		warnings,
+		duplicate_of: None,
+	})
+}
+
+#[napi(object)]
+pub struct ImportedTrack {
+	/// The new track, or the existing track if the file was skipped or replaced
+	pub id: TrackID,
+	pub track: Track,
+	/// Problems with the file that were worked around
+	pub warnings: Vec<String>,
+	/// The existing track that the file is a likely duplicate of
+	pub duplicate_of: Option<TrackID>,
+}
+
+fn add_prepared(
+	data: &mut Data,
+	mut prepared: PreparedImport,
+	settings: &ImportSettings,
+	index: &mut DuplicateIndex,
+	now: i64,
+) -> Result<ImportedTrack> {
+	let duplicate_of = prepared
+		.find_duplicate(index, settings.compare_hash)?
+		.and_then(|candidate| candidate.id.clone());
+	let mut warnings = std::mem::take(&mut prepared.warnings);
+	let (id, track) = match (&duplicate_of, settings.duplicates) {
+		(Some(id), DuplicateAction::Skip) => (id.clone(), data.library.get_track(id)?.clone()),
+		(Some(id), DuplicateAction::Replace) => {
+			let new_track = prepared.add_to_library(&data.paths, settings.mode, now)?;
+			let track =
+				replace_track_file(&mut data.library, &data.paths, id, new_track, &mut warnings)?;
+			index.update(id, &track, &data.paths);
+			(id.clone(), track)
+		}
+		_ => {
+			let track = prepared.add_to_library(&data.paths, settings.mode, now)?;
+			let id = data.library.generate_id();
+			data.library.insert_track(id.clone(), track.clone());
+			if settings.duplicates != DuplicateAction::Import {
+				index.insert(Some(id.clone()), &track, &data.paths);
+			}
+			(id, track)
+		}
+	};
+	Ok(ImportedTrack {
+		id,
+		track,
+		warnings,
+		duplicate_of,
+	})
+}
+
+/// `index` is updated with the imported track, so it can be reused for the
+/// next file in the same batch
+pub fn import(
+	data: &mut Data,
+	track_path: &Path,
+	settings: &ImportSettings,
+	index: &mut DuplicateIndex,
+	now: i64,
+) -> Result<ImportedTrack> {
+	let prepared = prepare(track_path, now)?;
+	add_prepared(data, prepared, settings, index, now)
+}
+
+/// Imports files one by one, checking each one for duplicates against the
+/// library and the files imported before it
+#[napi]
+pub struct TrackImport {
+	settings: ImportSettings,
+	index: DuplicateIndex,
+	now: i64,
+}
+#[napi]
+impl TrackImport {
+	#[napi(factory)]
+	pub fn new(
+		now: MsSinceUnixEpoch,
+		options: Option<ImportOptions>,
+		env: Env,
+	) -> napi::Result<Self> {
+		let data = get_data(&env);
+		let settings = ImportSettings::from_options(options)?;
+		let index = DuplicateIndex::new(&data.library, &data.paths, settings.duplicates);
+		Ok(Self {
+			settings,
+			index,
+			now,
+		})
+	}
+	#[napi]
+	pub fn import_file(&mut self, path: String, env: Env) -> napi::Result<ImportedTrack> {
+		let data = get_data(&env);
+		let imported = import(
+			data,
+			Path::new(&path),
+			&self.settings,
+			&mut self.index,
+			self.now,
+		)?;
+		Ok(imported)
+	}
+}
+
+#[napi(object)]
+pub struct FolderImportStatus {
+	pub errors: Vec<String>,
+	/// Problems with imported files that were worked around
+	pub warnings: Vec<String>,
+	pub tracks_count: i64,
+	/// Files that were ignored because they aren't a supported file type
+	pub skipped_count: i64,
+	/// Likely duplicates that were skipped or replaced
+	pub duplicates_count: i64,
+}
+
+/// Recursively finds files with a supported `FileType`, sorted by path
+fn find_importable_files(dir: &Path, status: &mut FolderImportStatus) -> Vec<PathBuf> {
+	let entries = match fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(e) => {
+			status
+				.errors
+				.push(format!("[{}] {e}", dir.to_string_lossy()));
+			return Vec::new();
+		}
+	};
+	let mut paths: Vec<PathBuf> = entries
+		.filter_map(|entry| match entry {
+			Ok(entry) => Some(entry.path()),
+			Err(e) => {
+				status
+					.errors
+					.push(format!("[{}] {e}", dir.to_string_lossy()));
+				None
+			}
+		})
+		.collect();
+	paths.sort_by(|a, b| compare_path(a, b));
+
+	let mut files = Vec::new();
+	for path in paths {
+		let is_hidden = path
+			.file_name()
+			.is_some_and(|name| name.to_string_lossy().starts_with('.'));
+		if is_hidden {
+			continue;
+		}
+		let is_symlink = fs::symlink_metadata(&path).is_ok_and(|md| md.is_symlink());
+		if is_symlink && path.is_dir() {
+			// Symlinked folders can form loops
+			let path = path.to_string_lossy();
+			status
+				.warnings
+				.push(format!("[{path}] Skipped symlinked folder"));
+		} else if path.is_dir() {
+			files.extend(find_importable_files(&path, status));
+		} else if FileType::from_extension(&path).is_ok() {
+			files.push(path);
+		} else {
+			status.skipped_count += 1;
+		}
+	}
+	files
+}
+
+enum FolderImportItem {
+	New(PreparedImport),
+	/// Replaces the file of an existing track
+	Replace(TrackID, PreparedImport),
+}
+
+/// Number of files that are parsed in parallel before they are checked for
+/// duplicates
+const FOLDER_IMPORT_CHUNK_SIZE: usize = 64;
+
+/// Files are parsed in parallel, then checked for duplicates one by one.
+/// Likely duplicates of files in the same folder are skipped.
+fn import_folder_files(
+	job: &FolderImportJob,
+	index: &mut DuplicateIndex,
+	items: &Mutex<Vec<FolderImportItem>>,
+) -> FolderImportStatus {
+	let mut status = FolderImportStatus {
+		errors: Vec::new(),
+		warnings: Vec::new(),
+		tracks_count: 0,
+		skipped_count: 0,
+		duplicates_count: 0,
+	};
+	let files = find_importable_files(&job.dir, &mut status);
+	job.total_count.store(files.len() as u32, Ordering::Relaxed);
+
+	for chunk in files.chunks(FOLDER_IMPORT_CHUNK_SIZE) {
+		if job.cancelled.load(Ordering::Relaxed) {
+			break;
+		}
+		let prepared: Vec<_> = chunk
+			.par_iter()
+			.map(|path| (path, prepare(path, job.now)))
+			.collect();
+		for (path, prepared) in prepared {
+			let item = match prepared {
+				Ok(prepared) => import_folder_file(job, index, prepared, &mut status),
+				Err(e) => Err(e),
+			};
+			match item {
+				Ok(Some(item)) => items.lock().unwrap().push(item),
+				Ok(None) => {}
+				Err(e) => status
+					.errors
+					.push(format!("[{}] {e:#}", path.to_string_lossy())),
+			}
+			job.done_count.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+	status
+}
+
+fn import_folder_file(
+	job: &FolderImportJob,
+	index: &mut DuplicateIndex,
+	mut prepared: PreparedImport,
+	status: &mut FolderImportStatus,
+) -> Result<Option<FolderImportItem>> {
+	let settings = &job.settings;
+	let duplicate = prepared
+		.find_duplicate(index, settings.compare_hash)?
+		.map(|candidate| candidate.id.clone());
+	let path = prepared.path.to_string_lossy().into_owned();
+	for warning in std::mem::take(&mut prepared.warnings) {
+		status.warnings.push(format!("[{path}] {warning}"));
+	}
+	match (duplicate, settings.duplicates) {
+		(Some(Some(id)), DuplicateAction::Replace) => {
+			status.duplicates_count += 1;
+			Ok(Some(FolderImportItem::Replace(id, prepared)))
+		}
+		(Some(_), _) => {
+			status.duplicates_count += 1;
+			Ok(None)
+		}
+		(None, _) => {
+			if settings.duplicates != DuplicateAction::Import {
+				index.insert_path(None, &prepared.track, prepared.path.clone());
+			}
+			status.tracks_count += 1;
+			Ok(Some(FolderImportItem::New(prepared)))
+		}
+	}
+}
+
+struct FolderImportJob {
+	dir: PathBuf,
+	settings: ImportSettings,
+	now: i64,
+	cancelled: AtomicBool,
+	done_count: AtomicU32,
+	total_count: AtomicU32,
+}
+
+/// Imports a folder recursively in the background. Files are only read while
+/// importing, and are copied and added to the library in `finish`.
+#[napi]
+pub struct FolderImport {
+	job: Arc<FolderImportJob>,
+	index: Arc<Mutex<Option<DuplicateIndex>>>,
+	items: Arc<Mutex<Vec<FolderImportItem>>>,
+}
+#[napi]
+impl FolderImport {
+	#[napi(factory)]
+	pub fn new(
+		path: String,
+		now: MsSinceUnixEpoch,
+		options: Option<ImportOptions>,
+		env: Env,
+	) -> napi::Result<Self> {
+		let data = get_data(&env);
+		let dir = PathBuf::from(path);
+		if !dir.is_dir() {
+			return Err(anyhow!("Not a folder: {}", dir.to_string_lossy()).into());
+		}
+		let settings = ImportSettings::from_options(options)?;
+		let index = DuplicateIndex::new(&data.library, &data.paths, settings.duplicates);
+		Ok(Self {
+			job: Arc::new(FolderImportJob {
+				dir,
+				settings,
+				now,
+				cancelled: AtomicBool::new(false),
+				done_count: AtomicU32::new(0),
+				total_count: AtomicU32::new(0),
+			}),
+			index: Arc::new(Mutex::new(Some(index))),
+			items: Arc::new(Mutex::new(Vec::new())),
+		})
+	}
+	#[napi]
+	pub async fn start(&self) -> napi::Result<FolderImportStatus> {
+		let job = self.job.clone();
+		let index = self.index.lock().unwrap().take();
+		let mut index = index.context("Already started")?;
+		let items = self.items.clone();
+		let status =
+			tokio::task::spawn_blocking(move || import_folder_files(&job, &mut index, &items))
+				.await
+				.context("Folder import thread failed")?;
+		Ok(status)
+	}
+	/// Files that were imported or failed. The total is 0 until the folder
+	/// has been scanned.
+	#[napi]
+	pub fn progress(&self) -> AnalysisProgress {
+		AnalysisProgress {
+			done_count: self.job.done_count.load(Ordering::Relaxed).into(),
+			total_count: self.job.total_count.load(Ordering::Relaxed).into(),
+		}
+	}
+	/// Stops after the files that are being parsed. Files that were already
+	/// read can still be added with `finish`.
+	#[napi]
+	pub fn cancel(&self) {
+		self.job.cancelled.store(true, Ordering::Relaxed);
+	}
+	/// Copies the files and adds the tracks to the library. Returns errors for
+	/// files that could not be copied, and for replaced files that could not
+	/// be moved to the trash.
+	#[napi]
+	pub fn finish(&self, env: Env) -> napi::Result<Vec<String>> {
+		let data = get_data(&env);
+		let items = std::mem::take(&mut *self.items.lock().unwrap());
+		let (mode, now) = (self.job.settings.mode, self.job.now);
+		let mut errors = Vec::new();
+		for item in items {
+			let (replace_id, prepared) = match item {
+				FolderImportItem::New(prepared) => (None, prepared),
+				FolderImportItem::Replace(id, prepared) => (Some(id), prepared),
+			};
+			let path = prepared.path.to_string_lossy().into_owned();
+			let track = match prepared.add_to_library(&data.paths, mode, now) {
+				Ok(track) => track,
+				Err(e) => {
+					errors.push(format!("[{path}] {e:#}"));
+					continue;
+				}
+			};
+			match replace_id {
+				Some(id) if data.library.get_track(&id).is_ok() => {
+					replace_track_file(&mut data.library, &data.paths, &id, track, &mut errors)?;
+				}
+				// Tracks that were deleted during the import are added again
+				_ => {
+					let id = data.library.generate_id();
+					data.library.insert_track(id, track);
+				}
+			}
+		}
+		Ok(errors)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn chunk(id: &[u8; 4], data: &[u8], big_endian: bool) -> Vec<u8> {
+		let size = data.len() as u32;
+		let mut bytes = id.to_vec();
+		bytes.extend(match big_endian {
+			true => size.to_be_bytes(),
+			false => size.to_le_bytes(),
+		});
+		bytes.extend(data);
+		if data.len() % 2 == 1 {
+			bytes.push(0);
+		}
+		bytes
+	}
+
+	/// One second of mono 16-bit silence at 8000 Hz, with an optional RIFF
+	/// INFO title
+	fn wav_bytes(info_title: Option<&str>) -> Vec<u8> {
+		let mut fmt = Vec::new();
+		fmt.extend(1u16.to_le_bytes());
+		fmt.extend(1u16.to_le_bytes());
+		fmt.extend(8000u32.to_le_bytes());
+		fmt.extend(16000u32.to_le_bytes());
+		fmt.extend(2u16.to_le_bytes());
+		fmt.extend(16u16.to_le_bytes());
+		let mut body = b"WAVE".to_vec();
+		body.extend(chunk(b"fmt ", &fmt, false));
+		if let Some(title) = info_title {
+			let mut info = b"INFO".to_vec();
+			info.extend(chunk(b"INAM", format!("{title}\0").as_bytes(), false));
+			body.extend(chunk(b"LIST", &info, false));
+		}
+		body.extend(chunk(b"data", &[0; 16000], false));
+		chunk(b"RIFF", &body, false)
+	}
+
+	/// One second of mono 16-bit silence at 8000 Hz
+	fn aiff_bytes() -> Vec<u8> {
+		let mut comm = Vec::new();
+		comm.extend(1u16.to_be_bytes());
+		comm.extend(8000u32.to_be_bytes());
+		comm.extend(16u16.to_be_bytes());
+		// 8000 as an 80-bit extended float
+		comm.extend([0x40, 0x0B, 0xFA, 0, 0, 0, 0, 0, 0, 0]);
+		let mut ssnd = vec![0; 8];
+		ssnd.extend([0; 16000]);
+		let mut body = b"AIFF".to_vec();
+		body.extend(chunk(b"COMM", &comm, true));
+		body.extend(chunk(b"SSND", &ssnd, true));
+		chunk(b"FORM", &body, true)
+	}
+
+	fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("ferrum_import_test_{name}"));
+		fs::write(&path, bytes).unwrap();
+		path
+	}
+
+	fn has_id3_chunk(path: &Path) -> bool {
+		let bytes = fs::read(path).unwrap();
+		bytes
+			.windows(4)
+			.any(|window| window == b"ID3 " || window == b"id3 ")
+	}
+
+	fn ogg_page(packet: &[u8]) -> Vec<u8> {
+		let mut page = b"OggS".to_vec();
+		page.extend([0; 22]);
+		page.push(1);
+		page.push(packet.len() as u8);
+		page.extend(packet);
+		page
+	}
+
+	#[test]
+	fn file_type_test() {
+		let from_extension = |path: &str| FileType::from_extension(Path::new(path)).ok();
+		assert_eq!(from_extension("a.MP3"), Some(FileType::Mp3));
+		assert_eq!(from_extension("a.wave"), Some(FileType::Wav));
+		assert_eq!(from_extension("a.AIF"), Some(FileType::Aiff));
+		assert_eq!(from_extension("a.ogg"), Some(FileType::Ogg));
+		assert!(from_extension("a.txt").is_none());
+		assert!(from_extension("ogg").is_none());
+
+		let opus = ogg_page(b"OpusHead\x01\x02\x38\x01\x80\xbb\0\0\0\0\0");
+		let vorbis = ogg_page(b"\x01vorbis\0\0\0\0\x02\x44\xac\0\0");
+		assert_eq!(FileType::from_ogg_header(&opus).ok(), Some(FileType::Opus));
+		assert_eq!(FileType::from_ogg_header(&vorbis).ok(), Some(FileType::Ogg));
+		assert!(FileType::from_ogg_header(&ogg_page(b"\x7fFLAC")).is_err());
+		assert!(FileType::from_ogg_header(b"RIFF").is_err());
+
+		let path = temp_file("opus.ogg", &opus);
+		assert_eq!(FileType::from_path(&path).ok(), Some(FileType::Opus));
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn wav_tags_test() {
+		let path = temp_file("info.wav", &wav_bytes(Some("Info Title")));
+		let prepared = prepare(&path, 0).unwrap();
+		assert_eq!(prepared.file_type, FileType::Wav);
+		assert_eq!(prepared.track.name, "Info Title");
+		assert_eq!(prepared.track.duration, 1.0);
+		assert_eq!(prepared.track.sampleRate, 8000.0);
+
+		// RIFF INFO is only read. Tags are written to an ID3 chunk.
+		let mut tag = Tag::read_from_path(&path).unwrap();
+		tag.set_title("New Title");
+		tag.write_to_path(&path).unwrap();
+		assert!(has_id3_chunk(&path));
+		assert_eq!(prepare(&path, 0).unwrap().track.name, "New Title");
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn aiff_tags_test() {
+		let path = temp_file("untagged.aiff", &aiff_bytes());
+		let prepared = prepare(&path, 0).unwrap();
+		assert_eq!(prepared.file_type, FileType::Aiff);
+		assert_eq!(prepared.track.name, "ferrum_import_test_untagged");
+		assert!(prepared.changed_tag.is_some());
+		assert_eq!(prepared.track.duration, 1.0);
+
+		let mut tag = Tag::read_from_path(&path).unwrap();
+		tag.set_title("AIFF Title");
+		tag.write_to_path(&path).unwrap();
+		assert!(has_id3_chunk(&path));
+		assert_eq!(prepare(&path, 0).unwrap().track.name, "AIFF Title");
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn duplicate_index_test() {
+		let bytes = wav_bytes(Some("Batch Title"));
+		let first = prepare(&temp_file("batch1.wav", &bytes), 0).unwrap();
+		let copy = prepare(&temp_file("batch2.wav", &bytes), 0).unwrap();
+		let mut different_bytes = bytes.clone();
+		*different_bytes.last_mut().unwrap() = 1;
+		let different = prepare(&temp_file("batch3.wav", &different_bytes), 0).unwrap();
+
+		let mut index = DuplicateIndex {
+			tracks: HashMap::new(),
+		};
+		assert!(first.find_duplicate(&index, true).unwrap().is_none());
+		// Files imported earlier in the same batch are candidates too
+		index.insert_path(None, &first.track, first.path.clone());
+		assert!(copy.find_duplicate(&index, false).unwrap().is_some());
+		assert!(copy.find_duplicate(&index, true).unwrap().is_some());
+		assert!(different.find_duplicate(&index, false).unwrap().is_some());
+		assert!(different.find_duplicate(&index, true).unwrap().is_none());
+
+		for prepared in [first, copy, different] {
+			fs::remove_file(&prepared.path).unwrap();
+		}
+	}
+
+	#[test]
+	fn owns_track_file_test() {
+		let path = temp_file("owned.wav", &wav_bytes(None));
+		let track = |file: &str| -> Track {
+			serde_json::from_value(serde_json::json!({
+				"size": 0,
+				"duration": 1.0,
+				"bitrate": 0.0,
+				"sampleRate": 0.0,
+				"file": file,
+				"dateModified": 0,
+				"dateAdded": 0,
+				"name": "Owned",
+				"artist": "",
+			}))
+			.unwrap()
+		};
+		assert!(owns_track_file(&track("owned.wav"), &path));
+		let referenced = track(&path.to_string_lossy());
+		assert!(!owns_track_file(&referenced, &path));
+
+		#[cfg(unix)]
+		{
+			let link_path = path.with_extension("link.wav");
+			let _ = fs::remove_file(&link_path);
+			fs::hard_link(&path, &link_path).unwrap();
+			assert!(!owns_track_file(&track("owned.wav"), &path));
+			fs::remove_file(&link_path).unwrap();
+		}
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn replace_track_file_test() {
+		let dir = std::env::temp_dir().join("ferrum_replace_test");
+		let dir = dir.to_string_lossy().into_owned();
+		let paths = Paths {
+			path_separator: std::path::MAIN_SEPARATOR.to_string(),
+			library_dir: dir.clone(),
+			tracks_dir: dir.clone(),
+			library_json: dir.clone(),
+			cache_dir: dir.clone(),
+			cache_db: dir.clone(),
+			local_data_dir: dir.clone(),
+			view_options_file: dir.clone(),
+			queue_file: dir.clone(),
+			scrobble_queue_file: dir.clone(),
+			logs_dir: dir,
+		};
+		let track = |file: &str, album: &str| -> Track {
+			serde_json::from_value(serde_json::json!({
+				"size": 0,
+				"duration": 200.0,
+				"bitrate": 0.0,
+				"sampleRate": 0.0,
+				"file": file,
+				"dateModified": 0,
+				"dateAdded": 0,
+				"name": file,
+				"artist": "Artist",
+				"albumName": album,
+				"albumArtist": "Artist",
+				"loudness": -10.0,
+				"peak": 0.9,
+				"albumLoudness": -11.0,
+				"albumPeak": 0.95,
+			}))
+			.unwrap()
+		};
+		let mut library = Library::new();
+		let ids = ["replace_a", "replace_b", "replace_c"].map(String::from);
+		library.insert_track(ids[0].clone(), track("a.mp3", "Album"));
+		library.insert_track(ids[1].clone(), track("b.mp3", "Album"));
+		library.insert_track(ids[2].clone(), track("c.mp3", "Other Album"));
+
+		let mut warnings = Vec::new();
+		let new_track = track("new.mp3", "Album");
+		let replaced =
+			replace_track_file(&mut library, &paths, &ids[0], new_track, &mut warnings).unwrap();
+		assert_eq!(replaced.file, "new.mp3");
+		assert_eq!((replaced.loudness, replaced.albumLoudness), (None, None));
+		let same_album = library.get_track(&ids[1]).unwrap();
+		assert_eq!(same_album.loudness, Some(-10.0));
+		assert_eq!(
+			(same_album.albumLoudness, same_album.albumPeak),
+			(None, None)
+		);
+		let other_album = library.get_track(&ids[2]).unwrap();
+		assert_eq!(other_album.albumLoudness, Some(-11.0));
+		assert!(warnings.is_empty());
+	}
+}
@@ -0,0 +1,580 @@
+use super::decode::AudioDecoder;
+use super::import::owns_track_file;
+use super::{AnalysisProgress, AnalysisStatus, Tag};
+use crate::data_js::get_data;
+use crate::get_now_timestamp;
+use crate::library_types::{ItemId, TRACK_ID_MAP, Track, TrackID};
+use anyhow::{Context, Result, bail};
+use napi::Env;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Blocks quieter than this are ignored, as specified by EBU R128
+const ABSOLUTE_GATE: f64 = -70.0;
+/// ReplayGain 2.0 reference level
+const REPLAY_GAIN_REFERENCE: f64 = -18.0;
+const TRUE_PEAK_TAPS: usize = 12;
+
+fn energy_to_loudness(energy: f64) -> f64 {
+	-0.691 + 10.0 * energy.log10()
+}
+fn loudness_to_energy(loudness: f64) -> f64 {
+	10_f64.powf((loudness + 0.691) / 10.0)
+}
+
+/// Integrated loudness in LUFS of the mean square energies of 400ms blocks,
+/// using the absolute and relative gates from ITU-R BS.1770-4
+pub fn gated_loudness(blocks: &[f64]) -> f64 {
+	let absolute_threshold = loudness_to_energy(ABSOLUTE_GATE);
+	let audible: Vec<f64> = blocks
+		.iter()
+		.copied()
+		.filter(|&energy| energy > absolute_threshold)
+		.collect();
+	if audible.is_empty() {
+		return ABSOLUTE_GATE;
+	}
+	// 10 LU below the loudness of the audible blocks
+	let relative_threshold = audible.iter().sum::<f64>() / audible.len() as f64 * 0.1;
+	let gated: Vec<f64> = audible
+		.into_iter()
+		.filter(|&energy| energy > relative_threshold)
+		.collect();
+	energy_to_loudness(gated.iter().sum::<f64>() / gated.len() as f64)
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+	b: [f64; 3],
+	a: [f64; 3],
+}
+impl Biquad {
+	fn process(&self, state: &mut [f64; 2], x: f64) -> f64 {
+		let y = self.b[0] * x + state[0];
+		state[0] = self.b[1] * x - self.a[1] * y + state[1];
+		state[1] = self.b[2] * x - self.a[2] * y;
+		y
+	}
+}
+
+/// The two stages of the K-weighting filter, calculated for any sample rate
+/// like libebur128 does
+fn k_weighting_filters(sample_rate: f64) -> (Biquad, Biquad) {
+	let f0 = 1681.974450955533;
+	let g = 3.999843853973347;
+	let q = 0.7071752369554196;
+	let k = (PI * f0 / sample_rate).tan();
+	let vh = 10_f64.powf(g / 20.0);
+	let vb = vh.powf(0.4996667741545416);
+	let a0 = 1.0 + k / q + k * k;
+	let shelf = Biquad {
+		b: [
+			(vh + vb * k / q + k * k) / a0,
+			2.0 * (k * k - vh) / a0,
+			(vh - vb * k / q + k * k) / a0,
+		],
+		a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+	};
+
+	let f0 = 38.13547087602444;
+	let q = 0.5003270373238773;
+	let k = (PI * f0 / sample_rate).tan();
+	let a0 = 1.0 + k / q + k * k;
+	let high_pass = Biquad {
+		b: [1.0, -2.0, 1.0],
+		a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+	};
+	(shelf, high_pass)
+}
+
+/// Finds the peak of the signal after oversampling it, which catches peaks
+/// between samples
+struct TruePeakMeter {
+	/// Windowed sinc filter, split into one set of taps per output phase
+	phases: Vec<[f64; TRUE_PEAK_TAPS]>,
+	/// The most recent input samples of each channel, newest first
+	history: Vec<[f64; TRUE_PEAK_TAPS]>,
+	peak: f64,
+}
+impl TruePeakMeter {
+	fn new(sample_rate: u32, channels: usize) -> Self {
+		let factor = match sample_rate {
+			..96000 => 4,
+			96000..192000 => 2,
+			_ => 1,
+		};
+		let len = TRUE_PEAK_TAPS * factor;
+		let center = (len - 1) as f64 / 2.0;
+		let coefficient = |i: usize| {
+			let t = (i as f64 - center) / factor as f64;
+			let sinc = if t == 0.0 {
+				1.0
+			} else {
+				(PI * t).sin() / (PI * t)
+			};
+			let window = 0.5 - 0.5 * (2.0 * PI * (i as f64 + 0.5) / len as f64).cos();
+			sinc * window
+		};
+		let phases = (0..factor)
+			.map(|phase| std::array::from_fn(|tap| coefficient(phase + tap * factor)))
+			.collect();
+		Self {
+			phases,
+			history: vec![[0.0; TRUE_PEAK_TAPS]; channels],
+			peak: 0.0,
+		}
+	}
+	fn process(&mut self, channel: usize, x: f64) {
+		let history = &mut self.history[channel];
+		history.copy_within(0..TRUE_PEAK_TAPS - 1, 1);
+		history[0] = x;
+		self.peak = self.peak.max(x.abs());
+		for phase in &self.phases {
+			let y: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+			self.peak = self.peak.max(y.abs());
+		}
+	}
+}
+
+struct LoudnessMeter {
+	channels: usize,
+	/// BS.1770 channel weights, which boost surround channels and ignore LFE
+	weights: Vec<f64>,
+	shelf: Biquad,
+	high_pass: Biquad,
+	filter_states: Vec<[[f64; 2]; 2]>,
+	/// Frames in 100ms. Blocks are 400ms long and overlap by 75%, so they
+	/// are made of 4 consecutive sub-blocks.
+	sub_block_len: usize,
+	sub_block_frames: usize,
+	sub_block_energy: f64,
+	sub_blocks: VecDeque<f64>,
+	/// Mean square energy of each block
+	blocks: Vec<f64>,
+	true_peak: TruePeakMeter,
+}
+impl LoudnessMeter {
+	fn new(sample_rate: u32, channels: usize) -> Self {
+		let (shelf, high_pass) = k_weighting_filters(sample_rate.into());
+		let weights = (0..channels)
+			.map(|channel| match (channels, channel) {
+				// L, R, C, LFE, Ls, Rs
+				(6, 3) => 0.0,
+				(6, 4 | 5) => 1.41,
+				_ => 1.0,
+			})
+			.collect();
+		Self {
+			channels,
+			weights,
+			shelf,
+			high_pass,
+			filter_states: vec![[[0.0; 2]; 2]; channels],
+			sub_block_len: (sample_rate as usize / 10).max(1),
+			sub_block_frames: 0,
+			sub_block_energy: 0.0,
+			sub_blocks: VecDeque::with_capacity(4),
+			blocks: Vec::new(),
+			true_peak: TruePeakMeter::new(sample_rate, channels),
+		}
+	}
+	fn process(&mut self, samples: &[f32]) {
+		for frame in samples.chunks_exact(self.channels) {
+			for (channel, &sample) in frame.iter().enumerate() {
+				let x = f64::from(sample);
+				self.true_peak.process(channel, x);
+				let states = &mut self.filter_states[channel];
+				let y = self.shelf.process(&mut states[0], x);
+				let y = self.high_pass.process(&mut states[1], y);
+				self.sub_block_energy += self.weights[channel] * y * y;
+			}
+			self.sub_block_frames += 1;
+			if self.sub_block_frames == self.sub_block_len {
+				if self.sub_blocks.len() == 4 {
+					self.sub_blocks.pop_front();
+				}
+				self.sub_blocks.push_back(self.sub_block_energy);
+				if self.sub_blocks.len() == 4 {
+					let sum: f64 = self.sub_blocks.iter().sum();
+					self.blocks.push(sum / (4 * self.sub_block_len) as f64);
+				}
+				self.sub_block_frames = 0;
+				self.sub_block_energy = 0.0;
+			}
+		}
+	}
+}
+
+pub struct TrackLoudness {
+	/// Integrated loudness in LUFS
+	pub loudness: f64,
+	/// True peak, where 1.0 is full scale
+	pub peak: f64,
+	/// Kept for calculating album loudness
+	blocks: Vec<f64>,
+}
+
+pub fn analyze_file(path: &Path, cancelled: &AtomicBool) -> Result<TrackLoudness> {
+	let mut decoder = AudioDecoder::open(path)?;
+	let mut meter: Option<LoudnessMeter> = None;
+	while let Some(samples) = decoder.next_samples()? {
+		if cancelled.load(Ordering::Relaxed) {
+			bail!("Cancelled");
+		}
+		let meter =
+			meter.get_or_insert_with(|| LoudnessMeter::new(samples.sample_rate, samples.channels));
+		if meter.channels != samples.channels {
+			bail!("Channel count changed while decoding");
+		}
+		meter.process(samples.data);
+	}
+	let meter = meter.context("No audio found")?;
+	Ok(TrackLoudness {
+		loudness: gated_loudness(&meter.blocks),
+		peak: meter.true_peak.peak,
+		blocks: meter.blocks,
+	})
+}
+
+/// Album loudness is measured over the gating blocks of all its tracks, like
+/// if they were one long track
+fn album_loudness(tracks: &[&TrackLoudness]) -> (f64, f64) {
+	let blocks: Vec<f64> = tracks
+		.iter()
+		.flat_map(|track| track.blocks.iter().copied())
+		.collect();
+	let peak = tracks.iter().map(|track| track.peak).fold(0.0, f64::max);
+	(gated_loudness(&blocks), peak)
+}
+
+pub fn replay_gain(loudness: f64) -> f64 {
+	REPLAY_GAIN_REFERENCE - loudness
+}
+
+/// Album name and album artist
+fn album_key(track: &Track) -> Option<(String, String)> {
+	match (&track.albumName, &track.albumArtist) {
+		(Some(name), Some(artist)) => Some((name.clone(), artist.clone())),
+		_ => None,
+	}
+}
+
+#[derive(Clone)]
+struct AnalysisItem {
+	track_id: TrackID,
+	path: PathBuf,
+	/// Album name and album artist
+	album: Option<(String, String)>,
+	/// Tags are only written to files that belong to the library
+	owns_file: bool,
+}
+
+struct AnalysisResult {
+	loudness: f64,
+	peak: f64,
+	album: Option<(f64, f64)>,
+}
+
+/// Measures loudness and true peak of tracks in the background. Album loudness
+/// is measured over all tracks of the album in the library, so the other
+/// tracks of the selected albums are analyzed too. If any track of an album
+/// fails, the album values are left unchanged.
+#[napi]
+pub struct LoudnessAnalysis {
+	items: Vec<AnalysisItem>,
+	/// Write ReplayGain tags, and iTunes Sound Check to MP4 files
+	write_tags: bool,
+	cancelled: Arc<AtomicBool>,
+	done_count: Arc<AtomicU32>,
+	results: Arc<Mutex<HashMap<TrackID, AnalysisResult>>>,
+}
+#[napi]
+impl LoudnessAnalysis {
+	#[napi(factory)]
+	pub fn new(item_ids: Vec<ItemId>, write_tags: Option<bool>, env: Env) -> napi::Result<Self> {
+		let data = get_data(&env);
+		let id_map = TRACK_ID_MAP.read().unwrap();
+		let new_item = |track_id: &TrackID, track: &Track| {
+			let path = data.paths.get_track_file_path(&track.file);
+			AnalysisItem {
+				track_id: track_id.clone(),
+				owns_file: owns_track_file(track, &path),
+				path,
+				album: album_key(track),
+			}
+		};
+		let mut items = Vec::new();
+		let mut track_ids = HashSet::new();
+		for item_id in item_ids {
+			let track_id = id_map.get(item_id as usize).context("Item ID not found")?;
+			let track = data.library.get_track(track_id)?;
+			if track_ids.insert(track_id.clone()) {
+				items.push(new_item(track_id, track));
+			}
+		}
+		let albums: HashSet<_> = items.iter().filter_map(|item| item.album.clone()).collect();
+		for (track_id, track) in data.library.get_tracks() {
+			let in_album = album_key(track).is_some_and(|album| albums.contains(&album));
+			if in_album && !track_ids.contains(track_id) {
+				items.push(new_item(track_id, track));
+			}
+		}
+		Ok(Self {
+			items,
+			write_tags: write_tags.unwrap_or(false),
+			cancelled: Arc::new(AtomicBool::new(false)),
+			done_count: Arc::new(AtomicU32::new(0)),
+			results: Arc::new(Mutex::new(HashMap::new())),
+		})
+	}
+	#[napi]
+	pub async fn start(&self) -> napi::Result<AnalysisStatus> {
+		let items = self.items.clone();
+		let write_tags = self.write_tags;
+		let cancelled = self.cancelled.clone();
+		let done_count = self.done_count.clone();
+		let results = self.results.clone();
+		let status = tokio::task::spawn_blocking(move || {
+			run_analysis(&items, write_tags, &cancelled, &done_count, &results)
+		})
+		.await
+		.context("Loudness analysis thread failed")?;
+		Ok(status)
+	}
+	#[napi]
+	pub fn progress(&self) -> AnalysisProgress {
+		AnalysisProgress {
+			done_count: self.done_count.load(Ordering::Relaxed).into(),
+			total_count: self.items.len() as i64,
+		}
+	}
+	/// Stops after the tracks that are being analyzed. Tracks that were
+	/// already analyzed are kept.
+	#[napi]
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+	/// Saves the results to the tracks
+	#[napi]
+	pub fn finish(&self, env: Env) -> napi::Result<()> {
+		let data = get_data(&env);
+		let results = std::mem::take(&mut *self.results.lock().unwrap());
+		let now = get_now_timestamp();
+		for (track_id, result) in results {
+			let track = match data.library.get_track_mut(&track_id) {
+				Ok(track) => track,
+				Err(_) => continue,
+			};
+			track.loudness = Some(result.loudness);
+			track.peak = Some(result.peak);
+			if let Some((album_loudness, album_peak)) = result.album {
+				track.albumLoudness = Some(album_loudness);
+				track.albumPeak = Some(album_peak);
+			}
+			track.dateModified = now;
+		}
+		Ok(())
+	}
+}
+
+fn run_analysis(
+	items: &[AnalysisItem],
+	write_tags: bool,
+	cancelled: &AtomicBool,
+	done_count: &AtomicU32,
+	results: &Mutex<HashMap<TrackID, AnalysisResult>>,
+) -> AnalysisStatus {
+	let analyzed: Vec<Option<Result<TrackLoudness>>> = items
+		.par_iter()
+		.map(|item| {
+			if cancelled.load(Ordering::Relaxed) {
+				return None;
+			}
+			let result = analyze_file(&item.path, cancelled);
+			done_count.fetch_add(1, Ordering::Relaxed);
+			Some(result)
+		})
+		.collect();
+	let is_cancelled = cancelled.load(Ordering::Relaxed);
+
+	let mut errors = Vec::new();
+	let mut untagged_files = Vec::new();
+	let mut albums: HashMap<&(String, String), Vec<Option<&TrackLoudness>>> = HashMap::new();
+	for (item, result) in items.iter().zip(&analyzed) {
+		let track_loudness = match result {
+			Some(Ok(track_loudness)) => Some(track_loudness),
+			Some(Err(e)) if !is_cancelled => {
+				errors.push(format!("{}: {e:#}", item.path.to_string_lossy()));
+				None
+			}
+			_ => None,
+		};
+		if let Some(album) = &item.album {
+			albums.entry(album).or_default().push(track_loudness);
+		}
+	}
+	let album_results: HashMap<&(String, String), (f64, f64)> = albums
+		.into_iter()
+		.filter_map(|(album, tracks)| {
+			let tracks: Option<Vec<&TrackLoudness>> = tracks.into_iter().collect();
+			Some((album, album_loudness(&tracks?)))
+		})
+		.collect();
+
+	let mut results = results.lock().unwrap();
+	for (item, result) in items.iter().zip(analyzed) {
+		let Some(Ok(track_loudness)) = result else {
+			continue;
+		};
+		let album = item
+			.album
+			.as_ref()
+			.and_then(|album| album_results.get(album).copied());
+		if write_tags && !item.owns_file {
+			untagged_files.push(item.path.to_string_lossy().into_owned());
+		} else if write_tags && let Err(e) = write_loudness_tags(&item.path, &track_loudness, album)
+		{
+			errors.push(format!("{}: {e:#}", item.path.to_string_lossy()));
+		}
+		results.insert(
+			item.track_id.clone(),
+			AnalysisResult {
+				loudness: track_loudness.loudness,
+				peak: track_loudness.peak,
+				album,
+			},
+		);
+	}
+	AnalysisStatus {
+		analyzed_count: results.len() as i64,
+		errors,
+		untagged_files,
+		cancelled: is_cancelled,
+	}
+}
+
+fn write_loudness_tags(
+	path: &Path,
+	track_loudness: &TrackLoudness,
+	album: Option<(f64, f64)>,
+) -> Result<()> {
+	let mut tag = Tag::read_from_path(&path.to_path_buf())?;
+	let track_gain = replay_gain(track_loudness.loudness);
+	let album = album.map(|(loudness, peak)| (replay_gain(loudness), peak));
+	if !tag.set_replay_gain(track_gain, track_loudness.peak, album) {
+		bail!("The tag format doesn't support ReplayGain");
+	}
+	tag.set_sound_check(track_gain, track_loudness.peak);
+	tag.write_to_path(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Blocks of a signal made of segments of constant loudness, each given
+	/// as seconds and LUFS
+	fn segment_blocks(segments: &[(usize, f64)]) -> Vec<f64> {
+		// 10 blocks per second, since blocks overlap by 75%
+		segments
+			.iter()
+			.flat_map(|&(seconds, loudness)| {
+				std::iter::repeat_n(loudness_to_energy(loudness), seconds * 10)
+			})
+			.collect()
+	}
+
+	fn sine_loudness(sample_rate: u32, frequency: f64, dbfs: f64, seconds: f64) -> TrackLoudness {
+		let amplitude = 10_f64.powf(dbfs / 20.0);
+		let frames = (f64::from(sample_rate) * seconds) as usize;
+		let samples: Vec<f32> = (0..frames)
+			.flat_map(|i| {
+				let t = i as f64 / f64::from(sample_rate);
+				let x = (amplitude * (2.0 * PI * frequency * t).sin()) as f32;
+				[x, x]
+			})
+			.collect();
+		let mut meter = LoudnessMeter::new(sample_rate, 2);
+		meter.process(&samples);
+		TrackLoudness {
+			loudness: gated_loudness(&meter.blocks),
+			peak: meter.true_peak.peak,
+			blocks: meter.blocks,
+		}
+	}
+
+	#[test]
+	fn gated_loudness_test() {
+		assert_eq!(gated_loudness(&[]), ABSOLUTE_GATE);
+		assert_eq!(
+			gated_loudness(&segment_blocks(&[(10, -80.0)])),
+			ABSOLUTE_GATE
+		);
+
+		// Gating cases of EBU Tech 3341, which should all measure -23 LUFS
+		let cases: [&[(usize, f64)]; 4] = [
+			&[(20, -23.0)],
+			&[(10, -36.0), (60, -23.0), (10, -36.0)],
+			&[
+				(10, -72.0),
+				(10, -36.0),
+				(60, -23.0),
+				(10, -36.0),
+				(10, -72.0),
+			],
+			&[(20, -26.0), (20, -20.0), (20, -26.0)],
+		];
+		for segments in cases {
+			let loudness = gated_loudness(&segment_blocks(segments));
+			assert!((loudness - -23.0).abs() < 0.1, "{segments:?}: {loudness}");
+		}
+	}
+
+	#[test]
+	fn loudness_meter_test() {
+		// A stereo 1 kHz sine at -23 dBFS measures -23 LUFS, at any sample rate
+		for sample_rate in [44100, 48000, 96000] {
+			let track = sine_loudness(sample_rate, 1000.0, -23.0, 3.0);
+			assert!(
+				(track.loudness - -23.0).abs() < 0.1,
+				"{sample_rate}: {}",
+				track.loudness
+			);
+			let peak_db = 20.0 * track.peak.log10();
+			assert!((peak_db - -23.0).abs() < 0.1, "{sample_rate}: {peak_db}");
+		}
+	}
+
+	#[test]
+	fn true_peak_test() {
+		// A sine at a quarter of the sample rate, sampled 45° off its peaks,
+		// has samples at -3 dBFS but a true peak of 0 dBTP
+		let samples: Vec<f32> = (0..48000)
+			.flat_map(|i| {
+				let x = (PI / 2.0 * i as f64 + PI / 4.0).sin() as f32;
+				[x, x]
+			})
+			.collect();
+		let mut meter = LoudnessMeter::new(48000, 2);
+		meter.process(&samples);
+		let peak_db = 20.0 * meter.true_peak.peak.log10();
+		assert!((-0.4..=0.2).contains(&peak_db), "{peak_db}");
+	}
+
+	#[test]
+	fn album_loudness_test() {
+		let quiet = sine_loudness(48000, 1000.0, -26.0, 2.0);
+		let loud = sine_loudness(48000, 1000.0, -20.0, 2.0);
+		let (loudness, peak) = album_loudness(&[&quiet, &loud]);
+		// Both halves pass the relative gate, so the album has their mean energy
+		let expected = energy_to_loudness(
+			(loudness_to_energy(quiet.loudness) + loudness_to_energy(loud.loudness)) / 2.0,
+		);
+		assert!((loudness - expected).abs() < 0.1, "{loudness} {expected}");
+		assert_eq!(peak, loud.peak);
+	}
+}
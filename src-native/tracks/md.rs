@@ -24,11 +24,22 @@ pub struct TrackMD {
 	pub discNum: String,
 	pub discCount: String,
 	pub bpm: String,
+	pub key: String,
 	// compilation: String,
 	// rating: String,
 	// liked: String,
 	// playCount: String,
 	pub comments: String,
+	/// Seconds. Not saved to the file tags
+	pub startTime: String,
+	/// Seconds. Not saved to the file tags
+	pub stopTime: String,
+	/// 0 to 100. Not saved to the file tags
+	pub albumRating: String,
+	/// Not saved to the file tags
+	pub albumLiked: bool,
+	/// Not saved to the file tags
+	pub equalizer: String,
 }
 
 pub fn update_track_info(
@@ -125,15 +136,26 @@ pub fn update_track_info(
 	};
 	tag.set_disc_info(new_disc_number, new_disc_count);
 
-	let new_bpm: Option<u16> = match new_info.bpm.as_ref() {
+	// bpm. Decimals are kept in the library, but not in the file tags
+	let new_bpm: Option<f64> = match new_info.bpm.as_ref() {
 		"" => None,
-		value => Some(value.parse().context("Invalid bpm")?),
+		value => match value.parse() {
+			Ok(bpm @ 1.0..=65535.0) => Some(bpm),
+			_ => bail!("Invalid bpm"),
+		},
 	};
 	match new_bpm {
 		None => tag.remove_bpm(),
-		Some(value) => tag.set_bpm(value),
+		Some(value) => tag.set_bpm(value.round() as u16),
 	};
 
+	// key
+	match new_info.key.as_ref() {
+		"" => tag.remove_initial_key(),
+		value => tag.set_initial_key(value),
+	};
+	let new_key = str_to_option(new_info.key);
+
 	// comment
 	match new_info.comments.as_ref() {
 		"" => tag.remove_comments(),
@@ -141,11 +163,39 @@ pub fn update_track_info(
 	};
 	let new_comments = str_to_option(new_info.comments);
 
+	// start_time, stop_time
+	let new_start_time: Option<f64> = match new_info.startTime.as_ref() {
+		"" => None,
+		value => Some(value.parse().context("Invalid start time")?),
+	};
+	let new_stop_time: Option<f64> = match new_info.stopTime.as_ref() {
+		"" => None,
+		value => Some(value.parse().context("Invalid stop time")?),
+	};
+	if new_start_time.is_some_and(|t| t < 0.0) || new_stop_time.is_some_and(|t| t < 0.0) {
+		bail!("Start and stop time cannot be negative");
+	}
+	if let (Some(start), Some(stop)) = (new_start_time, new_stop_time)
+		&& start >= stop
+	{
+		bail!("Start time must be before stop time");
+	}
+
+	// album_rating
+	let new_album_rating: Option<u8> = match new_info.albumRating.as_ref() {
+		"" => None,
+		value => match value.parse() {
+			Ok(rating @ 0..=100) => Some(rating),
+			_ => bail!("Invalid album rating"),
+		},
+	};
+
 	// save tag
 	tag.write_to_path(&old_path).context("Failed to save tag")?;
 
 	// move file
-	if new_name != track.name || new_artist != track.artist {
+	let name_changed = new_name != track.name || new_artist != track.artist;
+	if name_changed && !track.is_referenced() {
 		let new_filename = generate_filename(&paths, &new_artist, &new_name, &ext);
 		let new_path = paths.get_track_file_path(&new_filename);
 		match fs::rename(old_path, new_path) {
@@ -168,8 +218,17 @@ pub fn update_track_info(
 	track.trackCount = new_track_count;
 	track.discNum = new_disc_number;
 	track.discCount = new_disc_count;
-	track.bpm = new_bpm.map(|n| n.into());
+	track.bpm = new_bpm;
+	track.key = new_key;
 	track.comments = new_comments;
+	track.startTime = new_start_time;
+	track.stopTime = new_stop_time;
+	track.albumRating = new_album_rating;
+	track.albumLiked = match new_info.albumLiked {
+		true => Some(true),
+		false => None,
+	};
+	track.equalizer = str_to_option(new_info.equalizer);
 	track.dateModified = get_now_timestamp();
 
 	Ok(())
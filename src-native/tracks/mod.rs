@@ -9,10 +9,15 @@ use napi::bindgen_prelude::{ArrayBuffer, Buffer};
 use std::fs;
 use std::path::Path;
 
+mod bpm_key;
 pub mod cover;
+mod decode;
+mod fingerprint;
 pub mod import;
+mod loudness;
 mod md;
 mod tag;
+mod waveform;
 
 pub use tag::Tag;
 
@@ -36,6 +41,24 @@ pub struct KeyedTrack {
 	pub track: Track,
 }
 
+/// Result of a background analysis of tracks, like `LoudnessAnalysis`
+#[napi(object)]
+pub struct AnalysisStatus {
+	pub analyzed_count: i64,
+	pub errors: Vec<String>,
+	/// Files that tags were not written to, because they are referenced or
+	/// hardlinked
+	pub untagged_files: Vec<String>,
+	pub cancelled: bool,
+}
+
+#[napi(object)]
+pub struct AnalysisProgress {
+	/// Tracks that were analyzed or failed
+	pub done_count: i64,
+	pub total_count: i64,
+}
+
 #[napi(js_name = "get_track_by_item_id")]
 #[allow(dead_code)]
 pub fn get_track_by_item_id(item_id: ItemId, env: Env) -> Result<KeyedTrack> {
@@ -110,7 +133,7 @@ pub fn add_play_time(id: TrackID, start: MsSinceUnixEpoch, dur_ms: i64, env: Env
 	Ok(())
 }
 
-fn sanitize_filename(input: &String) -> String {
+pub fn sanitize_filename(input: &String) -> String {
 	let mut string = input.replace('/', "_");
 	string = string.replace('?', "_");
 	string = string.replace('<', "_");
@@ -150,12 +173,31 @@ pub fn generate_filename(paths: &Paths, artist: &str, title: &str, ext: &str) ->
 
 #[napi(js_name = "import_file")]
 #[allow(dead_code)]
-pub fn import_file(path: String, now: MsSinceUnixEpoch, env: Env) -> Result<()> {
+pub fn import_file(
+	path: String,
+	now: MsSinceUnixEpoch,
+	options: Option<import::ImportOptions>,
+	env: Env,
+) -> Result<import::ImportedTrack> {
 	let data: &mut Data = get_data(&env);
-	let id = data.library.generate_id();
-	let track = import::import(&data, Path::new(&path), now)?;
-	data.library.insert_track(id, track);
-	Ok(())
+	let settings = import::ImportSettings::from_options(options)?;
+	let mut index = import::DuplicateIndex::new(&data.library, &data.paths, settings.duplicates);
+	import::import(data, Path::new(&path), &settings, &mut index, now)
+}
+
+/// Replaces the file of a track with a copy of another file. See
+/// `import::relink`.
+#[napi(js_name = "replace_track_file")]
+#[allow(dead_code)]
+pub fn replace_track_file(
+	track_id: TrackID,
+	path: String,
+	now: MsSinceUnixEpoch,
+	keep_tags: bool,
+	env: Env,
+) -> Result<import::ImportedTrack> {
+	let data: &mut Data = get_data(&env);
+	import::relink(data, &track_id, Path::new(&path), keep_tags, now)
 }
 
 #[napi(js_name = "load_tags")]
@@ -1,6 +1,8 @@
+use super::import::{FileType, fallback_tag};
+use crate::library_types::Track;
 use anyhow::{Context, Result, bail};
 use lofty::picture::{MimeType, Picture};
-use lofty::tag::ItemKey;
+use lofty::tag::{ItemKey, ItemValue, TagItem, TagType};
 use lofty::{file::TaggedFileExt, tag::Accessor, tag::TagExt};
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
@@ -24,35 +26,71 @@ impl Tag {
 		if !path.exists() {
 			bail!("File does not exist: {}", path.to_string_lossy());
 		}
-		let ext = path.extension().unwrap_or_default().to_string_lossy();
+		FileType::from_path(path)?;
 
-		let tag = match ext.as_ref() {
-			"mp3" | "m4a" | "opus" => {
-				let parse_options = lofty::config::ParseOptions::new()
-					.read_properties(false)
-					.parsing_mode(lofty::config::ParsingMode::Strict);
-				let probe = lofty::probe::Probe::open(path)
-					.context("File does not exist")?
-					.options(parse_options);
+		let parse_options = lofty::config::ParseOptions::new()
+			.read_properties(false)
+			.parsing_mode(lofty::config::ParsingMode::Strict);
+		let probe = lofty::probe::Probe::open(path)
+			.context("File does not exist")?
+			.options(parse_options);
 
-				let mut tagged_file = probe.read().context("Unable to read file")?;
+		let mut tagged_file = probe.read().context("Unable to read file")?;
 
-				let tag = match tagged_file.remove(tagged_file.primary_tag_type()) {
-					Some(t) => t.clone(),
-					None => lofty::tag::Tag::new(tagged_file.primary_tag_type()),
-				};
+		let tag = match tagged_file.remove(tagged_file.primary_tag_type()) {
+			Some(t) => t,
+			None => fallback_tag(&tagged_file),
+		};
 
-				Tag { tag }
-			}
-			_ => bail!("Unsupported file extension: {}", ext),
+		Ok(Tag { tag })
+	}
+	/// A tag with the metadata of `track`, for when its file can't be read
+	pub fn from_track(track: &Track) -> Tag {
+		let mut tag = Tag {
+			tag: lofty::tag::Tag::new(TagType::Id3v2),
 		};
-		Ok(tag)
+		tag.set_title(&track.name);
+		tag.set_artist(&track.artist);
+		let text_fields = [
+			(&track.albumName, Tag::set_album as fn(&mut Tag, &str)),
+			(&track.albumArtist, Tag::set_album_artist),
+			(&track.composer, Tag::set_composer),
+			(&track.grouping, Tag::set_grouping),
+			(&track.genre, Tag::set_genre),
+			(&track.key, Tag::set_initial_key),
+			(&track.comments, Tag::set_comment),
+		];
+		for (value, set) in text_fields {
+			if let Some(value) = value {
+				set(&mut tag, value);
+			}
+		}
+		if let Some(year) = track.year {
+			tag.set_year(year.clamp(0, i32::MAX.into()) as i32);
+		}
+		tag.set_track_info(track.trackNum, track.trackCount);
+		tag.set_disc_info(track.discNum, track.discCount);
+		if let Some(bpm) = track.bpm {
+			tag.set_bpm(bpm.round().clamp(1.0, 65535.0) as u16);
+		}
+		tag
 	}
 	pub fn write_to_path(&mut self, path: &Path) -> Result<()> {
 		self.tag
 			.save_to_path(path, lofty::config::WriteOptions::default())
 			.context("Unable to tag file")
 	}
+	/// Writes the tag to a file of any type, converted to the file's primary
+	/// tag type. Items that tag type doesn't support are lost.
+	pub fn write_converted_to_path(&mut self, path: &Path) -> Result<()> {
+		let probe = lofty::probe::Probe::open(path)
+			.context("File does not exist")?
+			.guess_file_type()
+			.context("Unable to read file")?;
+		let file_type = probe.file_type().context("Unsupported file type")?;
+		self.tag.re_map(file_type.primary_tag_type());
+		self.write_to_path(path)
+	}
 	pub fn remove_title(&mut self) {
 		self.tag.remove_title()
 	}
@@ -139,6 +177,66 @@ impl Tag {
 		}
 		assert!(inserted, "Failed to set BPM");
 	}
+	pub fn remove_initial_key(&mut self) {
+		self.tag.remove_key(&ItemKey::InitialKey);
+	}
+	pub fn set_initial_key(&mut self, value: &str) {
+		let inserted = self.tag.insert_text(ItemKey::InitialKey, value.to_string());
+		assert!(inserted, "Failed to set key");
+	}
+	/// Gains in dB and peaks where 1.0 is full scale. Album tags are left
+	/// unchanged if `album` is `None`. Returns false if the tag format doesn't
+	/// support ReplayGain.
+	pub fn set_replay_gain(&mut self, gain: f64, peak: f64, album: Option<(f64, f64)>) -> bool {
+		let inserted = self
+			.tag
+			.insert_text(ItemKey::ReplayGainTrackGain, format!("{gain:.2} dB"))
+			&& self
+				.tag
+				.insert_text(ItemKey::ReplayGainTrackPeak, format!("{peak:.6}"));
+		if let Some((gain, peak)) = album {
+			self.tag
+				.insert_text(ItemKey::ReplayGainAlbumGain, format!("{gain:.2} dB"));
+			self.tag
+				.insert_text(ItemKey::ReplayGainAlbumPeak, format!("{peak:.6}"));
+		}
+		inserted
+	}
+	/// iTunes Sound Check, which iTunes uses instead of ReplayGain. Only
+	/// written to MP4 files.
+	pub fn set_sound_check(&mut self, gain: f64, peak: f64) {
+		if self.tag.tag_type() != TagType::Mp4Ilst {
+			return;
+		}
+		// Adjustments relative to 1000 and 2500 units, and the peak as a
+		// 16-bit sample value, each for the left and right channel
+		let adjustment = |base: f64| {
+			(10_f64.powf(-gain / 10.0) * base)
+				.round()
+				.clamp(1.0, 65534.0)
+		};
+		let peak = (peak.abs() * 32768.0).min(u32::MAX.into());
+		let values = [
+			adjustment(1000.0),
+			adjustment(1000.0),
+			adjustment(2500.0),
+			adjustment(2500.0),
+			0.0,
+			0.0,
+			peak,
+			peak,
+			0.0,
+			0.0,
+		];
+		let value: String = values
+			.iter()
+			.map(|v| format!(" {:08X}", *v as u32))
+			.collect();
+		self.tag.insert_unchecked(TagItem::new(
+			ItemKey::Unknown("----:com.apple.iTunes:iTunNORM".into()),
+			ItemValue::Text(value),
+		));
+	}
 	pub fn remove_comments(&mut self) {
 		self.tag.remove_comment()
 	}
@@ -186,3 +284,44 @@ impl Tag {
 		self.tag.remove_picture(index)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn from_track_test() {
+		let track: Track = serde_json::from_value(serde_json::json!({
+			"size": 0,
+			"duration": 200.0,
+			"bitrate": 0.0,
+			"sampleRate": 0.0,
+			"file": "track.mp3",
+			"dateModified": 0,
+			"dateAdded": 0,
+			"name": "Name",
+			"artist": "Artist",
+			"albumName": "Album",
+			"albumArtist": "Album Artist",
+			"year": 1999,
+			"trackNum": 3,
+			"trackCount": 12,
+			"bpm": 127.6,
+			"key": "Am",
+		}))
+		.unwrap();
+		let tag = Tag::from_track(&track).tag;
+		assert_eq!(tag.title().as_deref(), Some("Name"));
+		assert_eq!(tag.artist().as_deref(), Some("Artist"));
+		assert_eq!(tag.album().as_deref(), Some("Album"));
+		assert_eq!(tag.get_string(&ItemKey::AlbumArtist), Some("Album Artist"));
+		assert_eq!(tag.year(), Some(1999));
+		assert_eq!((tag.track(), tag.track_total()), (Some(3), Some(12)));
+		assert_eq!(tag.disk(), None);
+		let bpm = tag.get_string(&ItemKey::Bpm);
+		assert_eq!(bpm.or(tag.get_string(&ItemKey::IntegerBpm)), Some("128"));
+		assert_eq!(tag.get_string(&ItemKey::InitialKey), Some("Am"));
+		assert_eq!(tag.genre(), None);
+		assert_eq!(tag.comment(), None);
+	}
+}
@@ -0,0 +1,212 @@
+use super::cover::{CACHE_DB, get_modified_timestamp_ms, init_cache_db};
+use super::decode::AudioDecoder;
+use anyhow::{Context, Result, anyhow, bail};
+use redb::{Database, TableDefinition};
+use std::path::Path;
+
+// (modified_timestamp_ms, bucket_count, min/max/rms f32 values)
+type WaveformCacheEntry = (i64, u32, Vec<u8>);
+
+pub const WAVEFORM_CACHE_TABLE: TableDefinition<&str, WaveformCacheEntry> =
+	TableDefinition::new("waveform_cache");
+
+/// Frames per chunk while decoding. Chunks are merged into buckets
+/// afterwards, because the number of frames isn't always known up front.
+const CHUNK_FRAMES: usize = 256;
+
+#[derive(Clone, Copy)]
+struct Chunk {
+	min: f32,
+	max: f32,
+	sum_squares: f64,
+	samples: u64,
+}
+impl Chunk {
+	const EMPTY: Chunk = Chunk {
+		min: 0.0,
+		max: 0.0,
+		sum_squares: 0.0,
+		samples: 0,
+	};
+	fn merge(&mut self, other: &Chunk) {
+		self.min = self.min.min(other.min);
+		self.max = self.max.max(other.max);
+		self.sum_squares += other.sum_squares;
+		self.samples += other.samples;
+	}
+}
+
+#[napi(object)]
+pub struct Waveform {
+	pub min: Vec<f64>,
+	pub max: Vec<f64>,
+	pub rms: Vec<f64>,
+}
+impl Waveform {
+	fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(self.min.len() * 12);
+		for i in 0..self.min.len() {
+			for value in [self.min[i], self.max[i], self.rms[i]] {
+				bytes.extend_from_slice(&(value as f32).to_le_bytes());
+			}
+		}
+		bytes
+	}
+	fn from_bytes(bytes: &[u8]) -> Self {
+		let mut waveform = Waveform {
+			min: Vec::new(),
+			max: Vec::new(),
+			rms: Vec::new(),
+		};
+		for bucket in bytes.chunks_exact(12) {
+			let value = |i: usize| {
+				let value_bytes = bucket[i * 4..i * 4 + 4].try_into().unwrap();
+				f64::from(f32::from_le_bytes(value_bytes))
+			};
+			waveform.min.push(value(0));
+			waveform.max.push(value(1));
+			waveform.rms.push(value(2));
+		}
+		waveform
+	}
+}
+
+/// Decodes the file and reduces all channels to `bucket_count` buckets
+fn extract_waveform(path: &Path, bucket_count: usize) -> Result<Waveform> {
+	let mut decoder = AudioDecoder::open(path)?;
+	let mut chunks = Vec::new();
+	let mut chunk = Chunk::EMPTY;
+	let mut chunk_frames = 0;
+	while let Some(samples) = decoder.next_samples()? {
+		for frame in samples.data.chunks_exact(samples.channels) {
+			for &sample in frame {
+				chunk.min = chunk.min.min(sample);
+				chunk.max = chunk.max.max(sample);
+				chunk.sum_squares += f64::from(sample) * f64::from(sample);
+				chunk.samples += 1;
+			}
+			chunk_frames += 1;
+			if chunk_frames == CHUNK_FRAMES {
+				chunks.push(chunk);
+				chunk = Chunk::EMPTY;
+				chunk_frames = 0;
+			}
+		}
+	}
+	if chunk_frames > 0 {
+		chunks.push(chunk);
+	}
+	if chunks.is_empty() {
+		bail!("No audio found");
+	}
+
+	let mut waveform = Waveform {
+		min: Vec::with_capacity(bucket_count),
+		max: Vec::with_capacity(bucket_count),
+		rms: Vec::with_capacity(bucket_count),
+	};
+	for i in 0..bucket_count {
+		let start = i * chunks.len() / bucket_count;
+		// Short tracks have fewer chunks than buckets
+		let end = ((i + 1) * chunks.len() / bucket_count).max(start + 1);
+		let mut bucket = Chunk::EMPTY;
+		for chunk in &chunks[start..end] {
+			bucket.merge(chunk);
+		}
+		waveform.min.push(bucket.min.into());
+		waveform.max.push(bucket.max.into());
+		waveform
+			.rms
+			.push((bucket.sum_squares / bucket.samples.max(1) as f64).sqrt());
+	}
+	Ok(waveform)
+}
+
+fn get_cached_waveform(
+	cache_db: &Database,
+	path: &str,
+	date_modified_ms: i64,
+	bucket_count: u32,
+) -> Result<Option<Waveform>> {
+	let read_txn = cache_db
+		.begin_read()
+		.context("Could not begin read transaction")?;
+	let table = read_txn
+		.open_table(WAVEFORM_CACHE_TABLE)
+		.context("Could not open table")?;
+	let cache_entry = match table.get(path).context("Could not get record")? {
+		Some(cache_entry) => cache_entry.value(),
+		None => return Ok(None),
+	};
+	if cache_entry.0 == date_modified_ms && cache_entry.1 == bucket_count {
+		return Ok(Some(Waveform::from_bytes(&cache_entry.2)));
+	}
+	Ok(None)
+}
+
+fn write_waveform_to_cache(
+	cache_db: &Database,
+	path: &str,
+	value: WaveformCacheEntry,
+) -> Result<()> {
+	let write_txn = cache_db
+		.begin_write()
+		.context("Could not begin write transaction")?;
+	{
+		let mut table = write_txn
+			.open_table(WAVEFORM_CACHE_TABLE)
+			.context("Could not open table")?;
+		table
+			.insert(path, value)
+			.context("Could not insert record")?;
+	}
+	write_txn.commit().context("Could not commit transaction")?;
+	Ok(())
+}
+
+/// Min, max and RMS of the audio in `buckets` evenly sized parts. Cached
+/// until the file is modified.
+#[napi(js_name = "read_waveform_async")]
+#[allow(dead_code)]
+pub async fn read_waveform_async(
+	path: String,
+	buckets: u32,
+	cache_db_path: String,
+) -> napi::Result<Waveform> {
+	if path.is_empty() {
+		return Err(anyhow!("path must not be empty").into());
+	} else if cache_db_path.is_empty() {
+		return Err(anyhow!("cache_db_path must not be empty").into());
+	} else if buckets == 0 {
+		return Err(anyhow!("buckets must be more than 0").into());
+	}
+
+	init_cache_db(cache_db_path)?;
+
+	let date_modified_ms: Option<i64> =
+		get_modified_timestamp_ms(&path)?.map(|n| n.try_into().unwrap());
+
+	if let Some(date_modified_ms) = date_modified_ms {
+		let cache_db_lock = CACHE_DB.read().unwrap();
+		let cache_db = cache_db_lock.as_ref().unwrap();
+		if let Some(waveform) = get_cached_waveform(cache_db, &path, date_modified_ms, buckets)? {
+			return Ok(waveform);
+		}
+	}
+
+	let file_path = path.clone();
+	let waveform = tokio::task::spawn_blocking(move || {
+		extract_waveform(Path::new(&file_path), buckets as usize)
+	})
+	.await
+	.context("Waveform thread failed")??;
+
+	if let Some(date_modified_ms) = date_modified_ms {
+		let cache_db_lock = CACHE_DB.read().unwrap();
+		let cache_db = cache_db_lock.as_ref().context("Cache.redb was closed")?;
+		let value = (date_modified_ms, buckets, waveform.to_bytes());
+		write_waveform_to_cache(cache_db, &path, value)?;
+	}
+
+	Ok(waveform)
+}
@@ -88,6 +88,21 @@ ipc_main.handle('show_tracks_menu', (e, options) => {
 				label: 'Get Info',
 				click: () => resolve('Get Info'),
 			},
+			{
+				label: 'Analyze Loudness...',
+				click: () => resolve('Analyze Loudness'),
+				visible: options.analyze === true,
+			},
+			{
+				label: 'Analyze BPM and Key...',
+				click: () => resolve('Analyze BPM and Key'),
+				visible: options.analyze === true,
+			},
+			{
+				label: 'Merge Tracks...',
+				click: () => resolve('Merge Tracks'),
+				visible: options.analyze === true,
+			},
 			{ type: 'separator' },
 			{
 				label: 'Show in Playlist',
@@ -106,6 +121,10 @@ ipc_main.handle('show_tracks_menu', (e, options) => {
 				})(),
 				click: () => resolve('reveal_track_file'),
 			},
+			{
+				label: 'Replace File...',
+				click: () => resolve('Replace File'),
+			},
 			{ type: 'separator', visible: options.is_editable_playlist === true },
 			{
 				label: 'Remove from Playlist',
@@ -135,6 +154,10 @@ ipc_main.handle('showTracklistMenu', (e, args) => {
 			label: 'New Playlist',
 			click: () => e.sender.send('newPlaylist', args.id, false),
 		},
+		{
+			label: 'New Smart Playlist',
+			click: () => e.sender.send('newSmartPlaylist', args.id),
+		},
 		{
 			label: 'New Folder',
 			click: () => e.sender.send('newPlaylist', args.id, true),
@@ -49,6 +49,12 @@ export function init_menu_bar(app: App, main_window: BrowserWindow) {
 						web_contents.send('newPlaylist', 'root', false)
 					},
 				},
+				{
+					label: 'New Smart Playlist',
+					click() {
+						web_contents.send('newSmartPlaylist', 'root')
+					},
+				},
 				{
 					label: 'New Playlist Folder',
 					click() {
@@ -70,6 +76,12 @@ export function init_menu_bar(app: App, main_window: BrowserWindow) {
 						web_contents.send('itunesImport')
 					},
 				},
+				{
+					label: 'Import Rhythmbox Library...',
+					click() {
+						web_contents.send('rhythmboxImport')
+					},
+				},
 				{ type: 'separator' },
 				{
 					label: 'Settings...',
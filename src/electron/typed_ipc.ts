@@ -99,7 +99,11 @@ export type SelectedTracksAction =
 	| { action: 'Add to Playlist'; playlist_id: string }
 	| { action: 'Show in Playlist'; playlist_id: string }
 	| 'Get Info'
+	| 'Analyze Loudness'
+	| 'Analyze BPM and Key'
+	| 'Merge Tracks'
 	| 'reveal_track_file'
+	| 'Replace File'
 	| 'Remove from Playlist'
 	| 'Delete from Library'
 
@@ -108,8 +112,10 @@ type Events = {
 	gonnaQuit: () => void
 
 	newPlaylist: (id: string, isFolder: boolean) => void
+	newSmartPlaylist: (id: string) => void
 	show_settings: () => void
 	itunesImport: () => void
+	rhythmboxImport: () => void
 	import: () => void
 	filter: (text?: string) => void
 
@@ -147,6 +153,8 @@ export type ShowTrackMenuOptions = {
 	show_in_playlists: TrackMenuNode[]
 	is_editable_playlist: boolean
 	queue: boolean
+	/** Only the track list can analyze tracks, because it uses item IDs */
+	analyze?: boolean
 }
 
 export type TrackMenuNode = {
@@ -6,13 +6,14 @@ import type {
 	TrackListID,
 	ItemId,
 	TrackMd,
+	SmartPlaylistRules,
 	TracksPageOptions,
 	ViewOptions,
 } from '../../ferrum-addon'
 import { init_queue_persistence, queue } from './queue'
 import { current_playlist_id } from '$components/TrackList.svelte'
 import { navigate } from './router'
-import { call_sync, get_error_message, strict_call } from './error'
+import { call_sync, error_popup, get_error_message, strict_call } from './error'
 
 export const is_dev = window.is_dev
 export const local_data_path = window.local_data_path
@@ -20,15 +21,55 @@ export const library_path = window.library_path
 export const is_mac = window.is_mac
 export const is_windws = window.is_windows
 const inner_addon = window.addon
+export const AcousticDuplicateSearch = inner_addon.AcousticDuplicateSearch
+export const BpmKeyAnalysis = inner_addon.BpmKeyAnalysis
+export const FolderImport = inner_addon.FolderImport
 export const ItunesImport = inner_addon.ItunesImport
+export const LoudnessAnalysis = inner_addon.LoudnessAnalysis
+export const RhythmboxImport = inner_addon.RhythmboxImport
+export const TrackMerge = inner_addon.TrackMerge
 
 strict_call((addon) => addon.load_data(is_dev, local_data_path, library_path))
 
 export const paths = strict_call((addon) => addon.get_paths())
 init_queue_persistence(paths.queueFile)
+
+export const scrobbler = inner_addon.Scrobbler.load()
+const scrobbler_load_error = scrobbler.loadError()
+if (scrobbler_load_error !== null) {
+	error_popup(new Error(scrobbler_load_error))
+}
+export async function flush_scrobbles() {
+	const status = await scrobbler.flush().catch((error) => {
+		console.error('Error flushing scrobble queue:', error)
+		return null
+	})
+	if (!status) {
+		return
+	}
+	if (status.error) {
+		console.warn('Submitting listens failed:', status.error)
+	}
+	for (const listen of status.rejected) {
+		console.warn('Listen was rejected:', listen)
+	}
+}
+flush_scrobbles()
+// Retries failed submissions once their backoff has passed
+setInterval(flush_scrobbles, 5 * 60_000)
+
 export function join_paths(...args: string[]) {
 	return args.join(paths.pathSeparator)
 }
+/** Tracks that are referenced in place have an absolute `file` path */
+export function get_track_file_path(file: string) {
+	const is_absolute =
+		file.startsWith('/') || file.startsWith('\\\\') || /^[a-zA-Z]:[\\/]/.test(file)
+	if (is_absolute) {
+		return file
+	}
+	return join_paths(paths.tracksDir, file)
+}
 
 export const track_lists_details_map = (() => {
 	const initial = strict_call((addon) => addon.get_track_lists_details())
@@ -95,11 +136,35 @@ export type PlaylistInfo = {
 	id: string
 	editMode: boolean
 }
+export type SmartPlaylistInfo = {
+	name: string
+	description: string
+	rules: SmartPlaylistRules
+	/** ID to edit, or ID to create playlist inside */
+	id: string
+	editMode: boolean
+}
 export function new_playlist(info: PlaylistInfo) {
 	strict_call((addon) => addon.new_playlist(info.name, info.description, info.isFolder, info.id))
 	track_lists_details_map.refresh()
 	save()
 }
+export function new_smart_playlist(
+	name: string,
+	description: string,
+	rules: SmartPlaylistRules,
+	parent_id: TrackListID,
+) {
+	const id = strict_call((addon) => addon.new_smart_playlist(name, description, rules, parent_id))
+	track_lists_details_map.refresh()
+	save()
+	return id
+}
+export function update_smart_playlist_rules(id: TrackListID, rules: SmartPlaylistRules) {
+	strict_call((addon) => addon.update_smart_playlist_rules(id, rules))
+	tracklist_updated.emit()
+	save()
+}
 export function update_playlist(id: string, name: string, description: string) {
 	strict_call((addon) => addon.update_playlist(id, name, description))
 	track_lists_details_map.refresh()
@@ -119,11 +184,13 @@ export function move_playlist(
 
 export async function import_tracks(paths: string[]) {
 	let skip_all_errors = false
-	const now = Date.now()
+	const warnings: string[] = []
+	const track_import = inner_addon.TrackImport.new(Date.now())
 	for (const [i, path] of paths.entries()) {
 		const is_last = i === paths.length - 1
 		try {
-			inner_addon.import_file(path, now)
+			const result = track_import.importFile(path)
+			warnings.push(...result.warnings.map((warning) => `[${path}] ${warning}`))
 		} catch (err) {
 			if (skip_all_errors) {
 				continue
@@ -142,6 +209,93 @@ export async function import_tracks(paths: string[]) {
 	}
 	tracklist_updated.emit()
 	save()
+	if (warnings.length > 0) {
+		await ipc_renderer.invoke('showMessageBox', false, {
+			type: 'warning',
+			message: 'Some tracks were imported with warnings',
+			detail: warnings.join('\n'),
+		})
+	}
+}
+
+/**
+ * Replaces the file of a track with a copy of another file, keeping its plays, skips and playlist
+ * entries. With `keep_tags`, the old tags and artwork are written to the new file, or the track's
+ * metadata if the old file can't be read.
+ */
+export function replace_track_file(id: TrackID, path: string, keep_tags: boolean) {
+	const now = Date.now()
+	return call_sync((addon) => addon.replace_track_file(id, path, now, keep_tags)).on_success(
+		(result) => {
+			tracks_updated.emit()
+			save()
+			if (result.warnings.length > 0) {
+				ipc_renderer.invoke('showMessageBox', false, {
+					type: 'warning',
+					message: 'The file was replaced with warnings',
+					detail: result.warnings.join('\n'),
+				})
+			}
+		},
+	)
+}
+
+export function import_m3u(path: string, parent_id: string, import_unknown: boolean) {
+	const options = import_unknown ? {} : undefined
+	const now = Date.now()
+	return call_sync((addon) => addon.import_m3u(path, parent_id, now, options)).on_success(() => {
+		tracklist_updated.emit()
+		track_lists_details_map.refresh()
+		save()
+	})
+}
+
+/** Imports plays from a `.scrobbler.log` file or a Last.fm CSV export */
+export function import_plays(path: string) {
+	return call_sync((addon) => addon.import_plays(path)).on_success(() => {
+		tracklist_updated.emit()
+		save()
+	})
+}
+
+/** Imports Spotify `Streaming_History_Audio_*.json` files */
+export function import_spotify_history(paths: string[], min_ms_played?: number) {
+	return call_sync((addon) => addon.import_spotify_history(paths, min_ms_played)).on_success(() => {
+		tracklist_updated.emit()
+		save()
+	})
+}
+
+export function export_itunes_library(path: string) {
+	return call_sync((addon) => addon.export_itunes_library(path)).on_success((errors) => {
+		if (errors.length > 0) {
+			ipc_renderer.invoke('showMessageBox', false, {
+				type: 'warning',
+				message: 'Some tracks were not exported',
+				detail: errors.join('\n'),
+			})
+		}
+	})
+}
+
+/** Exports a playlist, or a folder as a directory of playlists, into `dir` */
+export function export_playlist(
+	id: string,
+	dir: string,
+	format: 'm3u8' | 'xspf' | 'pls',
+	relative_paths: boolean,
+) {
+	return call_sync((addon) => addon.export_playlist(id, dir, format, relative_paths))
+}
+
+/** Exports plays between `from` and `to` as listens */
+export function export_plays(
+	path: string,
+	format: 'listenbrainz' | 'scrobbler_log',
+	from?: MsSinceUnixEpoch,
+	to?: MsSinceUnixEpoch,
+) {
+	return call_sync((addon) => addon.export_plays(path, format, from, to))
 }
 
 export function get_default_sort_desc(field: string) {
@@ -184,6 +338,7 @@ export function add_play(id: TrackID) {
 	return call_sync((addon) => addon.add_play(id)).on_success(() => {
 		tracklist_updated.emit()
 		save()
+		call_sync(() => scrobbler.add(id)).on_success(flush_scrobbles)
 	})
 }
 export function add_skip(id: TrackID) {
@@ -200,6 +355,9 @@ export function add_play_time(id: TrackID, start_time: MsSinceUnixEpoch, duratio
 export function read_cover_async(file_path: string, index: number) {
 	return inner_addon.read_cover_async(file_path, index)
 }
+export function read_waveform_async(file_path: string, buckets: number) {
+	return inner_addon.read_waveform_async(file_path, buckets, paths.cacheDb)
+}
 export function update_track_info(id: TrackID, md: TrackMd) {
 	strict_call((addon) => addon.update_track_info(id, md))
 	tracks_updated.emit()
@@ -97,6 +97,9 @@ export function flatten_child_lists(
 				enabled: true,
 				id: child_list.id,
 			})
+		} else if (child_list.kind === 'smart') {
+			// Smart playlists can't be added to, and never contain tracks directly
+			continue
 		}
 	}
 	return flat
@@ -1,9 +1,10 @@
 import {
 	add_tracks_to_playlist,
 	get_track,
+	get_track_file_path,
 	get_track_list,
 	get_track_playlist_ids,
-	paths,
+	replace_track_file,
 	track_lists_details_map,
 } from '$lib/data'
 import { flatten_child_lists } from '$lib/helpers'
@@ -60,7 +61,9 @@ export function handle_selected_tracks_action({
 		open_track_info(all_ids, first_index)
 	} else if (action === 'reveal_track_file') {
 		const track = get_track(first_track_id)
-		ipc_renderer.invoke('revealTrackFile', paths.tracksDir, track.file)
+		ipc_renderer.invoke('revealTrackFile', get_track_file_path(track.file))
+	} else if (action === 'Replace File') {
+		replace_file(first_track_id)
 	} else if (typeof action === 'object' && action.action === 'Add to Playlist') {
 		add_tracks_to_playlist(action.playlist_id, track_ids)
 	} else if (typeof action === 'object' && action.action === 'Show in Playlist') {
@@ -75,3 +78,27 @@ export function handle_selected_tracks_action({
 		})
 	}
 }
+
+async function replace_file(track_id: TrackID) {
+	const track = get_track(track_id)
+	const open = await ipc_renderer.invoke('showOpenDialog', false, {
+		properties: ['openFile'],
+		filters: [{ name: 'Audio', extensions: ['mp3', 'm4a', 'opus', 'flac', 'ogg', 'wav', 'aif', 'aiff'] }],
+	})
+	if (open.canceled || open.filePaths.length === 0) {
+		return
+	}
+	const result = await ipc_renderer.invoke('showMessageBox', false, {
+		type: 'info',
+		message: `Replace the file of "${track.name}"?`,
+		detail:
+			'Plays, skips and playlist entries are kept, and the old file is moved to the trash. The tags and artwork can be kept, or read from the new file.',
+		buttons: ['Keep Tags', 'Use New Tags', 'Cancel'],
+		defaultId: 0,
+		cancelId: 2,
+	})
+	if (result.response === 2) {
+		return
+	}
+	replace_track_file(track_id, open.filePaths[0], result.response === 0)
+}
@@ -8,8 +8,7 @@ import {
 	add_skip,
 	get_track,
 	get_track_ids,
-	join_paths,
-	paths,
+	get_track_file_path,
 	read_cover_async,
 } from '$lib/data'
 import type { Track, TrackID } from '../../ferrum-addon'
@@ -89,7 +88,7 @@ export const cover_src = (() => {
 	return {
 		async newFromTrackId(id: TrackID) {
 			try {
-				const file_path = join_paths(paths.tracksDir, get_track(id).file)
+				const file_path = get_track_file_path(get_track(id).file)
 				const result = await read_cover_async(file_path, 0)
 				if (!result) {
 					set(null)
@@ -117,7 +116,13 @@ audio.onplay = update_time_details
 audio.onloadeddata = update_time_details
 audio.onloadedmetadata = update_time_details
 audio.onpause = update_time_details
-audio.ontimeupdate = update_time_details
+audio.ontimeupdate = () => {
+	update_time_details()
+	const stop_time = get(playing_track)?.stopTime
+	if (stop_time !== undefined && !audio.paused && audio.currentTime >= stop_time) {
+		next(false)
+	}
+}
 
 audio.addEventListener('error', async (e) => {
 	stop()
@@ -150,9 +155,10 @@ function start_playback() {
 
 function set_playing_file(id: TrackID, paused = false) {
 	const track = get_track(id)
-	const file_url = 'track:' + join_paths(paths.tracksDir, track.file)
+	const file_url = 'track:' + get_track_file_path(track.file)
 	waiting_to_play = !paused
 	audio.src = file_url
+	audio.currentTime = track.startTime ?? 0
 	playing_track.set(track)
 	if (media_session) {
 		media_session.metadata = new MediaMetadata({